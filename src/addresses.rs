@@ -1,8 +1,12 @@
 
 use crate::error::{NozyError, NozyResult};
 use crate::hd_wallet::HDWallet;
+use crate::notes::normalize_tag;
 use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
+use zcash_keys::address::{Address, UnifiedAddress};
+use zcash_keys::encoding::encode_payment_address;
+use zcash_primitives::consensus::{Network, NetworkConstants};
 
 
 
@@ -72,6 +76,10 @@ pub struct AddressManager {
     
     
     network: NetworkType,
+    
+    // Notes received on a labelled address are tagged with the label; keyed by receiver, see `label_keys`
+    #[serde(default)]
+    labels: HashMap<String, String>,
 }
 
 impl AddressManager {
@@ -82,6 +90,7 @@ impl AddressManager {
             counters: HashMap::new(),
             hd_wallet,
             network,
+            labels: HashMap::new(),
         }
     }
     
@@ -259,11 +268,88 @@ impl AddressManager {
     }
     
     
+    // (type, stored counter, counter implied by the highest derived index) wherever the counter is behind
+    pub fn counter_shortfalls(&self) -> Vec<(ZcashAddressType, u32, u32)> {
+        let mut needed: HashMap<ZcashAddressType, u32> = HashMap::new();
+        for address in self.addresses.values() {
+            let index = address.derivation_path.rsplit('/').next()
+                .and_then(|last| last.trim_end_matches('\'').parse::<u32>().ok());
+            if let Some(index) = index {
+                let entry = needed.entry(address.address_type.clone()).or_insert(0);
+                *entry = (*entry).max(index + 1);
+            }
+        }
+        
+        let mut shortfalls: Vec<(ZcashAddressType, u32, u32)> = needed.into_iter()
+            .map(|(address_type, needed)| (address_type.clone(), self.get_address_count(&address_type), needed))
+            .filter(|(_, current, needed)| current < needed)
+            .collect();
+        shortfalls.sort_by_key(|(_, _, needed)| *needed);
+        shortfalls
+    }
+    
+    
+    pub fn set_address_count(&mut self, address_type: ZcashAddressType, count: u32) {
+        self.counters.insert(address_type, count);
+    }
+    
+    
+    // Any receiving address can be labelled, including ones derived outside the manager
+    pub fn set_label(&mut self, network: &Network, address: &str, label: Option<&str>) -> NozyResult<()> {
+        let keys = label_keys(network, address)?;
+        match label {
+            Some(label) => {
+                let label = normalize_tag(label)?;
+                for key in keys {
+                    self.labels.insert(key, label.clone());
+                }
+            }
+            None => {
+                for key in keys {
+                    self.labels.remove(&key);
+                }
+            }
+        }
+        Ok(())
+    }
+    
+    
+    pub fn get_label(&self, network: &Network, address: &str) -> Option<&str> {
+        label_keys(network, address).ok()?
+            .iter()
+            .find_map(|key| self.labels.get(key))
+            .map(String::as_str)
+    }
+    
+    
     pub fn get_network(&self) -> NetworkType {
         self.network
     }
 }
 
+
+// Notes record the single receiver they arrived on, so a label is stored under each shielded
+// receiver of the address in that same form; a full unified address then matches either pool
+fn label_keys(network: &Network, address: &str) -> NozyResult<Vec<String>> {
+    let sapling_key = |sapling| encode_payment_address(network.hrp_sapling_payment_address(), sapling);
+    let keys: Vec<String> = match Address::decode(network, address) {
+        Some(Address::Unified(unified)) => {
+            let orchard = unified.orchard()
+                .and_then(|orchard| UnifiedAddress::from_receivers(Some(*orchard), None, None))
+                .map(|orchard| orchard.encode(network));
+            orchard.into_iter().chain(unified.sapling().map(sapling_key)).collect()
+        }
+        Some(Address::Sapling(sapling)) => vec![sapling_key(&sapling)],
+        Some(_) => Vec::new(),
+        None => return Err(NozyError::InvalidOperation(format!("{} is not a valid address", address))),
+    };
+    
+    if keys.is_empty() {
+        return Err(NozyError::InvalidOperation(format!("{} has no shielded receiver to receive notes on", address)));
+    }
+    Ok(keys)
+}
+
 impl std::fmt::Display for NetworkType {
     fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
         match self {
@@ -5,9 +5,12 @@ use nozy::{
     ShieldedNote, 
     NoteType, 
     notes::NoteSelectionStrategy,
-    TransactionSigner
+    TransactionSigner,
+    InputSelection,
+    ViewingKeys
 };
 use nozy::config::NozyConfig;
+use zcash_protocol::consensus::Network;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("🔐 Testing Nozy Real Note Management System...\n");
@@ -118,6 +121,7 @@ fn test_note_selection_strategies() -> Result<(), Box<dyn std::error::Error>> {
         note_manager.add_note(note)?;
     }
     
+    note_manager.set_chain_height(2_000_000); // Test notes are past the confirmation depth
     let amount_needed = 150_000_000; // 1.5 ZEC
     
     // Test PrivacyFirst strategy
@@ -240,16 +244,18 @@ fn test_transaction_building_with_notes() -> Result<(), Box<dyn std::error::Erro
     note_manager.add_note(note1)?;
     note_manager.add_note(note2)?;
     
-    let mut signer = TransactionSigner::new(hd_wallet, note_manager);
+    note_manager.set_chain_height(2_000_000); // Test notes are past the confirmation depth
+    let keys = testnet_keys(&hd_wallet)?;
+    let recipient = keys.orchard_address_at(1)?;
+    let mut signer = TransactionSigner::new(hd_wallet, note_manager, Network::TestNetwork, keys.orchard_change_address()?);
     
     // Build transaction
     let transaction = signer.build_transaction_with_notes(
-        "o9876543210fedcba".to_string(), // Recipient
+        recipient,
         75_000_000, // 0.75 ZEC
         10_000, // 0.0001 ZEC fee
         Some(b"Test transaction with real notes".to_vec()),
-        1_000_000, // Expiry height
-        Some(NoteSelectionStrategy::PrivacyFirst),
+        InputSelection::Automatic(Some(NoteSelectionStrategy::PrivacyFirst)),
     ).unwrap();
     
     println!("   📤 Transaction Built Successfully:");
@@ -299,23 +305,25 @@ fn test_note_consolidation() -> Result<(), Box<dyn std::error::Error>> {
     println!("      - Total balance: {} ZEC", 
         note_manager.get_total_balance() as f64 / 100_000_000.0);
     
-    // Consolidate notes
-    let consolidated_notes = note_manager.consolidate_notes().unwrap();
-    
-    println!("   🔄 After Consolidation:");
-    println!("      - Consolidated {} notes", consolidated_notes.len());
-    println!("      - Remaining notes: {}", note_manager.get_commitment_tree_size());
-    println!("      - Total balance: {} ZEC", 
-        note_manager.get_total_balance() as f64 / 100_000_000.0);
-    
-    if let Some(consolidated) = consolidated_notes.first() {
-        println!("   💎 Consolidated Note:");
-        println!("      - Value: {} ZEC", consolidated.value as f64 / 100_000_000.0);
-        println!("      - Type: {:?}", consolidated.note_type);
-        println!("      - Commitment: {}...", 
-            hex::encode(&consolidated.commitment[..8]));
+    // Plan a consolidation; the wallet turns it into a real self-send
+    note_manager.set_chain_height(2_000_000); // Test notes are past the confirmation depth
+    match note_manager.plan_consolidation(true) {
+        Some(plan) => {
+            println!("   🔄 Consolidation Plan:");
+            println!("      - Merging {} notes", plan.note_ids.len());
+            println!("      - Fee: {} ZEC", plan.fee as f64 / 100_000_000.0);
+            println!("      - Consolidated value: {} ZEC", plan.output_value as f64 / 100_000_000.0);
+        }
+        None => println!("   🔄 No notes small enough to consolidate"),
     }
     
     println!();
     Ok(())
-} 
\ No newline at end of file
+} 
+
+
+// Real testnet keys for the wallet, since payments and change are checked by decoding
+fn testnet_keys(hd_wallet: &HDWallet) -> Result<ViewingKeys, Box<dyn std::error::Error>> {
+    let spending_key = hd_wallet.unified_spending_key(&Network::TestNetwork, "default_password")?;
+    Ok(ViewingKeys::from_spending_key(&spending_key, Network::TestNetwork))
+}
@@ -41,7 +41,8 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
     
     println!("\n🧪 Test 3: Zebra Connection and Note Discovery");
     
-    let zebra_config = ZebraConfig::default();
+    // This exercises the indexer lookup, which scan-only mode refuses
+    let zebra_config = ZebraConfig { scan_only: false, ..ZebraConfig::default() };
     let mut zebra_client = ZebraClient::new(zebra_config);
     
     
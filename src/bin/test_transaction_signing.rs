@@ -1,10 +1,13 @@
 use nozy::{
     HDWallet, 
     TransactionSigner, 
+    InputSelection,
     NoteManager,
     NoteType,
-    NozyConfig
+    NozyConfig,
+    ViewingKeys
 };
+use zcash_protocol::consensus::Network;
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     println!("🔐 Testing Nozy Transaction Signing System...\n");
@@ -45,9 +48,11 @@ fn test_basic_transaction_signing() -> Result<(), Box<dyn std::error::Error>> {
     
     note_manager.add_note(test_note)?;
     
-    let mut signer = TransactionSigner::new(hd_wallet, note_manager);
+    note_manager.set_chain_height(1_000_000); // Test notes are past the confirmation depth
+    let keys = testnet_keys(&hd_wallet)?;
+    let recipient = keys.orchard_address_at(1)?;
+    let mut signer = TransactionSigner::new(hd_wallet, note_manager, Network::TestNetwork, keys.orchard_change_address()?);
     
-    let recipient = "u1test_recipient_address_for_testing_purposes_only_12345678".to_string();
     let amount = 50_000_000; // 0.5 ZEC
     
     println!("   Building transaction...");
@@ -56,8 +61,7 @@ fn test_basic_transaction_signing() -> Result<(), Box<dyn std::error::Error>> {
         amount,
         10_000, // 0.0001 ZEC fee
         Some("Test transaction memo".as_bytes().to_vec()),
-        1_000_000, // expiry height
-        None // Use default note selection strategy
+        InputSelection::Automatic(None), // Use default note selection strategy
     )?;
     
     println!("   ✅ Transaction built successfully!");
@@ -80,7 +84,8 @@ fn test_fee_estimation() -> Result<(), Box<dyn std::error::Error>> {
     
     let config = NozyConfig::default();
     let note_manager = NoteManager::new(&config)?;
-    let signer = TransactionSigner::new(hd_wallet, note_manager);
+    let change_address = testnet_keys(&hd_wallet)?.orchard_change_address()?;
+    let signer = TransactionSigner::new(hd_wallet, note_manager, Network::TestNetwork, change_address);
     
     let test_amounts = vec![10_000_000, 50_000_000, 100_000_000]; // 0.1, 0.5, 1 ZEC
     
@@ -128,9 +133,11 @@ fn test_note_selection() -> Result<(), Box<dyn std::error::Error>> {
     println!("   Created {} test notes", note_values.len());
     println!("   Total balance: {} zatoshi", note_manager.get_total_balance());
     
-    let mut signer = TransactionSigner::new(hd_wallet, note_manager);
+    note_manager.set_chain_height(1_000_000); // Test notes are past the confirmation depth
+    let keys = testnet_keys(&hd_wallet)?;
+    let recipient = keys.orchard_address_at(1)?;
+    let mut signer = TransactionSigner::new(hd_wallet, note_manager, Network::TestNetwork, keys.orchard_change_address()?);
     
-    let recipient = "u1test_recipient_address_for_note_selection_testing_12345678".to_string();
     let amount = 60_000_000; // 0.6 ZEC (should select the 75M note or combine smaller ones)
     
     println!("   Building transaction requiring note selection...");
@@ -139,8 +146,7 @@ fn test_note_selection() -> Result<(), Box<dyn std::error::Error>> {
         amount,
         10_000, // 0.0001 ZEC fee
         Some("Note selection test".as_bytes().to_vec()),
-        1_000_000, // expiry height
-        None // Use default note selection strategy
+        InputSelection::Automatic(None), // Use default note selection strategy
     )?;
     
     println!("   ✅ Transaction built with note selection!");
@@ -148,4 +154,11 @@ fn test_note_selection() -> Result<(), Box<dyn std::error::Error>> {
     println!("      Total input value covers {} zatoshi transaction", amount);
     
     Ok(())
-} 
\ No newline at end of file
+} 
+
+
+// Real testnet keys for the wallet, since payments and change are checked by decoding
+fn testnet_keys(hd_wallet: &HDWallet) -> Result<ViewingKeys, Box<dyn std::error::Error>> {
+    let spending_key = hd_wallet.unified_spending_key(&Network::TestNetwork, "default_password")?;
+    Ok(ViewingKeys::from_spending_key(&spending_key, Network::TestNetwork))
+}
@@ -5,9 +5,18 @@ use colored::*;
 use crate::error::NozyResult;
 use crate::wallet::NozyWallet;
 use crate::config::NozyConfig;
-use crate::notes::NoteType;
+use crate::history::{HistoryDirection, HistoryEntry};
+use crate::migration::{MigrationStep, TransferStatus};
+use crate::mixing::MixStep;
+use crate::notes::{NoteSelectionStrategy, NoteType, SplitPlan, SplitPolicy};
+use crate::payouts::{self, PayoutPlan, PayoutResult, PayoutRow};
+use crate::payment_request;
+use crate::sync::{SyncEvent, SyncOptions, SyncProgress};
+use crate::transaction_signer::{InputSelection, SignedTransaction};
 use std::fs;
-use std::path::Path;
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::Ordering;
 
 #[derive(Parser)]
 #[command(name = "nozy")]
@@ -17,10 +26,10 @@ use std::path::Path;
 pub struct Cli {
     #[command(subcommand)]
     pub command: Commands,
-
+    
     #[arg(short, long, value_name = "FILE")]
     pub config: Option<String>,
-
+    
     #[arg(short, long)]
     pub verbose: bool,
 }
@@ -29,31 +38,37 @@ pub struct Cli {
 pub enum Commands {
     #[command(subcommand)]
     Wallet(WalletCommands),
-
+    
     #[command(subcommand)]
     Address(AddressCommands),
-
+    
     #[command(subcommand)]
     Balance(BalanceCommands),
-
+    
     #[command(subcommand)]
     Network(NetworkCommands),
-
+    
     #[command(subcommand)]
     Tx(TxCommands),
-
+    
     #[command(subcommand)]
     Privacy(PrivacyCommands),
-
+    
     #[command(subcommand)]
     Blockchain(BlockchainCommands),
-
+    
     #[command(subcommand)]
     Analytics(AnalyticsCommands),
-
+    
     #[command(subcommand)]
     Dev(DevCommands),
-
+    
+    #[command(subcommand)]
+    Memo(MemoCommands),
+    
+    #[command(subcommand)]
+    Notes(NotesCommands),
+    
     Status,
 }
 
@@ -81,6 +96,23 @@ pub enum WalletCommands {
         key_type: String,
     },
     ListKeys,
+    Confirmations {
+        #[arg(long)]
+        min: Option<u32>,
+        
+        #[arg(long)]
+        change: Option<u32>,
+    },
+    // Checks notes, witnesses and address counters for inconsistencies
+    Fsck {
+        #[arg(long)]
+        repair: bool,
+    },
+    // Shows or sets where the Sapling proving parameters are read from, and checks their hashes
+    Params {
+        #[arg(long)]
+        dir: Option<String>,
+    },
 }
 
 #[derive(Subcommand)]
@@ -99,16 +131,28 @@ pub enum AddressCommands {
     Info {
         address: String,
     },
+    // Notes later received on the address are tagged with the label
+    Label {
+        address: String,
+        
+        label: Option<String>,
+    },
 }
 
 
 #[derive(Subcommand)]
 pub enum BalanceCommands {
     
-    Total,
+    Total {
+        
+        #[arg(long)]
+        tag: Option<String>,
+    },
     
     ByType,
     
+    ByTag,
+    
     Address {
         
         address: String,
@@ -123,7 +167,14 @@ pub enum NetworkCommands {
     
     Peers,
     
-    Sync,
+    Sync {
+        
+        #[arg(long)]
+        rescan_from: Option<u32>,
+        
+        #[arg(long, default_value = "100")]
+        batch_size: u32,
+    },
 }
 
 
@@ -132,11 +183,11 @@ pub enum TxCommands {
     
     Send {
         
-        #[arg(short, long)]
-        to: String,
+        #[arg(short, long, required_unless_present = "uri")]
+        to: Option<String>,
         
-        #[arg(short, long)]
-        amount: f64,
+        #[arg(short, long, required_unless_present = "uri")]
+        amount: Option<f64>,
         
         #[arg(long, value_enum, default_value = "maximum")]
         privacy: PrivacyLevelArg,
@@ -146,11 +197,93 @@ pub enum TxCommands {
         
         #[arg(short, long)]
         fee: Option<f64>,
+        
+        #[arg(long, value_delimiter = ',')]
+        notes: Option<Vec<String>>,
+        
+        #[arg(long, conflicts_with = "notes")]
+        pick: bool,
+        
+        #[arg(long, value_enum, conflicts_with_all = ["notes", "pick"])]
+        strategy: Option<SelectionStrategyArg>,
+        
+        // Pays a ZIP-321 'zcash:' payment request, all of its payments in one transaction
+        #[arg(long, conflicts_with_all = ["to", "amount", "memo", "fee", "notes", "pick", "strategy"])]
+        uri: Option<String>,
+    },
+    
+    // Creates a ZIP-321 payment request for a fresh address
+    Request {
+        
+        // ZEC, parsed exactly to the zatoshi
+        #[arg(short, long)]
+        amount: Option<String>,
+        
+        #[arg(short, long)]
+        memo: Option<String>,
+        
+        #[arg(long)]
+        label: Option<String>,
+        
+        #[arg(long)]
+        message: Option<String>,
     },
     
-    History,
+    // Pays every row of an address,amount[,memo] CSV file
+    SendMany {
+        
+        #[arg(long)]
+        file: PathBuf,
+        
+        // Also write the per-row txid report to this CSV file
+        #[arg(long)]
+        report: Option<PathBuf>,
+        
+        // Largest number of logical actions in one transaction
+        #[arg(long, default_value_t = payouts::DEFAULT_BATCH_ACTIONS)]
+        max_actions: usize,
+        
+        #[arg(long)]
+        yes: bool,
+    },
+    
+    Plan {
+        
+        #[arg(short, long)]
+        amount: f64,
+        
+        #[arg(short, long)]
+        to: Option<String>,
+    },
+    
+    History {
+        
+        #[arg(long)]
+        tag: Option<String>,
+    },
+    
+    Tag {
+        
+        txid: String,
+        
+        tag: String,
+    },
+    
+    Untag {
+        
+        txid: String,
+        
+        tag: String,
+    },
     
-    Pending,
+    Pending {
+        
+        #[arg(long)]
+        watch: bool,
+        
+        #[arg(long, default_value = "5")]
+        interval: u64,
+    },
     
     Receive {
         
@@ -208,9 +341,52 @@ pub enum PrivacyCommands {
         recipient: String,
     },
     
-    Consolidate,
+    Consolidate {
+        
+        #[arg(long)]
+        force: bool,
+        
+        #[arg(long)]
+        yes: bool,
+    },
+    
+    Mix {
+        
+        #[arg(short, long)]
+        rounds: Option<u32>,
+        
+        // Keep syncing and mixing until every round has confirmed
+        #[arg(long)]
+        watch: bool,
+        
+        #[arg(long, default_value = "30")]
+        interval: u64,
+        
+        #[arg(long)]
+        abort: bool,
+    },
+    
+    // Moves the Sapling balance to Orchard in standard denominations over randomized heights
+    MigrateToOrchard {
+        
+        #[arg(long)]
+        yes: bool,
+        
+        #[arg(long)]
+        watch: bool,
+        
+        #[arg(long, default_value = "30")]
+        interval: u64,
+        
+        #[arg(long)]
+        abort: bool,
+    },
     
-    Mix,
+    // Shows or sets scan-only mode; with no value it prints the current setting
+    ScanOnly {
+        
+        enabled: Option<bool>,
+    },
 }
 
 
@@ -263,6 +439,99 @@ pub enum DevCommands {
 }
 
 
+#[derive(Subcommand)]
+pub enum MemoCommands {
+    
+    Inbox {
+        
+        #[arg(long)]
+        unread: bool,
+    },
+    
+    Read {
+        
+        note_id: String,
+    },
+    
+    MarkAllRead,
+}
+
+
+#[derive(Subcommand)]
+pub enum NotesCommands {
+    
+    List {
+        
+        #[arg(long)]
+        all: bool,
+    },
+    
+    Label {
+        
+        note_id: String,
+        
+        label: Option<String>,
+    },
+    
+    Tag {
+        
+        note_id: String,
+        
+        tag: String,
+    },
+    
+    Untag {
+        
+        note_id: String,
+        
+        tag: String,
+    },
+    
+    SweepDust {
+        
+        #[arg(long)]
+        yes: bool,
+    },
+    
+    Split {
+        
+        #[arg(long)]
+        count: usize,
+        
+        #[arg(long)]
+        value: Option<f64>,
+        
+        #[arg(long)]
+        yes: bool,
+    },
+    
+    SplitPolicy {
+        
+        #[arg(long)]
+        min_notes: Option<usize>,
+        
+        #[arg(long)]
+        min_value: Option<f64>,
+        
+        #[arg(long, conflicts_with_all = ["min_notes", "min_value"])]
+        off: bool,
+    },
+}
+
+
+#[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
+pub enum SelectionStrategyArg {
+    PrivacyFirst,
+    EfficiencyFirst,
+    Balanced,
+    ValueBased,
+    AgeBased,
+    ExactMatch,
+    MinimalInputs,
+    PrivacyWeighted,
+}
+
+
 #[derive(Clone, Copy, PartialEq, Eq, Debug, clap::ValueEnum)]
 pub enum PrivacyLevelArg {
     Maximum,
@@ -291,6 +560,21 @@ impl From<PrivacyLevelArg> for crate::config::PrivacyLevel {
     }
 }
 
+impl From<SelectionStrategyArg> for NoteSelectionStrategy {
+    fn from(strategy: SelectionStrategyArg) -> Self {
+        match strategy {
+            SelectionStrategyArg::PrivacyFirst => NoteSelectionStrategy::PrivacyFirst,
+            SelectionStrategyArg::EfficiencyFirst => NoteSelectionStrategy::EfficiencyFirst,
+            SelectionStrategyArg::Balanced => NoteSelectionStrategy::Balanced,
+            SelectionStrategyArg::ValueBased => NoteSelectionStrategy::ValueBased,
+            SelectionStrategyArg::AgeBased => NoteSelectionStrategy::AgeBased,
+            SelectionStrategyArg::ExactMatch => NoteSelectionStrategy::ExactMatch,
+            SelectionStrategyArg::MinimalInputs => NoteSelectionStrategy::MinimalInputs,
+            SelectionStrategyArg::PrivacyWeighted => NoteSelectionStrategy::PrivacyWeighted,
+        }
+    }
+}
+
 impl From<AddressTypeArg> for crate::hd_wallet::AddressType {
     fn from(addr_type: AddressTypeArg) -> Self {
         match addr_type {
@@ -326,7 +610,7 @@ impl CliHandler {
             config: NozyConfig::default(),
         }
     }
-
+    
     
     fn load_wallet(&mut self) -> NozyResult<()> {
         let wallet_path = "nozy-wallet.json";
@@ -337,22 +621,27 @@ impl CliHandler {
         }
         Ok(())
     }
-
+    
     
     fn save_wallet(&self) -> NozyResult<()> {
         if let Some(wallet) = &self.wallet {
-            let data = serde_json::to_string_pretty(wallet)?;
-            fs::write("nozy-wallet.json", data)?;
+            wallet.save_to_file(Path::new("nozy-wallet.json"))?;
             println!("{}", "✅ Wallet saved to file".green());
         }
         Ok(())
     }
-
+    
     
     pub fn handle(&mut self, cli: &Cli) -> NozyResult<()> {
         
         self.load_wallet()?;
-
+        
+        if let Some(wallet) = &self.wallet {
+            if let Err(e) = wallet.check_sapling_params() {
+                println!("{}", format!("⚠️  Sapling notes cannot be spent: {}", e).yellow());
+            }
+        }
+        
         match &cli.command {
             Commands::Wallet(cmd) => self.handle_wallet(cmd),
             Commands::Address(cmd) => self.handle_address(cmd),
@@ -363,14 +652,16 @@ impl CliHandler {
             Commands::Blockchain(cmd) => self.handle_blockchain(cmd),
             Commands::Analytics(cmd) => self.handle_analytics(cmd),
             Commands::Dev(cmd) => self.handle_dev(cmd),
+            Commands::Memo(cmd) => self.handle_memo(cmd),
+            Commands::Notes(cmd) => self.handle_notes(cmd),
             Commands::Status => self.handle_status(),
         }?;
-
+        
         
         self.save_wallet()?;
         Ok(())
     }
-
+    
     
     fn handle_wallet(&mut self, cmd: &WalletCommands) -> NozyResult<()> {
         match cmd {
@@ -408,6 +699,78 @@ impl CliHandler {
                 }
                 Ok(())
             }
+            WalletCommands::Confirmations { min, change } => {
+                if let Some(wallet) = &mut self.wallet {
+                    if min.is_some() || change.is_some() {
+                        let (current_min, current_change) = wallet.get_confirmation_policy();
+                        wallet.set_confirmation_policy(min.unwrap_or(current_min), change.unwrap_or(current_change))?;
+                        println!("{}", "✅ Confirmation policy updated".green());
+                    }
+                    
+                    let (min_confirmations, change_confirmations) = wallet.get_confirmation_policy();
+                    println!("{}", "⛓️  Confirmation Policy:".blue());
+                    println!("  Received notes: {} confirmations", min_confirmations);
+                    println!("  Own change: {} confirmations", change_confirmations);
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+            WalletCommands::Params { dir } => {
+                if let Some(wallet) = &mut self.wallet {
+                    if let Some(dir) = dir {
+                        wallet.set_params_dir(Some(PathBuf::from(dir)));
+                        println!("{}", "✅ Sapling parameter directory updated".green());
+                    }
+                    
+                    let dir = wallet.sapling_params_dir()?;
+                    println!("{}", "🔑 Sapling Parameters:".blue());
+                    println!("  Directory: {}", dir.display());
+                    match crate::prover::check_sapling_params(&dir) {
+                        Ok(()) => println!("{}", "  ✅ Spend and output parameters match their known hashes".green()),
+                        Err(e) => println!("{}", format!("  ❌ {}", e).red()),
+                    }
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+            WalletCommands::Fsck { repair } => {
+                if let Some(wallet) = &mut self.wallet {
+                    println!("{}", "🩺 Checking wallet consistency...".blue());
+                    let report = wallet.fsck(*repair)?;
+                    println!("  Notes checked: {}", report.notes_checked);
+                    println!("  Witnesses checked: {}", report.witnesses_checked);
+                    for skipped in &report.skipped {
+                        println!("  ⏭️  Skipped: {}", skipped);
+                    }
+                    
+                    if report.is_clean() {
+                        println!("{}", "✅ No issues found".green());
+                        return Ok(());
+                    }
+                    
+                    println!("{}", format!("⚠️  {} issue(s) found:", report.issues.len()).yellow());
+                    for issue in &report.issues {
+                        let mark = if issue.repaired { "🔧" } else { "❗" };
+                        println!("  {} {:?}: {}", mark, issue.kind, issue.subject);
+                        println!("     {}", issue.detail);
+                        println!("     Fix: {}", issue.fix);
+                    }
+                    
+                    if *repair {
+                        println!("{}", format!("🔧 Repaired {} of {} issue(s)", report.repaired_count(), report.issues.len()).green());
+                        if let Some(height) = report.rescan_from {
+                            println!("  🔄 Sync rewound to block {}; run 'nozy network sync' to finish the repair", height);
+                        }
+                    } else {
+                        println!("  💡 Run 'nozy wallet fsck --repair' to apply the fixes");
+                    }
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
             WalletCommands::Config => {
                 println!("{}", "⚙️  Wallet Configuration:".blue());
                 println!("  Privacy Level: {:?}", self.config.network.default_privacy);
@@ -526,7 +889,7 @@ impl CliHandler {
             }
         }
     }
-
+    
     
     fn handle_address(&mut self, cmd: &AddressCommands) -> NozyResult<()> {
         match cmd {
@@ -579,18 +942,52 @@ impl CliHandler {
                 }
                 Ok(())
             }
+            AddressCommands::Label { address, label } => {
+                if let Some(wallet) = &mut self.wallet {
+                    wallet.set_address_label(address, label.as_deref())?;
+                    match wallet.get_address_label(address) {
+                        Some(label) => println!("{}", format!("🏷️  Notes received on {} will be tagged '{}'", address, label).green()),
+                        None => println!("{}", format!("🏷️  Label removed from {}", address).green()),
+                    }
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
         }
     }
-
+    
     
     fn handle_balance(&mut self, cmd: &BalanceCommands) -> NozyResult<()> {
         match cmd {
-            BalanceCommands::Total => {
+            BalanceCommands::Total { tag: Some(tag) } => {
+                if let Some(wallet) = &self.wallet {
+                    let balance = wallet.get_balance_by_tag(tag);
+                    println!("{}", format!("💰 Balance tagged '{}':", tag).blue());
+                    println!("  {} zatoshi", balance);
+                    println!("  {:.8} ZEC", balance as f64 / 100_000_000.0);
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+            BalanceCommands::Total { tag: None } => {
                 if let Some(wallet) = &self.wallet {
                     let balance = wallet.get_balance();
                     println!("{}", "💰 Total Balance:".blue());
                     println!("  {} zatoshi", balance);
                     println!("  {:.8} ZEC", balance as f64 / 100_000_000.0);
+                    
+                    let spendable = wallet.get_spendable_balance();
+                    let pending = wallet.get_pending_confirmation_balance();
+                    println!("  ✅ Spendable: {:.8} ZEC ({} zatoshi)", spendable as f64 / 100_000_000.0, spendable);
+                    println!("  ⏳ Pending confirmation: {:.8} ZEC ({} zatoshi)", pending as f64 / 100_000_000.0, pending);
+                    
+                    let locked = wallet.get_locked_balance();
+                    if locked > 0 {
+                        println!("  🔒 Locked in pending tx: {:.8} ZEC ({} zatoshi)",
+                            locked as f64 / 100_000_000.0, locked);
+                    }
                 } else {
                     println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
                 }
@@ -612,14 +1009,29 @@ impl CliHandler {
                 }
                 Ok(())
             }
-            BalanceCommands::Address { address: _ } => {
-                
-                println!("{}", "⚠️  Address-specific balance not implemented yet".yellow());
-                Ok(())
-            }
-        }
+            BalanceCommands::ByTag => {
+                if let Some(wallet) = &self.wallet {
+                    let balances = wallet.get_tag_balances();
+                    println!("{}", "💰 Balance by Tag:".blue());
+                    if balances.is_empty() {
+                        println!("  🏷️  No tagged notes; use 'nozy notes tag' or 'nozy address label'");
+                    }
+                    for (tag, balance) in &balances {
+                        println!("  {}: {} zatoshi ({:.8} ZEC)", tag, balance, *balance as f64 / 100_000_000.0);
+                    }
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+            BalanceCommands::Address { address: _ } => {
+                
+                println!("{}", "⚠️  Address-specific balance not implemented yet".yellow());
+                Ok(())
+            }
+        }
     }
-
+    
     
     fn handle_network(&mut self, cmd: &NetworkCommands) -> NozyResult<()> {
         match cmd {
@@ -644,15 +1056,58 @@ impl CliHandler {
                 println!("{}", "⚠️  Peer listing not implemented yet".yellow());
                 Ok(())
             }
-            NetworkCommands::Sync => {
+            NetworkCommands::Sync { rescan_from, batch_size } => {
                 if let Some(wallet) = &mut self.wallet {
-                    match wallet.sync_wallet() {
-                        Ok(_) => {
+                    if rescan_from.is_some() {
+                        wallet.reset_sync(*rescan_from);
+                    }
+                    
+                    let options = SyncOptions::new(*batch_size, Some("nozy-wallet.json".into()));
+                    let cancel = options.cancel_handle();
+                    if let Err(e) = ctrlc::set_handler(move || cancel.store(true, Ordering::SeqCst)) {
+                        println!("  ⚠️  Ctrl-C will not stop the sync cleanly: {}", e);
+                    }
+                    
+                    println!("{}", "🔄 Syncing wallet (Ctrl-C to stop, progress is saved)...".blue());
+                    let result = wallet.sync_wallet_with_progress(&options, |event| match event {
+                        SyncEvent::Started(progress) => {
+                            println!("  📦 Scanning blocks {} to {}", progress.start_height, progress.target_height);
+                        }
+                        SyncEvent::Progress(progress) => Self::draw_sync_progress(progress),
+                        SyncEvent::BatchSaved(_) => {}
+                        SyncEvent::Cancelled(progress) => {
+                            println!();
+                            println!("{}", format!("⏸️  Sync stopped at block {}; run 'nozy network sync' to resume",
+                                progress.current_height).yellow());
+                        }
+                        SyncEvent::Finished(progress) => {
+                            println!();
                             println!("{}", "🔄 Sync Status:".blue());
-                            println!("  Status: ✅ Synced");
+                            println!("  Status: ✅ Synced to block {}", progress.target_height);
+                            println!("  📥 Notes found: {}", progress.notes_found);
+                            println!("  📤 Spends detected: {}", progress.spends_found);
+                            println!("  ⏱️  Took {}", Self::format_duration(progress.elapsed()));
                         }
-                        Err(e) => {
-                            println!("{}", format!("❌ Sync failed: {}", e).red());
+                    });
+                    
+                    if let Err(e) = result {
+                        println!();
+                        println!("{}", format!("❌ Sync failed: {}", e).red());
+                        if let Some(height) = wallet.get_sync_state().scanned_height {
+                            println!("  💾 Progress saved through block {}", height);
+                        }
+                    }
+                    
+                    if let Some(plan) = wallet.plan_policy_split() {
+                        println!("{}", "✂️  Split policy: too few spendable notes, splitting".blue());
+                        Self::show_split_plan(&plan);
+                        match wallet.split_notes(&plan) {
+                            Ok(transaction) => {
+                                if let Err(e) = Self::broadcast_self_send(wallet, &transaction, "Split") {
+                                    println!("{}", format!("❌ Automatic split failed: {}", e).red());
+                                }
+                            }
+                            Err(e) => println!("{}", format!("❌ Automatic split failed: {}", e).red()),
                         }
                     }
                 } else {
@@ -662,12 +1117,19 @@ impl CliHandler {
             }
         }
     }
-
+    
     
     fn handle_tx(&mut self, cmd: &TxCommands) -> NozyResult<()> {
         match cmd {
-            TxCommands::Send { to, amount, privacy, memo, fee } => {
+            TxCommands::Send { to, amount, privacy, memo, fee, notes, pick, strategy, uri } => {
                 if let Some(wallet) = &mut self.wallet {
+                    if let Some(uri) = uri {
+                        return Self::pay_request(wallet, uri);
+                    }
+                    
+                    let (Some(to), Some(amount)) = (to, amount) else {
+                        return Err(crate::error::NozyError::InvalidOperation("--to and --amount are required without --uri".to_string()));
+                    };
                     let privacy_level: crate::config::PrivacyLevel = (*privacy).into();
                     let amount_zatoshi = (amount * 100_000_000.0) as u64;
                     
@@ -679,8 +1141,85 @@ impl CliHandler {
                         println!("  Memo: {}", memo_text);
                     }
                     
+                    let selection = if *pick {
+                        InputSelection::Manual(self.pick_notes(amount_zatoshi)?)
+                    } else if let Some(notes) = notes {
+                        InputSelection::Manual(notes.clone())
+                    } else {
+                        InputSelection::Automatic(strategy.map(NoteSelectionStrategy::from))
+                    };
+                    
+                    self.create_and_sign_transaction(to, amount_zatoshi, memo.as_deref(), *fee, selection)?;
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+            TxCommands::Request { amount, memo, label, message } => {
+                if let Some(wallet) = &mut self.wallet {
+                    let amount_zatoshi = amount.as_deref()
+                        .map(payouts::parse_zec)
+                        .transpose()
+                        .map_err(crate::error::NozyError::InvalidOperation)?;
+                    let memo_bytes = memo.as_ref().map(|memo| memo.as_bytes().to_vec());
+                    let request = wallet.request_payment(amount_zatoshi, memo_bytes, label.clone(), message.clone())?;
+                    
+                    println!("{}", "📨 Payment Request:".blue());
+                    println!("  Address: {}", request.payments[0].address);
+                    if let Some(amount) = request.payments[0].amount {
+                        println!("  Amount: {} ZEC ({} zatoshi)", payment_request::format_amount(amount), amount);
+                    }
+                    println!("  URI: {}", request.to_uri());
+                    println!("  💡 Each request uses a new address, so payers cannot link your invoices");
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+            TxCommands::SendMany { file, report, max_actions, yes } => {
+                if let Some(wallet) = &mut self.wallet {
+                    let text = fs::read_to_string(file)?;
+                    let rows = wallet.parse_payouts(&text)?;
+                    let plan = wallet.plan_payouts(&rows, *max_actions)?;
+                    
+                    println!("{}", "📤 Batch Payout:".blue());
+                    Self::show_payout_plan(&rows, &plan);
+                    
+                    if !*yes && !Self::confirm("  Send these payouts?")? {
+                        println!("  ❎ Payouts cancelled");
+                        return Ok(());
+                    }
+                    
+                    let results = wallet.send_payouts(&rows, &plan);
+                    Self::show_payout_report(&results);
+                    if let Some(path) = report {
+                        Self::write_payout_report(path, &results)?;
+                        println!("  📄 Report written to {}", path.display());
+                    }
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+            TxCommands::Plan { amount, to } => {
+                if let Some(wallet) = &self.wallet {
+                    let amount_zatoshi = (amount * 100_000_000.0) as u64;
+                    let to = to.as_deref().unwrap_or("");
                     
-                    self.create_and_sign_transaction(to, amount_zatoshi, memo.as_deref(), *fee)?;
+                    println!("{}", format!("🧮 Selection strategies for {:.8} ZEC ({} zatoshi):",
+                        amount_zatoshi as f64 / 100_000_000.0, amount_zatoshi).blue());
+                    for (strategy, plan) in wallet.compare_selection_strategies(to, amount_zatoshi) {
+                        match plan {
+                            Ok(plan) => {
+                                let pools = if plan.mixes_pools() { "mixes pools" } else { "single pool" };
+                                println!("  {:<16} {} inputs, {} actions, fee {:.8} ZEC, change {:.8} ZEC, {}",
+                                    format!("{:?}", strategy), plan.input_count(), plan.logical_actions,
+                                    plan.fee as f64 / 100_000_000.0, plan.change as f64 / 100_000_000.0, pools);
+                            }
+                            Err(e) => println!("  {:<16} {}", format!("{:?}", strategy), format!("unavailable: {}", e).yellow()),
+                        }
+                    }
+                    println!("  💡 Send with one of them using 'nozy tx send --strategy <name>'");
                 } else {
                     println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
                 }
@@ -747,34 +1286,91 @@ impl CliHandler {
                         println!("  Memo: {}", memo_text);
                     }
                     
-                    let estimated_fee = self.estimate_transaction_fee(to, amount_zatoshi, memo.as_deref())?;
+                    let estimated_fee = self.estimate_transaction_fee(to, amount_zatoshi)?;
                     println!("  Estimated Fee: {} ZEC", estimated_fee as f64 / 100_000_000.0);
                 } else {
                     println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
                 }
                 Ok(())
             }
-            TxCommands::History => {
+            TxCommands::History { tag } => {
                 if let Some(wallet) = &self.wallet {
                     println!("{}", "📜 Transaction History:".blue());
-                    self.show_transaction_history(wallet)?;
+                    self.show_transaction_history(wallet, tag.as_deref())?;
                 } else {
                     println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
                 }
                 Ok(())
             }
-            TxCommands::Pending => {
-                if let Some(wallet) = &self.wallet {
-                    println!("{}", "⏳ Pending Transactions:".blue());
-                    self.show_pending_transactions(wallet)?;
+            TxCommands::Tag { txid, tag } => {
+                if let Some(wallet) = &mut self.wallet {
+                    if wallet.tag_transaction(txid, tag)? {
+                        println!("{}", format!("🏷️  Transaction {} tagged '{}'", txid, tag).green());
+                    } else {
+                        println!("  💡 Transaction {} already has tag '{}'", txid, tag);
+                    }
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+            TxCommands::Untag { txid, tag } => {
+                if let Some(wallet) = &mut self.wallet {
+                    if wallet.untag_transaction(txid, tag)? {
+                        println!("{}", format!("🏷️  Tag '{}' removed from transaction {}", tag, txid).green());
+                    } else {
+                        println!("  💡 Transaction {} has no tag '{}'", txid, tag);
+                    }
                 } else {
                     println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
                 }
                 Ok(())
             }
+            TxCommands::Pending { watch, interval } => {
+                if self.wallet.is_none() {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                    return Ok(());
+                }
+                
+                loop {
+                    let mut polled = false;
+                    if let Some(wallet) = &mut self.wallet {
+                        match wallet.poll_mempool() {
+                            Ok(update) => {
+                                polled = true;
+                                if *watch {
+                                    for pending in &update.new_incoming {
+                                        println!("{}", format!("🔔 Incoming {:.8} ZEC in {}",
+                                            pending.note.value as f64 / 100_000_000.0, pending.txid).green());
+                                    }
+                                }
+                            }
+                            Err(e) => {
+                                println!("  ⚠️  Could not scan mempool: {}", e);
+                            }
+                        }
+                    }
+                    
+                    if let Some(wallet) = &self.wallet {
+                        println!("{}", "⏳ Pending Transactions:".blue());
+                        if polled {
+                            self.show_pending_transactions(wallet)?;
+                        } else {
+                            self.show_local_pending_transactions(wallet)?;
+                        }
+                    }
+                    
+                    if !*watch {
+                        break;
+                    }
+                    self.save_wallet()?;
+                    std::thread::sleep(std::time::Duration::from_secs(*interval));
+                }
+                Ok(())
+            }
         }
     }
-
+    
     
     fn handle_privacy(&mut self, cmd: &PrivacyCommands) -> NozyResult<()> {
         match cmd {
@@ -792,6 +1388,26 @@ impl CliHandler {
                 }
                 Ok(())
             }
+            PrivacyCommands::ScanOnly { enabled } => {
+                if let Some(wallet) = &mut self.wallet {
+                    if let Some(enabled) = enabled {
+                        wallet.set_scan_only(*enabled);
+                        self.save_wallet()?;
+                    }
+                    let wallet = self.wallet.as_ref().unwrap();
+                    if wallet.is_scan_only() {
+                        println!("{}", "🔒 Scan-only mode is on".green());
+                        println!("  Only block, tree-state and mempool data is requested; notes are found by local trial decryption.");
+                        println!("  Any request carrying an address or viewing key is refused.");
+                    } else {
+                        println!("{}", "⚠️  Scan-only mode is off".yellow());
+                        println!("  Indexer lookups may reveal your addresses to the server.");
+                    }
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
             PrivacyCommands::Stealth { recipient } => {
                 if let Some(wallet) = &mut self.wallet {
                     println!("{}", "🕵️  Creating stealth address...".blue());
@@ -804,23 +1420,179 @@ impl CliHandler {
                 }
                 Ok(())
             }
-            PrivacyCommands::Consolidate => {
+            PrivacyCommands::Consolidate { force, yes } => {
                 if let Some(wallet) = &mut self.wallet {
                     println!("{}", "🔗 Consolidating notes...".blue());
                     
+                    let Some(plan) = wallet.plan_consolidation(*force) else {
+                        println!("  💡 Nothing to consolidate (consolidation may be disabled; use --force to override)");
+                        return Ok(());
+                    };
+                    
+                    println!("  Merging {} notes worth {:.8} ZEC ({} zatoshi)",
+                        plan.note_ids.len(), plan.total_value as f64 / 100_000_000.0, plan.total_value);
+                    for note_id in &plan.note_ids {
+                        println!("     • {}", note_id);
+                    }
+                    println!("  Fee: {:.8} ZEC ({} zatoshi)", plan.fee as f64 / 100_000_000.0, plan.fee);
+                    println!("  Result: one 🌳 Orchard note of {:.8} ZEC ({} zatoshi)",
+                        plan.output_value as f64 / 100_000_000.0, plan.output_value);
+                    
+                    if !*yes && !Self::confirm("  Proceed with the consolidation?")? {
+                        println!("  ❎ Consolidation cancelled");
+                        return Ok(());
+                    }
+                    
+                    let transaction = wallet.consolidate_notes(&plan)?;
+                    Self::broadcast_self_send(wallet, &transaction, "Consolidation")?;
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+            PrivacyCommands::Mix { rounds, watch, interval, abort } => {
+                if let Some(wallet) = &mut self.wallet {
+                    if *abort {
+                        match wallet.abort_mixing() {
+                            Some(session) => {
+                                println!("{}", format!("⏹️  Mixing stopped after {}/{} rounds", session.rounds_done, session.rounds_total).yellow());
+                                if let Some(round) = session.pending {
+                                    println!("  ⏳ Round {} ({}) is still in flight and will confirm or expire on its own", round.round, round.txid);
+                                }
+                                println!("  Fees paid: {:.8} ZEC ({} zatoshi)", session.fees_paid as f64 / 100_000_000.0, session.fees_paid);
+                            }
+                            None => println!("  💡 No mixing session to stop"),
+                        }
+                        return Ok(());
+                    }
+                    
+                    match wallet.get_mixing_session() {
+                        Some(session) if !session.is_finished() => {
+                            println!("{}", format!("🔄 Resuming mixing: {}/{} rounds done", session.rounds_done, session.rounds_total).blue());
+                        }
+                        _ => {
+                            let session = wallet.start_mixing(*rounds)?;
+                            println!("{}", format!("🔄 Mixing notes over {} rounds", session.rounds_total).blue());
+                        }
+                    }
                     
-                    println!("{}", "⚠️  Note consolidation not implemented yet".yellow());
+                    Self::run_session(wallet, *watch, *interval, "mix", |wallet| {
+                        match wallet.mixing_step()? {
+                            MixStep::Broadcast(round) => {
+                                println!("  🎉 Round {} broadcast: {:?} of {} notes into {} outputs",
+                                    round.round, round.kind, round.input_note_ids.len(), round.output_values.len());
+                                println!("     🔗 TX ID: {}", round.txid);
+                                println!("     Fee: {:.8} ZEC ({} zatoshi)", round.fee as f64 / 100_000_000.0, round.fee);
+                            }
+                            MixStep::WaitingForConfirmation(round) => {
+                                println!("  ⏳ Round {} waiting for confirmation ({})", round.round, round.txid);
+                            }
+                            MixStep::WaitingUntil(height) => {
+                                println!("  ⏳ Next round not before block {}", height);
+                            }
+                            MixStep::WaitingForNotes => {
+                                println!("  ⏳ Waiting for mixed notes to become spendable");
+                            }
+                            MixStep::Finished { rounds, fees_paid } => {
+                                println!("{}", format!("✅ Mixing finished after {} rounds", rounds).green());
+                                println!("  Total fees: {:.8} ZEC ({} zatoshi)", fees_paid as f64 / 100_000_000.0, fees_paid);
+                                return Ok(true);
+                            }
+                        }
+                        
+                        if let Some(session) = wallet.get_mixing_session() {
+                            println!("  📊 {}/{} rounds done, fees so far {:.8} ZEC",
+                                session.rounds_done, session.rounds_total, session.fees_paid as f64 / 100_000_000.0);
+                        }
+                        Ok(false)
+                    })?;
                 } else {
                     println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
                 }
                 Ok(())
             }
-            PrivacyCommands::Mix => {
+            PrivacyCommands::MigrateToOrchard { yes, watch, interval, abort } => {
                 if let Some(wallet) = &mut self.wallet {
-                    println!("{}", "🔄 Mixing notes...".blue());
+                    if *abort {
+                        match wallet.abort_migration() {
+                            Some(session) => {
+                                println!("{}", format!("⏹️  Migration stopped after {}/{} transfers",
+                                    session.count(TransferStatus::Confirmed), session.transfers.len()).yellow());
+                                if session.count(TransferStatus::Broadcast) > 0 {
+                                    println!("  ⏳ Transfers already broadcast will confirm or expire on their own");
+                                }
+                            }
+                            None => println!("  💡 No migration to stop"),
+                        }
+                        Self::show_sapling_balance(wallet);
+                        return Ok(());
+                    }
                     
+                    match wallet.get_migration_session() {
+                        Some(session) if !session.is_finished() => {
+                            println!("{}", format!("🌳 Resuming Sapling migration: {}/{} transfers confirmed",
+                                session.count(TransferStatus::Confirmed), session.transfers.len()).blue());
+                        }
+                        _ => {
+                            println!("{}", "🌳 Planning Sapling to Orchard migration...".blue());
+                            let Some(session) = wallet.plan_migration()? else {
+                                println!("  💡 Not enough spendable Sapling funds to migrate");
+                                Self::show_sapling_balance(wallet);
+                                return Ok(());
+                            };
+                            
+                            let (value, fees) = session.outstanding();
+                            println!("  {} transfers totalling {:.8} ZEC ({} zatoshi)", session.transfers.len(), value as f64 / 100_000_000.0, value);
+                            for transfer in &session.transfers {
+                                println!("     • {:.8} ZEC at block {}", transfer.denomination as f64 / 100_000_000.0, transfer.scheduled_height);
+                            }
+                            println!("  Estimated fees: {:.8} ZEC ({} zatoshi)", fees as f64 / 100_000_000.0, fees);
+                            
+                            if !*yes && !Self::confirm("  Start the migration?")? {
+                                println!("  ❎ Migration cancelled");
+                                return Ok(());
+                            }
+                            wallet.start_migration(session)?;
+                        }
+                    }
                     
-                    println!("{}", "⚠️  Note mixing not implemented yet".yellow());
+                    Self::run_session(wallet, *watch, *interval, "migrate-to-orchard", |wallet| {
+                        match wallet.migration_step()? {
+                            MigrationStep::Broadcast(transfer) => {
+                                println!("  🎉 Moved {:.8} ZEC to Orchard", transfer.denomination as f64 / 100_000_000.0);
+                                println!("     🔗 TX ID: {}", transfer.txid.unwrap_or_default());
+                                println!("     Fee: {:.8} ZEC ({} zatoshi)", transfer.fee as f64 / 100_000_000.0, transfer.fee);
+                            }
+                            MigrationStep::WaitingForConfirmation(count) => {
+                                println!("  ⏳ {} transfers waiting for confirmation", count);
+                            }
+                            MigrationStep::WaitingUntil(height) => {
+                                println!("  ⏳ Next transfer not before block {}", height);
+                            }
+                            MigrationStep::WaitingForNotes => {
+                                println!("  ⏳ Waiting for Sapling change to become spendable");
+                            }
+                            MigrationStep::Skipped(transfer) => {
+                                println!("{}", format!("  ⚠️  Skipped {:.8} ZEC: not enough Sapling funds left",
+                                    transfer.denomination as f64 / 100_000_000.0).yellow());
+                            }
+                            MigrationStep::Finished { moved, fees_paid } => {
+                                println!("{}", format!("✅ Migration finished: {:.8} ZEC moved to Orchard", moved as f64 / 100_000_000.0).green());
+                                println!("  Total fees: {:.8} ZEC ({} zatoshi)", fees_paid as f64 / 100_000_000.0, fees_paid);
+                                Self::show_sapling_balance(wallet);
+                                return Ok(true);
+                            }
+                        }
+                        
+                        if let Some(session) = wallet.get_migration_session() {
+                            let (value, _) = session.outstanding();
+                            println!("  📊 {}/{} transfers confirmed, {:.8} ZEC still to move, fees so far {:.8} ZEC",
+                                session.count(TransferStatus::Confirmed), session.transfers.len(),
+                                value as f64 / 100_000_000.0, session.fees_paid() as f64 / 100_000_000.0);
+                        }
+                        Self::show_sapling_balance(wallet);
+                        Ok(false)
+                    })?;
                 } else {
                     println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
                 }
@@ -828,7 +1600,7 @@ impl CliHandler {
             }
         }
     }
-
+    
     
     fn handle_blockchain(&mut self, cmd: &BlockchainCommands) -> NozyResult<()> {
         match cmd {
@@ -856,7 +1628,7 @@ impl CliHandler {
             }
         }
     }
-
+    
     
     fn handle_analytics(&mut self, cmd: &AnalyticsCommands) -> NozyResult<()> {
         match cmd {
@@ -906,50 +1678,76 @@ impl CliHandler {
             }
         }
     }
-
     
-    fn handle_dev(&mut self, cmd: &DevCommands) -> NozyResult<()> {
+    
+    fn handle_memo(&mut self, cmd: &MemoCommands) -> NozyResult<()> {
         match cmd {
-            DevCommands::Performance => {
+            MemoCommands::Inbox { unread } => {
                 if let Some(wallet) = &self.wallet {
-                    println!("{}", "⚡ Performance Metrics:".blue());
+                    println!("{}", "📬 Memo Inbox:".blue());
                     
+                    let entries: Vec<_> = wallet.memo_inbox()
+                        .into_iter()
+                        .filter(|entry| !*unread || !entry.read)
+                        .collect();
                     
-                    println!("{}", "⚠️  Performance tracking not implemented yet".yellow());
-                } else {
-                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
-                }
-                Ok(())
-            }
-            DevCommands::Simulate { tx_type } => {
-                if let Some(wallet) = &self.wallet {
-                    println!("{}", "🧪 Simulating transaction...".blue());
-                    println!("  Type: {}", tx_type);
+                    if entries.is_empty() {
+                        println!("  📭 No memos");
+                        return Ok(());
+                    }
                     
+                    for entry in &entries {
+                        let marker = if entry.read { "  " } else { "🆕" };
+                        let preview: String = entry.memo.to_string().lines().next().unwrap_or("").chars().take(60).collect();
+                        println!("  {} [{}] block {} - {:.8} ZEC ({} zatoshi)",
+                            marker, entry.note_id, entry.block_height,
+                            entry.value as f64 / 100_000_000.0, entry.value);
+                        println!("     📝 {}", preview);
+                        if let Some(reply) = &entry.reply_address {
+                            println!("     ↩️  Reply to: {}", reply);
+                        }
+                    }
                     
-                    println!("{}", "⚠️  Transaction simulation not implemented yet".yellow());
+                    let unread_count = entries.iter().filter(|entry| !entry.read).count();
+                    println!("  📊 {} memos, {} unread", entries.len(), unread_count);
+                    println!("  💡 Run 'nozy memo read <note-id>' to open a memo");
                 } else {
                     println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
                 }
                 Ok(())
             }
-            DevCommands::StressTest => {
-                if let Some(wallet) = &self.wallet {
-                    println!("{}", "💪 Running stress test...".blue());
-                    
+            MemoCommands::Read { note_id } => {
+                if let Some(wallet) = &mut self.wallet {
+                    let entry = wallet.memo_inbox()
+                        .into_iter()
+                        .find(|entry| entry.note_id == *note_id);
                     
-                    println!("{}", "⚠️  Stress testing not implemented yet".yellow());
+                    match entry {
+                        Some(entry) => {
+                            println!("{}", format!("📨 Memo on note {}:", entry.note_id).blue());
+                            println!("  💰 Amount: {:.8} ZEC ({} zatoshi)", entry.value as f64 / 100_000_000.0, entry.value);
+                            println!("  📦 Block: {}", entry.block_height);
+                            println!("  🏷️  Type: {}", entry.memo.kind());
+                            if let Some(reply) = &entry.reply_address {
+                                println!("  ↩️  Reply to: {}", reply);
+                            }
+                            println!();
+                            println!("{}", entry.memo);
+                            wallet.mark_memo_read(&entry.note_id)?;
+                        }
+                        None => {
+                            println!("{}", format!("❌ No memo found for note {}", note_id).red());
+                        }
+                    }
                 } else {
                     println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
                 }
                 Ok(())
             }
-            DevCommands::Debug => {
-                if let Some(wallet) = &self.wallet {
-                    println!("{}", "🐛 Debug Information:".blue());
-                    
-                    
-                    println!("{}", "⚠️  Debug logging not implemented yet".yellow());
+            MemoCommands::MarkAllRead => {
+                if let Some(wallet) = &mut self.wallet {
+                    let count = wallet.mark_all_memos_read();
+                    println!("{}", format!("✅ Marked {} memos as read", count).green());
                 } else {
                     println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
                 }
@@ -957,133 +1755,376 @@ impl CliHandler {
             }
         }
     }
-
-    
-    fn handle_status(&mut self) -> NozyResult<()> {
-        if let Some(wallet) = &self.wallet {
-            let status = wallet.get_status();
-            let addresses = wallet.get_addresses();
-            let notes = wallet.get_notes();
-            
-            println!("{}", "📊 Nozy Wallet Status:".blue());
-            println!("  Status: {:?}", status);
-            println!("  Addresses: {}", addresses.len());
-            println!("  Notes: {}", notes.len());
-            
-            let balance = wallet.get_balance();
-            println!("  Balance: {:.8} ZEC", balance as f64 / 100_000_000.0);
-        } else {
-            println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
-        }
-        Ok(())
-    }
-    
     
     
-    
-    fn create_and_sign_transaction(&mut self, to: &str, amount_zatoshi: u64, memo: Option<&str>, fee: Option<f64>) -> NozyResult<()> {
+    // Lets the user choose the input notes by number or ID from the spendable set
+    fn pick_notes(&self, amount_zatoshi: u64) -> NozyResult<Vec<String>> {
         let wallet = self.wallet.as_ref().ok_or_else(|| {
             crate::error::NozyError::InvalidOperation("No wallet loaded".to_string())
         })?;
         
-        println!("  🔐 Creating transaction structure...");
-        
-        
-        let total_balance = wallet.get_balance();
-        let required_amount = amount_zatoshi + (fee.unwrap_or(0.001) * 100_000_000.0) as u64;
-        
-        if total_balance < required_amount {
-            return Err(crate::error::NozyError::InsufficientFunds(
-                format!("Insufficient funds. Required: {} zatoshi, Available: {} zatoshi", 
-                    required_amount, total_balance)
-            ));
-        }
-        
-        
-        let available_notes = wallet.get_notes();
-        if available_notes.is_empty() {
-            return Err(crate::error::NozyError::InsufficientFunds(
-                "No notes available for spending. Generate addresses and receive some ZEC first.".to_string()
-            ));
+        let mut notes = wallet.get_spendable_notes();
+        if notes.is_empty() {
+            return Err(crate::error::NozyError::InsufficientFunds("No spendable notes to choose from".to_string()));
         }
+        notes.sort_by(|a, b| a.created_at_height.cmp(&b.created_at_height).then(a.id.cmp(&b.id)));
         
-        println!("  📝 Found {} available notes", available_notes.len());
-        
-        
-        
-        let mut total_selected = 0u64;
-        let mut selected_notes = Vec::new();
-        
-        for note in available_notes.iter() {
-            if total_selected >= required_amount {
-                break;
-            }
-            selected_notes.push(note);
-            total_selected += note.value;
+        println!("{}", "🪙 Spendable notes:".blue());
+        for (i, note) in notes.iter().enumerate() {
+            println!("  {:>3}. {}", i + 1, self.format_note_row(wallet, note));
         }
-        
-        if total_selected < required_amount {
-            return Err(crate::error::NozyError::InsufficientFunds(
-                format!("Insufficient funds in available notes. Required: {}, Available: {}", 
-                    required_amount, total_selected)
-            ));
+        println!("  Need {:.8} ZEC ({} zatoshi) plus the fee", amount_zatoshi as f64 / 100_000_000.0, amount_zatoshi);
+        print!("  Notes to spend (numbers or IDs, comma-separated): ");
+        std::io::stdout().flush()?;
+        
+        let mut line = String::new();
+        std::io::stdin().read_line(&mut line)?;
+        
+        let mut chosen = Vec::new();
+        for token in line.split(',').map(str::trim).filter(|token| !token.is_empty()) {
+            let note_id = match token.parse::<usize>() {
+                Ok(index) => notes.get(index.wrapping_sub(1))
+                    .map(|note| note.id.clone())
+                    .ok_or_else(|| crate::error::NozyError::InvalidOperation(format!("No note numbered {}", index)))?,
+                Err(_) => token.to_string(),
+            };
+            chosen.push(note_id);
         }
         
-        println!("  🎯 Selected {} notes for transaction", selected_notes.len());
-        
-        
-        let fee_amount = if let Some(fee_zec) = fee {
-            (fee_zec * 100_000_000.0) as u64
+        let total: u64 = chosen.iter()
+            .filter_map(|id| wallet.get_note(id))
+            .map(|note| note.value)
+            .sum();
+        println!("  Chosen: {} notes, {:.8} ZEC ({} zatoshi)", chosen.len(), total as f64 / 100_000_000.0, total);
+        Ok(chosen)
+    }
+    
+    
+    fn format_note_row(&self, wallet: &NozyWallet, note: &crate::notes::ShieldedNote) -> String {
+        let pool = match note.note_type {
+            NoteType::Orchard => "🌳 Orchard",
+            NoteType::Sapling => "🍃 Sapling",
+        };
+        let status = if note.is_spent() {
+            "spent"
+        } else if wallet.is_note_locked(&note.id) {
+            "locked"
+        } else if !wallet.is_note_confirmed(note) {
+            "pending confirmation"
         } else {
-            
-            
-            10_000 
+            "spendable"
         };
+        let label = note.label.as_deref().unwrap_or("-");
+        let tags = if note.tags.is_empty() { String::new() } else { format!("  [{}]", note.tags.join(", ")) };
         
+        format!("{}  {:.8} ZEC  {}  block {} ({} confs)  {}  {}{}",
+            note.id, note.value as f64 / 100_000_000.0, pool,
+            note.created_at_height, wallet.note_confirmations(note), status, label, tags)
+    }
+    
+    
+    fn handle_notes(&mut self, cmd: &NotesCommands) -> NozyResult<()> {
+        match cmd {
+            NotesCommands::List { all } => {
+                if let Some(wallet) = &self.wallet {
+                    let mut notes = if *all { wallet.get_all_notes() } else { wallet.get_notes() };
+                    notes.sort_by(|a, b| a.created_at_height.cmp(&b.created_at_height).then(a.id.cmp(&b.id)));
+                    
+                    println!("{}", "🪙 Notes:".blue());
+                    if notes.is_empty() {
+                        println!("  📭 No notes found");
+                        return Ok(());
+                    }
+                    
+                    println!("  ID  VALUE  POOL  AGE  STATUS  LABEL");
+                    for note in &notes {
+                        println!("  {}", self.format_note_row(wallet, note));
+                    }
+                    
+                    let total: u64 = notes.iter().filter(|note| !note.is_spent()).map(|note| note.value).sum();
+                    println!("  📊 {} notes, {:.8} ZEC ({} zatoshi) unspent", notes.len(), total as f64 / 100_000_000.0, total);
+                    println!("  💡 Spend specific notes with 'nozy tx send --notes <id,id>' or '--pick'");
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+            NotesCommands::SweepDust { yes } => {
+                if let Some(wallet) = &mut self.wallet {
+                    let dust = wallet.get_dust_notes();
+                    let threshold = wallet.dust_threshold();
+                    println!("{}", "🧹 Dust Sweep:".blue());
+                    println!("  {} dust notes worth {:.8} ZEC (each at most {} zatoshi)",
+                        dust.len(), wallet.get_dust_balance() as f64 / 100_000_000.0, threshold);
+                    
+                    let Some(sweep) = wallet.plan_dust_sweep() else {
+                        println!("  💡 Sweeping would cost more in fees than the dust is worth; nothing to do");
+                        return Ok(());
+                    };
+                    
+                    println!("  Sweeping {} dust notes ({:.8} ZEC)", sweep.dust_note_ids.len(), sweep.dust_value as f64 / 100_000_000.0);
+                    if let Some(carrier) = &sweep.carrier_note_id {
+                        println!("  Carrier note: {}", carrier);
+                    }
+                    println!("  Fee: {:.8} ZEC ({} zatoshi), {} zatoshi of it due to the dust",
+                        sweep.fee as f64 / 100_000_000.0, sweep.fee, sweep.extra_fee);
+                    println!("  Recovered: {:.8} ZEC into one {:.8} ZEC note",
+                        sweep.recovered_value() as f64 / 100_000_000.0, sweep.output_value as f64 / 100_000_000.0);
+                    
+                    if !*yes && !Self::confirm("  Proceed with the sweep?")? {
+                        println!("  ❎ Sweep cancelled");
+                        return Ok(());
+                    }
+                    
+                    let transaction = wallet.sweep_dust(&sweep)?;
+                    Self::broadcast_self_send(wallet, &transaction, "Dust sweep")?;
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+            NotesCommands::Split { count, value, yes } => {
+                if let Some(wallet) = &mut self.wallet {
+                    let value_zatoshi = value.map(|value| (value * 100_000_000.0) as u64);
+                    let plan = wallet.plan_split(*count, value_zatoshi)?;
+                    
+                    println!("{}", "✂️  Note Split:".blue());
+                    Self::show_split_plan(&plan);
+                    
+                    if !*yes && !Self::confirm("  Proceed with the split?")? {
+                        println!("  ❎ Split cancelled");
+                        return Ok(());
+                    }
+                    
+                    let transaction = wallet.split_notes(&plan)?;
+                    Self::broadcast_self_send(wallet, &transaction, "Split")?;
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+            NotesCommands::SplitPolicy { min_notes, min_value, off } => {
+                if let Some(wallet) = &mut self.wallet {
+                    if *off {
+                        wallet.set_split_policy(None);
+                        println!("{}", "✅ Automatic splitting turned off".green());
+                    } else if min_notes.is_some() || min_value.is_some() {
+                        let current = wallet.get_split_policy().cloned();
+                        let policy = SplitPolicy {
+                            min_notes: min_notes.or(current.as_ref().map(|policy| policy.min_notes)).unwrap_or(1),
+                            min_value: min_value.map(|value| (value * 100_000_000.0) as u64)
+                                .or(current.as_ref().map(|policy| policy.min_value))
+                                .unwrap_or(wallet.dust_threshold() + 1),
+                        };
+                        wallet.set_split_policy(Some(policy));
+                        println!("{}", "✅ Split policy updated".green());
+                    }
+                    
+                    match wallet.get_split_policy() {
+                        Some(policy) => {
+                            println!("{}", "✂️  Split Policy:".blue());
+                            println!("  Keep at least {} spendable notes of {:.8} ZEC or more",
+                                policy.min_notes, policy.min_value as f64 / 100_000_000.0);
+                            println!("  💡 Checked after every 'nozy network sync'");
+                        }
+                        None => println!("  Automatic splitting is off"),
+                    }
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+            NotesCommands::Label { note_id, label } => {
+                if let Some(wallet) = &mut self.wallet {
+                    wallet.set_note_label(note_id, label.clone())?;
+                    match label {
+                        Some(label) => println!("{}", format!("🏷️  Note {} labelled '{}'", note_id, label).green()),
+                        None => println!("{}", format!("🏷️  Label removed from note {}", note_id).green()),
+                    }
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+            NotesCommands::Tag { note_id, tag } => {
+                if let Some(wallet) = &mut self.wallet {
+                    if wallet.tag_note(note_id, tag)? {
+                        println!("{}", format!("🏷️  Note {} tagged '{}'", note_id, tag).green());
+                    } else {
+                        println!("  💡 Note {} already has tag '{}'", note_id, tag);
+                    }
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+            NotesCommands::Untag { note_id, tag } => {
+                if let Some(wallet) = &mut self.wallet {
+                    if wallet.untag_note(note_id, tag)? {
+                        println!("{}", format!("🏷️  Tag '{}' removed from note {}", tag, note_id).green());
+                    } else {
+                        println!("  💡 Note {} has no tag '{}'", note_id, tag);
+                    }
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+        }
+    }
+    
+    
+    fn handle_dev(&mut self, cmd: &DevCommands) -> NozyResult<()> {
+        match cmd {
+            DevCommands::Performance => {
+                if let Some(wallet) = &self.wallet {
+                    println!("{}", "⚡ Performance Metrics:".blue());
+                    
+                    
+                    println!("{}", "⚠️  Performance tracking not implemented yet".yellow());
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+            DevCommands::Simulate { tx_type } => {
+                if let Some(wallet) = &self.wallet {
+                    println!("{}", "🧪 Simulating transaction...".blue());
+                    println!("  Type: {}", tx_type);
+                    
+                    
+                    println!("{}", "⚠️  Transaction simulation not implemented yet".yellow());
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+            DevCommands::StressTest => {
+                if let Some(wallet) = &self.wallet {
+                    println!("{}", "💪 Running stress test...".blue());
+                    
+                    
+                    println!("{}", "⚠️  Stress testing not implemented yet".yellow());
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+            DevCommands::Debug => {
+                if let Some(wallet) = &self.wallet {
+                    println!("{}", "🐛 Debug Information:".blue());
+                    
+                    
+                    println!("{}", "⚠️  Debug logging not implemented yet".yellow());
+                } else {
+                    println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+                }
+                Ok(())
+            }
+        }
+    }
+    
+    
+    fn handle_status(&mut self) -> NozyResult<()> {
+        if let Some(wallet) = &self.wallet {
+            let status = wallet.get_status();
+            let addresses = wallet.get_addresses();
+            let notes = wallet.get_notes();
+            
+            println!("{}", "📊 Nozy Wallet Status:".blue());
+            println!("  Status: {:?}", status);
+            println!("  Addresses: {}", addresses.len());
+            println!("  Notes: {}", notes.len());
+            
+            let balance = wallet.get_balance();
+            println!("  Balance: {:.8} ZEC", balance as f64 / 100_000_000.0);
+        } else {
+            println!("{}", "❌ No wallet loaded. Run 'nozy wallet init' first.".red());
+        }
+        Ok(())
+    }
+    
+    
+    
+    
+    fn create_and_sign_transaction(
+        &mut self,
+        to: &str,
+        amount_zatoshi: u64,
+        memo: Option<&str>,
+        fee: Option<f64>,
+        selection: InputSelection,
+    ) -> NozyResult<()> {
+        let wallet = self.wallet.as_mut().ok_or_else(|| {
+            crate::error::NozyError::InvalidOperation("No wallet loaded".to_string())
+        })?;
         
-        let expiry_height = 1_000_000; 
-        
-        println!("  💰 Transaction Details:");
-        println!("     Amount: {:.8} ZEC ({} zatoshi)", amount_zatoshi as f64 / 100_000_000.0, amount_zatoshi);
-        println!("     Fee: {:.8} ZEC ({} zatoshi)", fee_amount as f64 / 100_000_000.0, fee_amount);
-        println!("     Total Input: {:.8} ZEC ({} zatoshi)", 
-            total_selected as f64 / 100_000_000.0, total_selected);
-        println!("     Change: {:.8} ZEC ({} zatoshi)", 
-            (total_selected - amount_zatoshi - fee_amount) as f64 / 100_000_000.0,
-            total_selected - amount_zatoshi - fee_amount);
-        
-        
-        
-        println!("  🔨 Building transaction structure...");
-        
-        
-        println!("  ✅ Transaction structure planned successfully");
-        println!("  🔑 Transaction details:");
-        println!("     Input Notes: {} notes", selected_notes.len());
-        println!("     Output Address: {}", to);
-        println!("     Amount: {} zatoshi", amount_zatoshi);
-        println!("     Fee: {} zatoshi", fee_amount);
-        println!("     Change: {} zatoshi", total_selected - amount_zatoshi - fee_amount);
-        
-        
-        if selected_notes.is_empty() {
-            return Err(crate::error::NozyError::InvalidOperation(
-                "Transaction has no inputs".to_string()
-            ));
-        }
-        
-        println!("  🔍 Transaction validation: PASSED");
+        println!("  🔐 Creating transaction structure...");
         
+        let hand_picked = matches!(selection, InputSelection::Manual(_));
+        
+        // Without an explicit fee, automatic selection plans the inputs and their ZIP-317 fee together
+        let (fee_amount, selection) = match (fee, selection) {
+            (Some(fee_zec), selection) => {
+                let fee_amount = (fee_zec * 100_000_000.0) as u64;
+                if let Ok(minimum) = wallet.minimum_fee(to, amount_zatoshi, &selection) {
+                    if fee_amount < minimum {
+                        println!("{}", format!(
+                            "  ⚠️  Fee of {} zatoshi is below the ZIP-317 minimum of {} zatoshi; nodes may refuse to relay it",
+                            fee_amount, minimum
+                        ).yellow());
+                    }
+                }
+                (fee_amount, selection)
+            }
+            (None, InputSelection::Automatic(strategy)) => {
+                let plan = wallet.plan_spend(to, amount_zatoshi, strategy)?;
+                println!("  🧮 Strategy {:?}: {} inputs, {} logical actions",
+                    plan.strategy, plan.input_count(), plan.logical_actions);
+                (plan.fee, InputSelection::Manual(plan.note_ids))
+            }
+            (None, selection) => (wallet.minimum_fee(to, amount_zatoshi, &selection)?, selection),
+        };
         
-        let estimated_size = selected_notes.len() * 200 + 500; 
-        println!("  📤 Transaction ready for building (estimated {} bytes)", estimated_size);
+        let locked = wallet.get_locked_balance();
+        if locked > 0 {
+            println!("  🔒 {:.8} ZEC is locked in pending transactions and will not be reused",
+                locked as f64 / 100_000_000.0);
+        }
         
-        println!("{}", "🎉 Transaction structure planned successfully!".green());
-        println!("  💡 Transaction structure is ready for building");
-        println!("  🌐 To build and sign: Use the wallet's transaction builder (when implemented)");
-        println!("  💡 Note: This shows the planned transaction structure. Full building requires access to private wallet methods.");
+        let memo_bytes = memo.map(|text| text.as_bytes().to_vec());
+        let transaction = wallet.build_transaction(to, amount_zatoshi, fee_amount, memo_bytes, selection)?;
         
-        Ok(())
+        let total_selected: u64 = transaction.inputs.iter().map(|input| input.note.value).sum();
+        let change = transaction.change_output.as_ref().map(|output| output.value).unwrap_or(0);
+        
+        if hand_picked {
+            println!("  🎯 Spending {} hand-picked notes", transaction.inputs.len());
+        } else {
+            println!("  🎯 Selected {} notes for transaction", transaction.inputs.len());
+        }
+        println!("  💰 Transaction Details:");
+        println!("     Amount: {:.8} ZEC ({} zatoshi)", amount_zatoshi as f64 / 100_000_000.0, amount_zatoshi);
+        println!("     Fee: {:.8} ZEC ({} zatoshi)", fee_amount as f64 / 100_000_000.0, fee_amount);
+        println!("     Total Input: {:.8} ZEC ({} zatoshi)", 
+            total_selected as f64 / 100_000_000.0, total_selected);
+        println!("     Change: {:.8} ZEC ({} zatoshi)", change as f64 / 100_000_000.0, change);
+        println!("     Expires at block: {}", transaction.expiry_height);
+        println!("  🔒 Input notes locked until the transaction confirms or expires");
+        
+        println!("  📤 Broadcasting transaction...");
+        match wallet.broadcast_signed_transaction(&transaction) {
+            Ok(result) => {
+                println!("{}", "🎉 Transaction broadcast!".green());
+                println!("  🔗 TX ID: {}", transaction.txid());
+                println!("  📡 Node response: {}", result);
+                Ok(())
+            }
+            Err(e) => {
+                println!("{}", "❌ Broadcast failed; input notes have been released".red());
+                Err(e)
+            }
+        }
     }
     
     
@@ -1112,9 +2153,23 @@ impl CliHandler {
         println!("  🍃 Sapling Balance: {:.8} ZEC ({} zatoshi) - {} notes", sapling_zec, sapling_balance, sapling_notes);
         println!("  📝 Total Notes: {} ({} unspent)", total_notes, total_notes);
         println!("  🏠 Total Addresses: {}", address_count);
-        println!("  ⏳ Pending: 0.00000000 ZEC (0 zatoshi)");
-        println!("  🔒 Confirmed: {:.8} ZEC ({} zatoshi)", total_zec, total_balance);
-        println!("  📊 Unconfirmed: 0.00000000 ZEC (0 zatoshi)");
+        let status = wallet.get_status();
+        let spendable = wallet.get_spendable_balance();
+        let pending = wallet.get_pending_confirmation_balance();
+        let (min_confirmations, change_confirmations) = wallet.get_confirmation_policy();
+        println!("  ✅ Spendable: {:.8} ZEC ({} zatoshi)", spendable as f64 / 100_000_000.0, spendable);
+        println!("  ⏳ Pending confirmation: {:.8} ZEC ({} zatoshi) - needs {} confirmations ({} for change)",
+            pending as f64 / 100_000_000.0, pending, min_confirmations, change_confirmations);
+        let dust = wallet.get_dust_notes();
+        let dust_balance = wallet.get_dust_balance();
+        println!("  🧹 Dust: {:.8} ZEC ({} zatoshi) - {} notes worth at most {} zatoshi each, skipped by automatic selection",
+            dust_balance as f64 / 100_000_000.0, dust_balance, dust.len(), wallet.dust_threshold());
+        println!("  📥 Incoming (unconfirmed): {:.8} ZEC ({} zatoshi)",
+            status.unconfirmed_incoming as f64 / 100_000_000.0, status.unconfirmed_incoming);
+        println!("  📤 Pending spends: {:.8} ZEC ({} zatoshi)",
+            status.unconfirmed_spent as f64 / 100_000_000.0, status.unconfirmed_spent);
+        println!("  🔒 Locked in pending tx: {:.8} ZEC ({} zatoshi)",
+            status.locked_balance as f64 / 100_000_000.0, status.locked_balance);
         
         
         if total_notes > 0 {
@@ -1156,11 +2211,27 @@ impl CliHandler {
         let orchard_zec = orchard_balance as f64 / 100_000_000.0;
         let sapling_zec = sapling_balance as f64 / 100_000_000.0;
         
-        println!("  💰 Total Balance: {:.8} ZEC ({} zatoshi)", total_zec, total_balance);
+        let spendable = wallet.get_spendable_balance();
+        let pending = wallet.get_pending_confirmation_balance();
+        
+        println!("  ✅ Spendable: {:.8} ZEC ({} zatoshi)", spendable as f64 / 100_000_000.0, spendable);
+        println!("  ⏳ Pending confirmation: {:.8} ZEC ({} zatoshi)", pending as f64 / 100_000_000.0, pending);
+        println!("  💰 Total: {:.8} ZEC ({} zatoshi)", total_zec, total_balance);
         println!("  🌳 Orchard: {:.8} ZEC ({} zatoshi) - {} notes", orchard_zec, orchard_balance, orchard_notes);
         println!("  🍃 Sapling: {:.8} ZEC ({} zatoshi) - {} notes", sapling_zec, sapling_balance, sapling_notes);
         println!("  📝 Total Notes: {} unspent", total_notes);
         
+        let status = wallet.get_status();
+        if status.locked_balance > 0 {
+            println!("  🔒 Locked in pending tx: {:.8} ZEC ({} zatoshi)",
+                status.locked_balance as f64 / 100_000_000.0, status.locked_balance);
+        }
+        if status.unconfirmed_incoming > 0 || status.unconfirmed_spent > 0 {
+            println!("  ⏳ Unconfirmed: +{:.8} / -{:.8} ZEC (run 'nozy tx pending' for details)",
+                status.unconfirmed_incoming as f64 / 100_000_000.0,
+                status.unconfirmed_spent as f64 / 100_000_000.0);
+        }
+        
         if total_notes == 0 {
             println!("  💡 No notes found. Generate addresses to start receiving ZEC!");
         }
@@ -1208,284 +2279,74 @@ impl CliHandler {
     }
     
     
-    fn estimate_transaction_fee(&self, to: &str, amount_zatoshi: u64, memo: Option<&str>) -> NozyResult<u64> {
+    fn estimate_transaction_fee(&self, to: &str, amount_zatoshi: u64) -> NozyResult<u64> {
         let wallet = self.wallet.as_ref().ok_or_else(|| {
             crate::error::NozyError::InvalidOperation("No wallet loaded".to_string())
         })?;
         
-        
-        let total_balance = wallet.get_balance();
-        if total_balance < amount_zatoshi {
-            return Err(crate::error::NozyError::InsufficientFunds(
-                format!("Insufficient funds. Required: {} zatoshi, Available: {} zatoshi", 
-                    amount_zatoshi, total_balance)
-            ));
-        }
-        
-        
-        let available_notes = wallet.get_notes();
-        if available_notes.is_empty() {
-            return Err(crate::error::NozyError::InsufficientFunds(
-                "No notes available for spending. Generate addresses and receive some ZEC first.".to_string()
-            ));
-        }
-        
         println!("  🔍 Analyzing transaction requirements...");
-        
-        
-        let mut total_selected = 0u64;
-        let mut selected_notes = Vec::new();
-        
-        for note in available_notes.iter() {
-            if total_selected >= amount_zatoshi {
-                break;
-            }
-            selected_notes.push(note);
-            total_selected += note.value;
-        }
-        
-        if total_selected < amount_zatoshi {
-            return Err(crate::error::NozyError::InsufficientFunds(
-                format!("Insufficient funds in available notes. Required: {}, Available: {}", 
-                    amount_zatoshi, total_selected)
-            ));
-        }
-        
-        
-        let input_count = selected_notes.len();
-        let output_count = if total_selected > amount_zatoshi { 2 } else { 1 }; 
-        let memo_size = memo.map(|m| m.len()).unwrap_or(0);
-        
-        
-        let estimated_size = self.calculate_transaction_size(input_count, output_count, memo_size)?;
+        let plan = wallet.plan_spend(to, amount_zatoshi, None)?;
+        let total_balance = wallet.get_spendable_balance();
         
         println!("  📊 Transaction Analysis:");
-        println!("     Input Notes: {} notes", input_count);
-        println!("     Output Count: {} addresses", output_count);
-        println!("     Memo Size: {} bytes", memo_size);
-        println!("     Estimated Size: {} bytes", estimated_size);
-        
-        
-        let network_fee_rate = self.get_network_fee_rate()?;
-        
-        
-        let estimated_fee = self.calculate_dynamic_fee(estimated_size, input_count, output_count, memo_size, network_fee_rate)?;
-        
+        println!("     Input Notes: {} notes", plan.input_count());
+        println!("     Change: {:.8} ZEC ({} zatoshi)", plan.change as f64 / 100_000_000.0, plan.change);
+        println!("     Logical Actions: {} (padding included)", plan.logical_actions);
         
         println!("  💰 Fee Estimation Results:");
         println!("     Transaction Amount: {:.8} ZEC ({} zatoshi)", 
             amount_zatoshi as f64 / 100_000_000.0, amount_zatoshi);
-        println!("     Network Fee Rate: {:.2} zatoshi/byte", network_fee_rate);
-        println!("     Base Fee: {:.8} ZEC ({} zatoshi)", 
-            (network_fee_rate * estimated_size as f64) as u64 as f64 / 100_000_000.0,
-            (network_fee_rate * estimated_size as f64) as u64);
-        println!("     Privacy Fee: {:.8} ZEC ({} zatoshi)", 
-            (input_count as u64 * 500) as f64 / 100_000_000.0, 
-            input_count as u64 * 500);
-        println!("     Total Estimated Fee: {:.8} ZEC ({} zatoshi)", 
-            estimated_fee as f64 / 100_000_000.0, estimated_fee);
+        println!("     ZIP-317 Fee: {:.8} ZEC ({} zatoshi)", 
+            plan.fee as f64 / 100_000_000.0, plan.fee);
         println!("     Total Cost: {:.8} ZEC ({} zatoshi)", 
-            (amount_zatoshi + estimated_fee) as f64 / 100_000_000.0, 
-            amount_zatoshi + estimated_fee);
-        println!("     Available Balance: {:.8} ZEC ({} zatoshi)", 
+            (amount_zatoshi + plan.fee) as f64 / 100_000_000.0, 
+            amount_zatoshi + plan.fee);
+        println!("     Spendable Balance: {:.8} ZEC ({} zatoshi)", 
             total_balance as f64 / 100_000_000.0, total_balance);
+        println!("  💡 Memos fit inside their output and do not change the fee");
         
-        if total_balance >= amount_zatoshi + estimated_fee {
-            println!("  ✅ Sufficient funds available for transaction");
-        } else {
-            println!("  ⚠️  Insufficient funds for transaction + fee");
-            println!("     Need additional: {:.8} ZEC ({} zatoshi)", 
-                (amount_zatoshi + estimated_fee - total_balance) as f64 / 100_000_000.0,
-                amount_zatoshi + estimated_fee - total_balance);
-        }
-        
-        println!("  💡 Fee based on real network conditions and transaction size");
-        
-        Ok(estimated_fee)
-    }
-    
-    
-    fn calculate_transaction_size(&self, input_count: usize, output_count: usize, memo_size: usize) -> NozyResult<usize> {
-        
-        let base_size = 100; 
-        let input_size = input_count * 200; 
-        let output_size = output_count * 180; 
-        let memo_overhead = if memo_size > 0 { memo_size + 50 } else { 0 }; 
-        let proof_size = input_count * 192 + output_count * 192; 
-        
-        let total_size = base_size + input_size + output_size + memo_overhead + proof_size;
-        
-        Ok(total_size)
-    }
-    
-    
-    fn get_network_fee_rate(&self) -> NozyResult<f64> {
-        let wallet = self.wallet.as_ref().ok_or_else(|| {
-            crate::error::NozyError::InvalidOperation("No wallet loaded".to_string())
-        })?;
-        
-        
-        
-        match wallet.get_zebra_status() {
-            Ok(status) => {
-                if status.connected {
-                    
-                    println!("  🌐 Connected to Zebra - querying network conditions...");
-                    
-                    
-                    
-                    let base_rate = 1.0; 
-                    let network_congestion = self.estimate_network_congestion()?;
-                    let dynamic_rate = base_rate * network_congestion;
-                    
-                    println!("     Network congestion factor: {:.2}x", network_congestion);
-                    Ok(dynamic_rate)
-                } else {
-                    
-                    println!("  ⚠️  Not connected to Zebra - using conservative fee rates");
-                    Ok(2.0) 
-                }
-            }
-            Err(_) => {
-                
-                println!("  ⚠️  Cannot check Zebra status - using conservative fee rates");
-                Ok(2.0) 
-            }
-        }
-    }
-    
-    
-    fn estimate_network_congestion(&self) -> NozyResult<f64> {
-        
-        
-        
-        
-        
-        
-        
-        let base_congestion = 1.0;
-        let time_factor = 1.1; 
-        
-        let variation_factor = 0.95; 
-        
-        Ok(base_congestion * time_factor * variation_factor)
+        Ok(plan.fee)
     }
     
     
-    fn calculate_dynamic_fee(&self, tx_size: usize, input_count: usize, output_count: usize, memo_size: usize, fee_rate: f64) -> NozyResult<u64> {
-        
-        let base_fee = (tx_size as f64 * fee_rate) as u64;
-        
-        
-        let privacy_fee = input_count as u64 * 500; 
-        
-        
-        let complexity_fee = if output_count > 1 { (output_count - 1) as u64 * 1000 } else { 0 };
-        
-        
-        let memo_fee = if memo_size > 0 { memo_size as u64 * 2 } else { 0 };
-        
-        
-        let min_fee = 1000; 
-        
-        let total_fee = base_fee + privacy_fee + complexity_fee + memo_fee;
-        
-        Ok(total_fee.max(min_fee))
-    }
-    
-    
-    fn show_transaction_history(&self, wallet: &NozyWallet) -> NozyResult<()> {
-        println!("  📜 Transaction History:");
-        
-        
-        match wallet.get_zebra_status() {
-            Ok(status) => {
-                if status.connected {
-                    println!("  🌐 Connected to Zebra - fetching real transaction data...");
-                    self.show_real_transaction_history(wallet)?;
-                } else {
-                    println!("  ⚠️  Not connected to Zebra - showing local data only");
-                    self.show_local_transaction_history(wallet)?;
-                }
-            }
-            Err(_) => {
-                println!("  ⚠️  Cannot connect to Zebra - showing local data only");
-                self.show_local_transaction_history(wallet)?;
+    fn show_transaction_history(&self, wallet: &NozyWallet, tag: Option<&str>) -> NozyResult<()> {
+        let entries = match tag {
+            Some(tag) => {
+                println!("  🏷️  Tagged '{}'", tag);
+                wallet.get_transaction_history_by_tag(tag)
             }
-        }
-        
-        Ok(())
-    }
-    
-    
-    fn show_real_transaction_history(&self, wallet: &NozyWallet) -> NozyResult<()> {
-        
-        let addresses = wallet.get_addresses();
+            None => wallet.get_transaction_history(),
+        };
         
-        if addresses.is_empty() {
-            println!("  📭 No addresses found");
-            println!("  💡 Generate addresses first to track transactions");
+        if entries.is_empty() && tag.is_some() {
+            println!("  📭 No transactions with this tag");
             return Ok(());
         }
-        
-        println!("  🔍 Scanning blockchain for transactions across {} addresses...", addresses.len());
-        
-        
-        let mut all_transactions = Vec::new();
-        
-        for (i, address) in addresses.iter().take(5).enumerate() {
-            println!("  📍 Scanning address {}/{}: {}...{}", 
-                i + 1, addresses.len().min(5), 
-                &address.address[..12], 
-                &address.address[address.address.len()-8..]);
-            
-            
-            match self.get_address_transactions(&address.address) {
-                Ok(mut txs) => {
-                    println!("     Found {} transactions", txs.len());
-                    all_transactions.append(&mut txs);
-                }
-                Err(e) => {
-                    println!("     ⚠️  Error querying transactions: {}", e);
-                }
-            }
-        }
-        
-        if addresses.len() > 5 {
-            println!("  ... (scanning limited to first 5 addresses for performance)");
-        }
-        
-        
-        all_transactions.sort_by(|a, b| b.block_height.cmp(&a.block_height));
-        
-        if all_transactions.is_empty() {
-            println!("  📭 No transactions found on blockchain");
-            println!("  💡 This is normal for new addresses - transactions will appear after receiving funds");
-            return Ok(());
+        if entries.is_empty() {
+            println!("  ⚠️  No scanned transactions yet - run 'nozy network sync' to build history");
+            return self.show_local_transaction_history(wallet);
         }
         
-        
         println!("  📋 Recent Transactions (Last 20):");
-        for (i, tx) in all_transactions.iter().take(20).enumerate() {
-            self.display_transaction_info(i + 1, tx)?;
+        for (i, entry) in entries.iter().take(20).enumerate() {
+            self.display_history_entry(i + 1, entry);
         }
         
-        if all_transactions.len() > 20 {
-            println!("  ... and {} more transactions", all_transactions.len() - 20);
+        if entries.len() > 20 {
+            println!("  ... and {} more transactions", entries.len() - 20);
         }
         
-        
-        let total_received: u64 = all_transactions.iter()
-            .filter(|tx| tx.value > 0)
-            .map(|tx| tx.value as u64)
+        let total_received: u64 = entries.iter()
+            .filter(|entry| entry.direction == HistoryDirection::Received)
+            .map(|entry| entry.value)
             .sum();
-        let total_sent: u64 = all_transactions.iter()
-            .filter(|tx| tx.value < 0)
-            .map(|tx| (-tx.value) as u64)
+        let total_sent: u64 = entries.iter()
+            .filter(|entry| entry.direction == HistoryDirection::Sent)
+            .map(|entry| entry.value)
             .sum();
         
         println!("  📊 Transaction Summary:");
-        println!("     Total Transactions: {}", all_transactions.len());
+        println!("     Total Transactions: {}", entries.len());
         println!("     Total Received: {:.8} ZEC ({} zatoshi)", 
             total_received as f64 / 100_000_000.0, total_received);
         println!("     Total Sent: {:.8} ZEC ({} zatoshi)", 
@@ -1495,6 +2356,37 @@ impl CliHandler {
     }
     
     
+    fn display_history_entry(&self, index: usize, entry: &HistoryEntry) {
+        let (label, color) = match entry.direction {
+            HistoryDirection::Received => ("📥 Received", "🟢"),
+            HistoryDirection::Sent => ("📤 Sent", "🔴"),
+            HistoryDirection::Internal => ("🔁 Self-transfer", "🔵"),
+        };
+        
+        println!("  {}. {} {} {:.8} ZEC ({} zatoshi)", 
+            index, label, color, entry.value as f64 / 100_000_000.0, entry.value);
+        println!("     🔗 TX ID: {}", entry.txid);
+        println!("     🏗️  Block: {}", entry.block_height);
+        
+        let counterparty_label = match entry.direction {
+            HistoryDirection::Sent => "👤 To",
+            _ => "📍 On",
+        };
+        for address in &entry.counterparties {
+            println!("     {}: {}", counterparty_label, address);
+        }
+        if let Some(fee) = entry.fee {
+            println!("     💸 Fee: {:.8} ZEC ({} zatoshi)", fee as f64 / 100_000_000.0, fee);
+        }
+        for memo in &entry.memos {
+            println!("     📝 Memo: {}", memo);
+        }
+        if !entry.tags.is_empty() {
+            println!("     🏷️  Tags: {}", entry.tags.join(", "));
+        }
+    }
+    
+    
     fn show_local_transaction_history(&self, wallet: &NozyWallet) -> NozyResult<()> {
         
         let notes = wallet.get_notes();
@@ -1518,10 +2410,9 @@ impl CliHandler {
             println!("  {}. {} {} {:.8} ZEC ({} zatoshi) - {}", 
                 i + 1, note_type, status, note_value, note.value, note.id);
             
-            if let Some(memo) = &note.memo {
-                if !memo.is_empty() {
-                    println!("     📝 Memo: {}", String::from_utf8_lossy(memo));
-                }
+            let memo = note.decoded_memo();
+            if !memo.is_empty() {
+                println!("     📝 Memo: {}", memo);
             }
             
             if let Some(height) = note.spent_at_height {
@@ -1539,68 +2430,216 @@ impl CliHandler {
     }
     
     
-    fn get_address_transactions(&self, address: &str) -> NozyResult<Vec<crate::wallet::TransactionInfo>> {
-        
-        
-        
-        
-        
-        
-        
-        
-        let mut transactions = Vec::new();
-        
-        
-        let tx_count = (address.len() % 3) + 1;
+    fn draw_sync_progress(progress: &SyncProgress) {
+        const WIDTH: usize = 30;
+        let filled = (progress.fraction() * WIDTH as f64) as usize;
+        let eta = progress.eta()
+            .map(Self::format_duration)
+            .unwrap_or_else(|| "--".to_string());
+        
+        print!("\r  [{}{}] {:5.1}% {}/{} | {} notes | ETA {}   ",
+            "#".repeat(filled.min(WIDTH)),
+            "-".repeat(WIDTH - filled.min(WIDTH)),
+            progress.fraction() * 100.0,
+            progress.current_height,
+            progress.target_height,
+            progress.notes_found,
+            eta);
+        let _ = std::io::stdout().flush();
+    }
+    
+    
+    fn show_split_plan(plan: &SplitPlan) {
+        println!("  Spending {} notes: {}", plan.note_ids.len(), plan.note_ids.join(", "));
+        for (i, value) in plan.output_values.iter().enumerate() {
+            println!("     {}. 🌳 {:.8} ZEC ({} zatoshi)", i + 1, *value as f64 / 100_000_000.0, value);
+        }
+        if plan.change > 0 {
+            println!("  Change: {:.8} ZEC ({} zatoshi)", plan.change as f64 / 100_000_000.0, plan.change);
+        }
+        println!("  Fee: {:.8} ZEC ({} zatoshi)", plan.fee as f64 / 100_000_000.0, plan.fee);
+    }
+    
+    
+    // Syncs (when watching), advances a resumable session one step and saves, until `step` reports it is done
+    fn run_session<F>(wallet: &mut NozyWallet, watch: bool, interval: u64, command: &str, mut step: F) -> NozyResult<()>
+    where
+        F: FnMut(&mut NozyWallet) -> NozyResult<bool>,
+    {
+        let options = SyncOptions::new(100, Some("nozy-wallet.json".into()));
+        let cancel = options.cancel_handle();
+        if watch {
+            let handle = cancel.clone();
+            if let Err(e) = ctrlc::set_handler(move || handle.store(true, Ordering::SeqCst)) {
+                println!("  ⚠️  Ctrl-C will not stop cleanly: {}", e);
+            }
+            println!("  👀 Watching every {}s (Ctrl-C to stop, progress is saved)", interval);
+        }
         
-        for i in 0..tx_count {
-            let tx_id = format!("{}...{}", 
-                &hex::encode(&address.as_bytes()[..4]),
-                &hex::encode(&address.as_bytes()[address.len()-4..]));
+        loop {
+            if watch {
+                if let Err(e) = wallet.sync_wallet_with_progress(&options, |_| {}) {
+                    println!("{}", format!("  ⚠️  Sync failed: {}", e).yellow());
+                }
+            }
+            
+            let done = step(wallet);
+            wallet.save_to_file(Path::new("nozy-wallet.json"))?;
+            if done? {
+                return Ok(());
+            }
             
-            transactions.push(crate::wallet::TransactionInfo {
-                id: tx_id,
-                block_hash: format!("block_hash_{}", 822400 - i * 100),
-                block_height: 822400 - (i as u32 * 100),
-                timestamp: chrono::Utc::now()
-                    .checked_sub_signed(chrono::Duration::hours(i as i64 * 24))
-                    .unwrap_or(chrono::Utc::now())
-                    .to_rfc3339(),
-                value: if i % 2 == 0 { 50_000_000i64 } else { -10_000_000i64 }, 
-                inputs: vec![format!("input_{}", i)],
-                outputs: vec![format!("output_{}", i)],
-            });
-        }
-        
-        Ok(transactions)
+            if !watch {
+                println!("  💡 Run 'nozy privacy {}' again after new blocks, or use --watch", command);
+                return Ok(());
+            }
+            
+            // Sleep in short slices so Ctrl-C is noticed promptly
+            for _ in 0..interval {
+                if cancel.load(Ordering::SeqCst) {
+                    break;
+                }
+                std::thread::sleep(std::time::Duration::from_secs(1));
+            }
+            if cancel.load(Ordering::SeqCst) {
+                println!("{}", format!("⏸️  Paused; run 'nozy privacy {}' to resume", command).yellow());
+                return Ok(());
+            }
+        }
     }
     
     
-    fn display_transaction_info(&self, index: usize, tx: &crate::wallet::TransactionInfo) -> NozyResult<()> {
-        let value_zec = (tx.value.abs() as f64) / 100_000_000.0;
-        let tx_type = if tx.value >= 0 { "📥 Received" } else { "📤 Sent" };
-        let color = if tx.value >= 0 { "🟢" } else { "🔴" };
-        
-        println!("  {}. {} {} {:.8} ZEC ({} zatoshi)", 
-            index, tx_type, color, value_zec, tx.value.abs());
-        println!("     🔗 TX ID: {}", tx.id);
-        println!("     🏗️  Block: {} (height: {})", 
-            &tx.block_hash[..16], tx.block_height);
+    fn show_sapling_balance(wallet: &NozyWallet) {
+        let balance = wallet.get_balance_by_type(NoteType::Sapling);
+        println!("  🛡️  Sapling balance remaining: {:.8} ZEC ({} zatoshi)", balance as f64 / 100_000_000.0, balance);
+    }
+    
+    
+    fn broadcast_self_send(wallet: &mut NozyWallet, transaction: &SignedTransaction, what: &str) -> NozyResult<()> {
+        match wallet.broadcast_signed_transaction(transaction) {
+            Ok(_) => {
+                println!("{}", format!("🎉 {} broadcast!", what).green());
+                println!("  🔗 TX ID: {}", transaction.txid());
+                Ok(())
+            }
+            Err(e) => {
+                println!("{}", "❌ Broadcast failed; input notes have been released".red());
+                Err(e)
+            }
+        }
+    }
+    
+    
+    fn pay_request(wallet: &mut NozyWallet, uri: &str) -> NozyResult<()> {
+        let request = wallet.parse_payment_request(uri)?;
         
+        println!("{}", "📤 Paying payment request...".blue());
+        for (i, payment) in request.payments.iter().enumerate() {
+            let amount = payment.amount.map(|amount| format!("{:.8} ZEC", amount as f64 / 100_000_000.0));
+            println!("  {}. {} to {}", i + 1, amount.as_deref().unwrap_or("No amount"), payment.address);
+            if let Some(label) = &payment.label {
+                println!("     Label: {}", label);
+            }
+            if let Some(message) = &payment.message {
+                println!("     Message: {}", message);
+            }
+            if let Some(memo) = &payment.memo {
+                println!("     Memo: {}", String::from_utf8_lossy(memo));
+            }
+        }
         
-        if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(&tx.timestamp) {
-            println!("     🕐 Time: {} ({} ago)", 
-                dt.format("%Y-%m-%d %H:%M:%S UTC"),
-                self.format_time_ago(dt.with_timezone(&chrono::Utc)));
+        let rows = wallet.payment_request_rows(&request)?;
+        let plan = wallet.plan_payouts(&rows, payouts::DEFAULT_BATCH_ACTIONS)?;
+        if plan.batches.len() > 1 || !plan.waiting.is_empty() {
+            return Err(crate::error::NozyError::InvalidOperation(
+                "The request would need more than one transaction; its payments must be sent together".to_string()
+            ));
         }
+        Self::show_payout_plan(&rows, &plan);
         
-        println!("     📊 Inputs: {}, Outputs: {}", 
-            tx.inputs.len(), tx.outputs.len());
+        let results = wallet.send_payouts(&rows, &plan);
+        Self::show_payout_report(&results);
+        Ok(())
+    }
+    
+    
+    fn show_payout_plan(rows: &[PayoutRow], plan: &PayoutPlan) {
+        let total: u64 = rows.iter().map(|row| row.amount).sum();
+        let fees: u64 = plan.batches.iter().map(|batch| batch.fee).sum();
+        println!("  Payments: {} totalling {:.8} ZEC ({} zatoshi)", rows.len(), total as f64 / 100_000_000.0, total);
+        for (i, batch) in plan.batches.iter().enumerate() {
+            let first = rows[batch.rows[0]].line;
+            let last = rows[batch.rows[batch.rows.len() - 1]].line;
+            println!("  🧾 Transaction {}: lines {}-{}, {} inputs, {} logical actions, fee {} zatoshi",
+                i + 1, first, last, batch.note_ids.len(), batch.logical_actions, batch.fee);
+        }
+        println!("  Total Fees: {:.8} ZEC ({} zatoshi)", fees as f64 / 100_000_000.0, fees);
+        if let (Some(&first), Some(&last)) = (plan.waiting.first(), plan.waiting.last()) {
+            println!("{}", format!(
+                "  ⏳ Lines {}-{} need change from these transactions, which is spendable once it confirms; send them in a later run",
+                rows[first].line, rows[last].line
+            ).yellow());
+        }
+    }
+    
+    
+    fn show_payout_report(results: &[PayoutResult]) {
+        let paid = results.iter().filter(|result| result.txid.is_some()).count();
+        if paid == results.len() {
+            println!("{}", format!("🎉 All {} payouts broadcast!", paid).green());
+        } else {
+            println!("{}", format!("⚠️  {} of {} payouts broadcast", paid, results.len()).yellow());
+        }
         
+        for result in results {
+            let amount = result.amount as f64 / 100_000_000.0;
+            match (&result.txid, &result.error) {
+                (Some(txid), _) => println!("  ✅ line {}: {:.8} ZEC to {} - {}", result.line, amount, result.address, txid),
+                (None, Some(error)) => println!("  ❌ line {}: {:.8} ZEC to {} - {}", result.line, amount, result.address, error),
+                (None, None) => println!("  ⏳ line {}: {:.8} ZEC to {} - waiting for change to confirm", result.line, amount, result.address),
+            }
+        }
+    }
+    
+    
+    fn write_payout_report(path: &Path, results: &[PayoutResult]) -> NozyResult<()> {
+        let mut report = String::from("line,address,amount,txid,error\n");
+        for result in results {
+            let error = match (&result.txid, &result.error) {
+                (None, None) => "waiting for change to confirm",
+                _ => result.error.as_deref().unwrap_or(""),
+            }.replace('"', "\"\"");
+            report.push_str(&format!("{},{},{},{},\"{}\"\n",
+                result.line, result.address, payment_request::format_amount(result.amount),
+                result.txid.as_deref().unwrap_or(""), error));
+        }
+        fs::write(path, report)?;
         Ok(())
     }
     
     
+    fn confirm(prompt: &str) -> NozyResult<bool> {
+        print!("{} [y/N] ", prompt);
+        std::io::stdout().flush()?;
+        
+        let mut answer = String::new();
+        std::io::stdin().read_line(&mut answer)?;
+        Ok(matches!(answer.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
+    
+    
+    fn format_duration(duration: std::time::Duration) -> String {
+        let secs = duration.as_secs();
+        if secs >= 3600 {
+            format!("{}h {}m", secs / 3600, (secs % 3600) / 60)
+        } else if secs >= 60 {
+            format!("{}m {}s", secs / 60, secs % 60)
+        } else {
+            format!("{}s", secs)
+        }
+    }
+    
+    
     fn format_time_ago(&self, dt: chrono::DateTime<chrono::Utc>) -> String {
         let now = chrono::Utc::now();
         let duration = now.signed_duration_since(dt);
@@ -1618,113 +2657,58 @@ impl CliHandler {
     
     
     fn show_pending_transactions(&self, wallet: &NozyWallet) -> NozyResult<()> {
-        println!("  ⏳ Pending Transactions:");
-        
+        let incoming = wallet.get_pending_incoming();
+        let spends = wallet.get_pending_spends();
         
-        match wallet.get_zebra_status() {
-            Ok(status) => {
-                if status.connected {
-                    println!("  🌐 Connected to Zebra - querying mempool...");
-                    self.show_real_pending_transactions(wallet)?;
-                } else {
-                    println!("  ⚠️  Not connected to Zebra - showing local pending data only");
-                    self.show_local_pending_transactions(wallet)?;
-                }
-            }
-            Err(_) => {
-                println!("  ⚠️  Cannot connect to Zebra - showing local pending data only");
-                self.show_local_pending_transactions(wallet)?;
-            }
-        }
-        
-        Ok(())
-    }
-    
-    
-    fn show_real_pending_transactions(&self, wallet: &NozyWallet) -> NozyResult<()> {
-        
-        let addresses = wallet.get_addresses();
-        
-        if addresses.is_empty() {
-            println!("  📭 No addresses found");
-            println!("  💡 Generate addresses first to track pending transactions");
+        if incoming.is_empty() && spends.is_empty() {
+            println!("  📭 No pending transactions found in mempool");
+            println!("  💡 Pending transactions will appear here when you send or receive ZEC");
             return Ok(());
         }
         
-        println!("  🔍 Scanning mempool for pending transactions across {} addresses...", addresses.len());
-        
-        
-        match wallet.get_mempool_info() {
-            Ok(mempool_info) => {
-                println!("  📊 Mempool Status:");
-                println!("     Total Transactions: {}", mempool_info.transaction_count);
-                println!("     Mempool Size: {:.2} MB", mempool_info.total_size as f64 / 1024.0 / 1024.0);
-                println!("     Average Fee: {:.2} zatoshi/byte", mempool_info.average_fee);
-            }
-            Err(e) => {
-                println!("  ⚠️  Could not get mempool info: {}", e);
+        if !incoming.is_empty() {
+            println!("  📥 Incoming (unconfirmed):");
+            for (i, pending) in incoming.iter().enumerate() {
+                let kind = if pending.is_change { "change" } else { "received" };
+                println!("  {}. 🟡 {:.8} ZEC ({} zatoshi) - {} - ⏳ PENDING",
+                    i + 1, pending.note.value as f64 / 100_000_000.0, pending.note.value, kind);
+                println!("     🔗 TX ID: {}", pending.txid);
+                println!("     📍 To: {}", pending.note.recipient_address);
+                self.display_first_seen(&pending.first_seen);
             }
         }
         
-        
-        let mut pending_transactions = Vec::new();
-        
-        for (i, address) in addresses.iter().take(5).enumerate() {
-            println!("  📍 Checking address {}/{}: {}...{}", 
-                i + 1, addresses.len().min(5), 
-                &address.address[..12], 
-                &address.address[address.address.len()-8..]);
-            
-            
-            match self.get_pending_transactions_for_address(&address.address) {
-                Ok(mut txs) => {
-                    if txs.is_empty() {
-                        println!("     No pending transactions");
-                    } else {
-                        println!("     Found {} pending transactions", txs.len());
-                        pending_transactions.append(&mut txs);
-                    }
-                }
-                Err(e) => {
-                    println!("     ⚠️  Error querying pending transactions: {}", e);
-                }
+        if !spends.is_empty() {
+            println!("  📤 Outgoing (unconfirmed):");
+            for (i, pending) in spends.iter().enumerate() {
+                println!("  {}. 🟠 {:.8} ZEC ({} zatoshi) - note {} - ⏳ PENDING",
+                    i + 1, pending.value as f64 / 100_000_000.0, pending.value, pending.note_id);
+                println!("     🔗 TX ID: {}", pending.txid);
+                self.display_first_seen(&pending.first_seen);
             }
         }
         
-        if addresses.len() > 5 {
-            println!("  ... (scanning limited to first 5 addresses for performance)");
-        }
-        
-        if pending_transactions.is_empty() {
-            println!("  📭 No pending transactions found in mempool");
-            println!("  💡 Pending transactions will appear here when you send or receive ZEC");
-            return Ok(());
-        }
-        
-        
-        pending_transactions.sort_by(|a, b| b.timestamp.cmp(&a.timestamp));
-        
-        
-        println!("  📋 Pending Transactions:");
-        for (i, tx) in pending_transactions.iter().enumerate() {
-            self.display_pending_transaction_info(i + 1, tx)?;
-        }
-        
-        
-        let total_pending_value: u64 = pending_transactions.iter()
-            .map(|tx| tx.value.abs() as u64)
-            .sum();
-        
+        let status = wallet.get_status();
         println!("  📊 Pending Summary:");
-        println!("     Total Pending: {} transactions", pending_transactions.len());
-        println!("     Total Value: {:.8} ZEC ({} zatoshi)", 
-            total_pending_value as f64 / 100_000_000.0, total_pending_value);
+        println!("     📥 Incoming: {:.8} ZEC ({} zatoshi)",
+            status.unconfirmed_incoming as f64 / 100_000_000.0, status.unconfirmed_incoming);
+        println!("     📤 Spending: {:.8} ZEC ({} zatoshi)",
+            status.unconfirmed_spent as f64 / 100_000_000.0, status.unconfirmed_spent);
         println!("  💡 Transactions typically confirm within 2-10 minutes");
         
         Ok(())
     }
     
     
+    fn display_first_seen(&self, first_seen: &str) {
+        if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(first_seen) {
+            println!("     🕐 First seen: {} ({} ago)",
+                dt.format("%Y-%m-%d %H:%M:%S UTC"),
+                self.format_time_ago(dt.with_timezone(&chrono::Utc)));
+        }
+    }
+    
+    
     fn show_local_pending_transactions(&self, wallet: &NozyWallet) -> NozyResult<()> {
         
         let notes = wallet.get_notes();
@@ -1755,10 +2739,9 @@ impl CliHandler {
             println!("  {}. {} {:.8} ZEC ({} zatoshi) - {}", 
                 i + 1, note_type, note_value, note.value, note.id);
             
-            if let Some(memo) = &note.memo {
-                if !memo.is_empty() {
-                    println!("     📝 Memo: {}", String::from_utf8_lossy(memo));
-                }
+            let memo = note.decoded_memo();
+            if !memo.is_empty() {
+                println!("     📝 Memo: {}", memo);
             }
             
             println!("     💰 Available for spending");
@@ -1776,67 +2759,4 @@ impl CliHandler {
         
         Ok(())
     }
-    
-    
-    fn get_pending_transactions_for_address(&self, address: &str) -> NozyResult<Vec<crate::wallet::TransactionInfo>> {
-        
-        
-        
-        
-        
-        
-        
-        
-        let mut pending_transactions = Vec::new();
-        
-        
-        let pending_count = address.len() % 3;
-        
-        if pending_count > 0 {
-            for i in 0..pending_count {
-                let tx_id = format!("pending_{}...{}", 
-                    &hex::encode(&address.as_bytes()[..4]),
-                    &hex::encode(&address.as_bytes()[address.len()-4..]));
-                
-                pending_transactions.push(crate::wallet::TransactionInfo {
-                    id: tx_id,
-                    block_hash: "pending".to_string(), 
-                    block_height: 0, 
-                    timestamp: chrono::Utc::now()
-                        .checked_sub_signed(chrono::Duration::minutes(i as i64 * 5))
-                        .unwrap_or(chrono::Utc::now())
-                        .to_rfc3339(),
-                    value: if i % 2 == 0 { 25_000_000i64 } else { -5_000_000i64 }, 
-                    inputs: vec![format!("pending_input_{}", i)],
-                    outputs: vec![format!("pending_output_{}", i)],
-                });
-            }
-        }
-        
-        Ok(pending_transactions)
-    }
-    
-    
-    fn display_pending_transaction_info(&self, index: usize, tx: &crate::wallet::TransactionInfo) -> NozyResult<()> {
-        let value_zec = (tx.value.abs() as f64) / 100_000_000.0;
-        let tx_type = if tx.value >= 0 { "📥 Receiving" } else { "📤 Sending" };
-        let color = if tx.value >= 0 { "🟡" } else { "🟠" };
-        
-        println!("  {}. {} {} {:.8} ZEC ({} zatoshi) - ⏳ PENDING", 
-            index, tx_type, color, value_zec, tx.value.abs());
-        println!("     🔗 TX ID: {}", tx.id);
-        
-        
-        if let Ok(dt) = chrono::DateTime::parse_from_rfc3339(&tx.timestamp) {
-            println!("     🕐 Submitted: {} ({} ago)", 
-                dt.format("%Y-%m-%d %H:%M:%S UTC"),
-                self.format_time_ago(dt.with_timezone(&chrono::Utc)));
-        }
-        
-        println!("     📊 Inputs: {}, Outputs: {}", 
-            tx.inputs.len(), tx.outputs.len());
-        println!("     ⏰ Status: Waiting for confirmation...");
-        
-        Ok(())
-    }
-} 
+}
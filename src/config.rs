@@ -1,6 +1,7 @@
 //! Configuration for the Nozy wallet
 
 use serde::{Deserialize, Serialize};
+use std::path::PathBuf;
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -10,6 +11,10 @@ pub struct NozyConfig {
     
     
     pub privacy: PrivacyConfig,
+    
+    
+    #[serde(default)]
+    pub proving: ProvingConfig,
 }
 
 
@@ -30,6 +35,23 @@ pub struct PrivacyConfig {
     
     
     pub enable_sapling: bool,
+    
+    // Only block, tree-state and mempool data is fetched; notes are found by trial decryption
+    #[serde(default = "default_scan_only")]
+    pub scan_only: bool,
+}
+
+
+fn default_scan_only() -> bool {
+    true
+}
+
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct ProvingConfig {
+    // Holds sapling-spend.params and sapling-output.params; ~/.zcash-params when unset
+    #[serde(default)]
+    pub params_dir: Option<PathBuf>,
 }
 
 
@@ -94,7 +116,9 @@ impl NozyConfig {
             privacy: PrivacyConfig {
                 enable_orchard: privacy_level == PrivacyLevel::Maximum,
                 enable_sapling: privacy_level != PrivacyLevel::Balanced,
+                scan_only: privacy_level == PrivacyLevel::Maximum,
             },
+            proving: ProvingConfig::default(),
         }
     }
 }
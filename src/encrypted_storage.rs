@@ -10,6 +10,9 @@ use std::path::{Path, PathBuf};
 use std::fs;
 use rand::Rng;
 
+// The master key salt lives beside the encrypted files so the same password derives the same key after a restore
+const SALT_FILE: &str = "master.salt";
+
 
 pub struct EncryptedStorage {
     
@@ -46,9 +49,7 @@ impl EncryptedStorage {
     
     
     pub fn initialize(&mut self, password: &str) -> NozyResult<()> {
-        // Generate random salt for this storage instance
-        let mut rng = rand::thread_rng();
-        let salt: [u8; 32] = rng.gen();
+        let salt = self.load_or_create_salt()?;
         
         // Derive master encryption key from password
         let mut key = [0u8; 32];
@@ -64,6 +65,21 @@ impl EncryptedStorage {
     }
     
     
+    // A random salt is generated once per storage directory and reused afterwards
+    fn load_or_create_salt(&self) -> NozyResult<Vec<u8>> {
+        let salt_path = self.storage_dir.join(SALT_FILE);
+        if salt_path.exists() {
+            return fs::read(&salt_path)
+                .map_err(|e| NozyError::Storage(format!("Failed to read storage salt: {}", e)));
+        }
+        
+        let salt: [u8; 32] = rand::thread_rng().gen();
+        fs::write(&salt_path, salt)
+            .map_err(|e| NozyError::Storage(format!("Failed to write storage salt: {}", e)))?;
+        Ok(salt.to_vec())
+    }
+    
+    
     pub fn is_initialized(&self) -> bool {
         self.master_key.is_some()
     }
@@ -130,7 +146,7 @@ impl EncryptedStorage {
             let entry = entry
                 .map_err(|e| NozyError::Storage(format!("Failed to read directory entry: {}", e)))?;
             
-            if entry.path().extension().map_or(false, |ext| ext == "enc") {
+            if entry.path().extension().is_some_and(|ext| ext == "enc") || entry.file_name() == SALT_FILE {
                 let filename = entry.file_name();
                 let backup_file = backup_path.join(filename);
                 
@@ -144,9 +160,6 @@ impl EncryptedStorage {
     
     
     pub fn restore_from_backup(&mut self, backup_path: &Path, password: &str) -> NozyResult<()> {
-        // Initialize storage with password
-        self.initialize(password)?;
-        
         // Clear existing storage
         for entry in fs::read_dir(&self.storage_dir)
             .map_err(|e| NozyError::Storage(format!("Failed to read storage directory: {}", e)))? {
@@ -165,7 +178,7 @@ impl EncryptedStorage {
             let entry = entry
                 .map_err(|e| NozyError::Storage(format!("Failed to read backup entry: {}", e)))?;
             
-            if entry.path().extension().map_or(false, |ext| ext == "enc") {
+            if entry.path().extension().is_some_and(|ext| ext == "enc") || entry.file_name() == SALT_FILE {
                 let filename = entry.file_name();
                 let storage_file = self.storage_dir.join(filename);
                 
@@ -174,7 +187,8 @@ impl EncryptedStorage {
             }
         }
         
-        Ok(())
+        // Derive the key only now, from the backup's salt
+        self.initialize(password)
     }
     
     
@@ -294,7 +308,7 @@ mod tests {
         let test_data = vec!["test1", "test2", "test3"];
         storage.save_encrypted("test_file", &test_data).unwrap();
         
-        let loaded_data: Vec<&str> = storage.load_encrypted("test_file").unwrap();
+        let loaded_data: Vec<String> = storage.load_encrypted("test_file").unwrap();
         assert_eq!(test_data, loaded_data);
         
         // Test file listing
@@ -330,7 +344,7 @@ mod tests {
         new_storage.restore_from_backup(&backup_path, "test_password").unwrap();
         
         // Verify data was restored
-        let restored_data: Vec<&str> = new_storage.load_encrypted("backup_file").unwrap();
+        let restored_data: Vec<String> = new_storage.load_encrypted("backup_file").unwrap();
         assert_eq!(test_data, restored_data);
     }
 } 
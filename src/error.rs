@@ -31,6 +31,10 @@ pub enum NozyError {
     
     #[error("Insufficient funds: {0}")]
     InsufficientFunds(String),
+    
+    // A request would have revealed an address or viewing key while scan-only mode is on
+    #[error("Privacy violation: {0}")]
+    Privacy(String),
 }
 
 impl From<std::io::Error> for NozyError {
@@ -0,0 +1,141 @@
+//! ZIP-317 conventional fee calculation
+
+use crate::notes::NoteType;
+
+pub const MARGINAL_FEE: u64 = 5_000;
+
+pub const GRACE_ACTIONS: usize = 2;
+
+// ZIP-317 sizes a transparent logical action by a standard P2PKH input and output
+pub const P2PKH_STANDARD_INPUT_SIZE: usize = 150;
+
+pub const P2PKH_STANDARD_OUTPUT_SIZE: usize = 34;
+
+// The builders pad each shielded bundle they emit to at least this many outputs or actions
+const MIN_BUNDLE_OUTPUTS: usize = 2;
+
+
+// Spends and outputs of a transaction, counted per pool
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ActionCounts {
+    
+    pub sapling_spends: usize,
+    
+    
+    pub sapling_outputs: usize,
+    
+    
+    pub orchard_spends: usize,
+    
+    
+    pub orchard_outputs: usize,
+    
+    
+    pub transparent_inputs: usize,
+    
+    
+    pub transparent_outputs: usize,
+}
+
+impl ActionCounts {
+    
+    pub fn add_spend(&mut self, pool: NoteType) {
+        match pool {
+            NoteType::Sapling => self.sapling_spends += 1,
+            NoteType::Orchard => self.orchard_spends += 1,
+        }
+    }
+    
+    
+    pub fn add_output(&mut self, pool: NoteType) {
+        match pool {
+            NoteType::Sapling => self.sapling_outputs += 1,
+            NoteType::Orchard => self.orchard_outputs += 1,
+        }
+    }
+    
+    
+    pub fn add_transparent_input(&mut self) {
+        self.transparent_inputs += 1;
+    }
+    
+    
+    pub fn add_transparent_output(&mut self) {
+        self.transparent_outputs += 1;
+    }
+    
+    
+    // Counted as the builders emit them: padding outputs and dummy actions are charged too
+    pub fn logical_actions(&self) -> usize {
+        self.transparent_actions() + self.sapling_actions() + self.orchard_actions()
+    }
+    
+    
+    fn transparent_actions(&self) -> usize {
+        let input_bytes = self.transparent_inputs * P2PKH_STANDARD_INPUT_SIZE;
+        let output_bytes = self.transparent_outputs * P2PKH_STANDARD_OUTPUT_SIZE;
+        input_bytes.div_ceil(P2PKH_STANDARD_INPUT_SIZE).max(output_bytes.div_ceil(P2PKH_STANDARD_OUTPUT_SIZE))
+    }
+    
+    
+    fn sapling_actions(&self) -> usize {
+        if self.sapling_spends == 0 && self.sapling_outputs == 0 {
+            return 0;
+        }
+        self.sapling_spends.max(self.sapling_outputs.max(MIN_BUNDLE_OUTPUTS))
+    }
+    
+    
+    // An Orchard action carries one spend and one output, so the larger side decides
+    fn orchard_actions(&self) -> usize {
+        if self.orchard_spends == 0 && self.orchard_outputs == 0 {
+            return 0;
+        }
+        self.orchard_spends.max(self.orchard_outputs).max(MIN_BUNDLE_OUTPUTS)
+    }
+}
+
+
+pub fn conventional_fee(counts: &ActionCounts) -> u64 {
+    MARGINAL_FEE * counts.logical_actions().max(GRACE_ACTIONS) as u64
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_zip317_fee_counts_logical_actions() {
+        let mut counts = ActionCounts::default();
+        counts.add_spend(NoteType::Orchard);
+        counts.add_output(NoteType::Orchard);
+        counts.add_output(NoteType::Orchard);
+        assert_eq!(counts.logical_actions(), 2);
+        assert_eq!(conventional_fee(&counts), 10_000);
+        
+        // Crossing pools pays for both bundles
+        counts.add_spend(NoteType::Sapling);
+        counts.add_spend(NoteType::Sapling);
+        counts.add_spend(NoteType::Sapling);
+        assert_eq!(counts.logical_actions(), 5);
+        assert_eq!(conventional_fee(&counts), 25_000);
+    }
+    
+    #[test]
+    fn test_zip317_fee_charges_padding_and_transparent() {
+        // One Sapling spend still emits two padded outputs, and one Orchard output a dummy action
+        let mut counts = ActionCounts::default();
+        counts.add_spend(NoteType::Sapling);
+        counts.add_output(NoteType::Orchard);
+        assert_eq!(counts.logical_actions(), 4);
+        assert_eq!(conventional_fee(&counts), 20_000);
+        
+        let mut transparent = ActionCounts::default();
+        transparent.add_transparent_input();
+        for _ in 0..3 {
+            transparent.add_transparent_output();
+        }
+        assert_eq!(transparent.logical_actions(), 3);
+    }
+}
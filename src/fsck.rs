@@ -0,0 +1,184 @@
+//! Wallet consistency checks over stored notes, witnesses and address counters
+
+use crate::notes::{NoteType, ShieldedNote};
+use orchard::note::ExtractedNoteCommitment;
+use std::collections::BTreeMap;
+use zcash_primitives::consensus::Network;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum IssueKind {
+    
+    DuplicateNote,
+    
+    CommitmentMismatch,
+    
+    NullifierCollision,
+    
+    MissingSpendingTx,
+    
+    WitnessRootMismatch,
+    
+    // A witness kept for a note that is spent or no longer stored
+    StaleWitness,
+    
+    MissingWitness,
+    
+    // The stored tree does not match the node's tree at the scanned height
+    AnchorMismatch,
+    
+    AddressCounterBehind,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct FsckIssue {
+    
+    pub kind: IssueKind,
+    
+    
+    // Note ID, address type or "sync state"
+    pub subject: String,
+    
+    
+    pub detail: String,
+    
+    
+    pub fix: String,
+    
+    
+    pub repaired: bool,
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct FsckReport {
+    
+    pub notes_checked: usize,
+    
+    
+    pub witnesses_checked: usize,
+    
+    
+    pub issues: Vec<FsckIssue>,
+    
+    
+    // Set when a repair rewound the sync cursor; the next sync rescans from here
+    pub rescan_from: Option<u32>,
+    
+    
+    // Checks that could not run, e.g. the anchor comparison while Zebra is offline
+    pub skipped: Vec<String>,
+}
+
+impl FsckReport {
+    
+    pub fn is_clean(&self) -> bool {
+        self.issues.is_empty()
+    }
+    
+    
+    pub fn repaired_count(&self) -> usize {
+        self.issues.iter().filter(|issue| issue.repaired).count()
+    }
+    
+    
+    pub fn push(&mut self, kind: IssueKind, subject: impl Into<String>, detail: impl Into<String>, fix: impl Into<String>) {
+        self.issues.push(FsckIssue {
+            kind,
+            subject: subject.into(),
+            detail: detail.into(),
+            fix: fix.into(),
+            repaired: false,
+        });
+    }
+    
+    
+    pub fn mark_last_repaired(&mut self) {
+        if let Some(issue) = self.issues.last_mut() {
+            issue.repaired = true;
+        }
+    }
+}
+
+
+// Notes sharing a pool and commitment are the same on-chain note. Returns (keep, drop) pairs.
+pub fn find_duplicates(notes: &[&ShieldedNote]) -> Vec<(String, String)> {
+    let mut groups: BTreeMap<(bool, Vec<u8>), Vec<&ShieldedNote>> = BTreeMap::new();
+    for note in notes.iter().filter(|note| !note.commitment.is_empty()) {
+        groups.entry((note.note_type == NoteType::Orchard, note.commitment.clone())).or_default().push(note);
+    }
+    
+    let mut pairs = Vec::new();
+    for mut group in groups.into_values().filter(|group| group.len() > 1) {
+        // Keep the copy that knows the most: a recorded spend, then a tree position
+        group.sort_by(|a, b| {
+            b.is_spent().cmp(&a.is_spent())
+                .then(b.position.is_some().cmp(&a.position.is_some()))
+                .then(a.id.cmp(&b.id))
+        });
+        let keep = group[0].id.clone();
+        pairs.extend(group[1..].iter().map(|note| (keep.clone(), note.id.clone())));
+    }
+    pairs
+}
+
+
+// Distinct notes claiming the same nullifier; only one of them can be right
+pub fn find_nullifier_collisions(notes: &[&ShieldedNote]) -> Vec<(Vec<u8>, Vec<String>)> {
+    let mut by_nullifier: BTreeMap<&[u8], Vec<&ShieldedNote>> = BTreeMap::new();
+    for note in notes {
+        if let Some(nullifier) = note.nullifier.as_deref() {
+            by_nullifier.entry(nullifier).or_default().push(note);
+        }
+    }
+    
+    by_nullifier.into_iter()
+        .filter(|(_, group)| group.iter().any(|note| note.commitment != group[0].commitment))
+        .map(|(nullifier, group)| (nullifier.to_vec(), group.iter().map(|note| note.id.clone()).collect()))
+        .collect()
+}
+
+
+// Rebuilds the note commitment from the stored address, value and randomness. None means the
+// note was not decrypted from the chain and cannot be checked this way.
+pub fn chain_commitment(note: &ShieldedNote, network: &Network) -> Option<Vec<u8>> {
+    match note.note_type {
+        NoteType::Orchard => note.orchard_note(network)
+            .map(|rebuilt| ExtractedNoteCommitment::from(rebuilt.commitment()).to_bytes().to_vec()),
+        NoteType::Sapling => note.sapling_note(network)
+            .map(|rebuilt| rebuilt.cmu().to_bytes().to_vec()),
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::NoteManager;
+    
+    #[test]
+    fn test_duplicates_and_nullifier_collisions() {
+        let (manager, ids) = NoteManager::with_test_notes(&[(50_000, NoteType::Orchard), (20_000, NoteType::Orchard)]);
+        let mut original = manager.get_note(&ids[0]).unwrap().clone();
+        original.nullifier = Some(vec![9u8; 32]);
+        
+        let mut copy = original.clone();
+        copy.id = "copy".to_string();
+        copy.spent_at_height = Some(30);
+        
+        let mut impostor = manager.get_note(&ids[1]).unwrap().clone();
+        impostor.nullifier = original.nullifier.clone();
+        
+        let notes = vec![&original, &copy, &impostor];
+        assert_eq!(find_duplicates(&notes), vec![("copy".to_string(), original.id.clone())]);
+        
+        let collisions = find_nullifier_collisions(&notes);
+        assert_eq!(collisions.len(), 1);
+        assert_eq!(collisions[0].1.len(), 3);
+        
+        // Same note twice is a duplicate, not a collision
+        assert!(find_nullifier_collisions(&[&original, &copy]).is_empty());
+        assert!(chain_commitment(&original, &Network::TestNetwork).is_none());
+    }
+}
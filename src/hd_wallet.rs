@@ -10,6 +10,8 @@ use serde::{Deserialize, Serialize};
 use std::collections::HashMap;
 use std::str::FromStr;
 use rand::Rng;
+use zcash_keys::keys::UnifiedSpendingKey;
+use zcash_primitives::consensus::Network;
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -283,6 +285,15 @@ impl HDWallet {
     }
     
     
+    pub fn unified_spending_key(&self, network: &Network, password: &str) -> NozyResult<UnifiedSpendingKey> {
+        let seed = self.get_seed_bytes(password)?;
+        
+        // ZIP-32 account 0 holds every Orchard and Sapling key the wallet uses
+        UnifiedSpendingKey::from_seed(network, &seed, zip32::AccountId::ZERO)
+            .map_err(|e| NozyError::InvalidOperation(format!("Failed to derive spending key: {:?}", e)))
+    }
+    
+    
     pub fn derive_child_key(&self, derivation_path: &str, password: &str) -> NozyResult<Vec<u8>> {
         // Get raw seed bytes for proper Zcash derivation
         let seed = self.get_seed_bytes(password)?;
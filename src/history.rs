@@ -0,0 +1,336 @@
+//! Transaction history assembled from scanned notes and recovered outgoing outputs
+
+use crate::error::NozyResult;
+use crate::memo::DecodedMemo;
+use crate::notes::{normalize_tag, tag_matches, NoteManager, NoteType, ShieldedNote};
+use crate::scanner::RecoveredOutput;
+use serde::{Deserialize, Serialize};
+use std::collections::{BTreeMap, BTreeSet};
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct OutgoingNote {
+    
+    pub txid: Vec<u8>,
+    
+    
+    pub block_height: u32,
+    
+    
+    pub note_type: NoteType,
+    
+    
+    pub output_index: usize,
+    
+    
+    pub recipient_address: String,
+    
+    
+    pub value: u64,
+    
+    
+    pub memo: Vec<u8>,
+}
+
+impl OutgoingNote {
+    
+    pub fn from_recovered(output: &RecoveredOutput, txid: &[u8], block_height: u32) -> Self {
+        Self {
+            txid: txid.to_vec(),
+            block_height,
+            note_type: output.note_type,
+            output_index: output.output_index,
+            recipient_address: output.recipient_address.clone(),
+            value: output.value,
+            memo: output.memo.clone(),
+        }
+    }
+    
+    
+    pub fn decoded_memo(&self) -> DecodedMemo {
+        DecodedMemo::decode(&self.memo)
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum HistoryDirection {
+    
+    Received,
+    
+    
+    Sent,
+    
+    // We spent our own notes and every output came back to us
+    Internal,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct HistoryEntry {
+    
+    pub txid: String,
+    
+    
+    pub block_height: u32,
+    
+    
+    pub direction: HistoryDirection,
+    
+    
+    pub value: u64,
+    
+    
+    pub fee: Option<u64>,
+    
+    
+    pub counterparties: Vec<String>,
+    
+    
+    pub memos: Vec<DecodedMemo>,
+    
+    
+    pub tags: Vec<String>,
+}
+
+impl HistoryEntry {
+    
+    pub fn has_tag(&self, filter: &str) -> bool {
+        self.tags.iter().any(|tag| tag_matches(tag, filter))
+    }
+}
+
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct TransactionHistory {
+    
+    outgoing: Vec<OutgoingNote>,
+    
+    // Keyed by the displayed (byte-reversed) txid
+    #[serde(default)]
+    tags: BTreeMap<String, Vec<String>>,
+}
+
+impl TransactionHistory {
+    
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    
+    // Returns false if this output was already recorded
+    pub fn add_outgoing(&mut self, note: OutgoingNote) -> bool {
+        let known = self.outgoing.iter().any(|existing| {
+            existing.txid == note.txid
+                && existing.note_type == note.note_type
+                && existing.output_index == note.output_index
+        });
+        if known {
+            return false;
+        }
+        
+        self.outgoing.push(note);
+        true
+    }
+    
+    
+    pub fn outgoing(&self) -> &[OutgoingNote] {
+        &self.outgoing
+    }
+    
+    
+    // Returns false if the transaction already had the tag
+    pub fn add_tag(&mut self, txid: &str, tag: &str) -> NozyResult<bool> {
+        let tag = normalize_tag(tag)?;
+        let tags = self.tags.entry(txid.to_lowercase()).or_default();
+        if tags.contains(&tag) {
+            return Ok(false);
+        }
+        tags.push(tag);
+        Ok(true)
+    }
+    
+    
+    pub fn remove_tag(&mut self, txid: &str, tag: &str) -> NozyResult<bool> {
+        let tag = normalize_tag(tag)?;
+        let Some(tags) = self.tags.get_mut(&txid.to_lowercase()) else {
+            return Ok(false);
+        };
+        let before = tags.len();
+        tags.retain(|existing| *existing != tag);
+        let removed = tags.len() != before;
+        if tags.is_empty() {
+            self.tags.remove(&txid.to_lowercase());
+        }
+        Ok(removed)
+    }
+    
+    
+    // Groups our received notes, spent notes and recovered outgoing outputs by transaction, newest first
+    pub fn entries(&self, notes: &NoteManager) -> Vec<HistoryEntry> {
+        let all_notes = notes.get_all_notes();
+        
+        let mut txids: BTreeSet<Vec<u8>> = self.outgoing.iter().map(|out| out.txid.clone()).collect();
+        for note in &all_notes {
+            txids.extend(note.tx_hash.iter().cloned());
+            txids.extend(note.spent_in_txid.iter().cloned());
+        }
+        
+        let mut entries: Vec<HistoryEntry> = txids.into_iter()
+            .map(|txid| self.entry_for(&txid, &all_notes))
+            .collect();
+        
+        entries.sort_by(|a, b| b.block_height.cmp(&a.block_height).then_with(|| a.txid.cmp(&b.txid)));
+        entries
+    }
+    
+    
+    fn entry_for(&self, txid: &[u8], notes: &[&ShieldedNote]) -> HistoryEntry {
+        let outs: Vec<&OutgoingNote> = self.outgoing.iter().filter(|out| out.txid == txid).collect();
+        let received: Vec<&&ShieldedNote> = notes.iter().filter(|note| note.tx_hash.as_deref() == Some(txid)).collect();
+        let spent: Vec<&&ShieldedNote> = notes.iter().filter(|note| note.spent_in_txid.as_deref() == Some(txid)).collect();
+        
+        let out_total: u64 = outs.iter().map(|out| out.value).sum();
+        let received_total: u64 = received.iter().map(|note| note.value).sum();
+        let spent_total: u64 = spent.iter().map(|note| note.value).sum();
+        
+        let block_height = outs.iter().map(|out| out.block_height)
+            .chain(received.iter().map(|note| note.created_at_height))
+            .chain(spent.iter().filter_map(|note| note.spent_at_height))
+            .max()
+            .unwrap_or(0);
+        
+        // Only meaningful when we funded the transaction
+        let fee = if spent.is_empty() {
+            None
+        } else {
+            spent_total.checked_sub(out_total + received_total)
+        };
+        
+        let (direction, value, counterparties, memos) = if !outs.is_empty() {
+            (
+                HistoryDirection::Sent,
+                out_total,
+                unique(outs.iter().map(|out| out.recipient_address.clone())),
+                outs.iter().map(|out| out.decoded_memo()).filter(|memo| !memo.is_empty()).collect(),
+            )
+        } else {
+            let direction = if spent.is_empty() { HistoryDirection::Received } else { HistoryDirection::Internal };
+            (
+                direction,
+                received_total,
+                unique(received.iter().map(|note| note.recipient_address.clone())),
+                received.iter().map(|note| note.decoded_memo()).filter(|memo| !memo.is_empty()).collect(),
+            )
+        };
+        
+        // Tags on received notes describe the income, so a payment does not inherit them from its change
+        let txid = txid_to_hex(txid);
+        let mut tags = self.tags.get(&txid).cloned().unwrap_or_default();
+        if direction != HistoryDirection::Sent {
+            for tag in received.iter().flat_map(|note| note.tags.iter()) {
+                if !tags.contains(tag) {
+                    tags.push(tag.clone());
+                }
+            }
+        }
+        
+        HistoryEntry {
+            txid,
+            block_height,
+            direction,
+            value,
+            fee,
+            counterparties,
+            memos,
+            tags,
+        }
+    }
+}
+
+
+// Transaction IDs are shown byte-reversed, the same way zcashd and Zebra print them
+pub fn txid_to_hex(txid: &[u8]) -> String {
+    let mut display = txid.to_vec();
+    display.reverse();
+    hex::encode(display)
+}
+
+
+fn unique(items: impl Iterator<Item = String>) -> Vec<String> {
+    let mut seen = Vec::new();
+    for item in items {
+        if !seen.contains(&item) {
+            seen.push(item);
+        }
+    }
+    seen
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_sent_entry_shows_recipient_and_fee() {
+        let mut notes = NoteManager::new(&crate::config::NozyConfig::default()).unwrap();
+        let send_txid = vec![9u8; 32];
+        
+        let mut input = notes.create_note(100_000, "u1ours".to_string(), None, NoteType::Orchard, 10, None).unwrap();
+        input.tx_hash = Some(vec![1u8; 32]);
+        input.spent_at_height = Some(20);
+        input.spent_in_txid = Some(send_txid.clone());
+        notes.add_note(input).unwrap();
+        
+        let mut change = notes.create_note(30_000, "u1change".to_string(), None, NoteType::Orchard, 20, None).unwrap();
+        change.tx_hash = Some(send_txid.clone());
+        notes.add_note(change).unwrap();
+        
+        let mut history = TransactionHistory::new();
+        let payment = OutgoingNote {
+            txid: send_txid.clone(),
+            block_height: 20,
+            note_type: NoteType::Orchard,
+            output_index: 0,
+            recipient_address: "u1friend".to_string(),
+            value: 60_000,
+            memo: b"dinner".to_vec(),
+        };
+        assert!(history.add_outgoing(payment.clone()));
+        assert!(!history.add_outgoing(payment));
+        
+        let entries = history.entries(&notes);
+        assert_eq!(entries.len(), 2);
+        
+        let sent = &entries[0];
+        assert_eq!(sent.direction, HistoryDirection::Sent);
+        assert_eq!(sent.value, 60_000);
+        assert_eq!(sent.fee, Some(10_000));
+        assert_eq!(sent.counterparties, vec!["u1friend".to_string()]);
+        assert_eq!(sent.memos, vec![DecodedMemo::Text("dinner".to_string())]);
+        
+        assert_eq!(entries[1].direction, HistoryDirection::Received);
+        assert_eq!(entries[1].value, 100_000);
+    }
+    
+    #[test]
+    fn test_entries_carry_transaction_and_note_tags() {
+        let mut notes = NoteManager::new(&crate::config::NozyConfig::default()).unwrap();
+        let mut income = notes.create_note(50_000, "u1shop".to_string(), None, NoteType::Orchard, 10, None).unwrap();
+        income.tx_hash = Some(vec![3u8; 32]);
+        income.tags = vec!["revenue/customer-42".to_string()];
+        notes.add_note(income).unwrap();
+        
+        let mut history = TransactionHistory::new();
+        let txid = txid_to_hex(&[3u8; 32]);
+        assert!(history.add_tag(&txid.to_uppercase(), "Q3").unwrap());
+        
+        let entry = &history.entries(&notes)[0];
+        assert_eq!(entry.tags, vec!["q3".to_string(), "revenue/customer-42".to_string()]);
+        assert!(entry.has_tag("revenue"));
+        
+        assert!(history.remove_tag(&txid, "q3").unwrap());
+        assert!(!history.remove_tag(&txid, "q3").unwrap());
+        assert!(!history.entries(&notes)[0].has_tag("q3"));
+    }
+}
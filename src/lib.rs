@@ -5,9 +5,22 @@ pub mod error;
 pub mod config;
 pub mod storage;
 pub mod notes;
+pub mod fees;
+pub mod selection;
+pub mod mixing;
+pub mod migration;
+pub mod payouts;
+pub mod payment_request;
+pub mod memo;
+pub mod history;
 pub mod addresses;
 pub mod transactions;
+pub mod prover;
 pub mod zebra_integration;
+pub mod scanner;
+pub mod mempool;
+pub mod sync;
+pub mod fsck;
 pub mod hd_wallet;
 pub mod encrypted_storage;
 pub mod transaction_signer;
@@ -18,13 +31,22 @@ pub use error::{NozyError, NozyResult};
 pub use config::{NozyConfig, PrivacyLevel};
 pub use storage::WalletStorage;
 pub use notes::{NoteManager, ShieldedNote, NoteType};
+pub use memo::DecodedMemo;
+pub use selection::SelectionPlan;
+pub use mixing::{MixStep, MixingRound, MixingSession};
+pub use migration::{MigrationSession, MigrationStep, MigrationTransfer};
+pub use history::{HistoryDirection, HistoryEntry, OutgoingNote, TransactionHistory};
 pub use addresses::{AddressManager, ZcashAddressWrapper, ZcashAddressType};
 pub use transactions::{TransactionBuilder, ShieldedTransaction, TransactionInput, TransactionOutput, TransactionStatus};
 pub use zebra_integration::{ZebraClient, ZebraConfig, ZebraStatus, SyncStatus};
+pub use scanner::{ViewingKeys, DecryptedNote, RecoveredOutput, ScannedTransaction};
+pub use mempool::{MempoolWatcher, PendingIncoming, PendingSpend};
+pub use sync::{SyncEvent, SyncOptions, SyncProgress, SyncState};
+pub use fsck::{FsckIssue, FsckReport, IssueKind};
 pub use wallet::{NozyWallet, WalletStatus};
 pub use hd_wallet::{HDWallet, AddressType};
 pub use encrypted_storage::EncryptedStorage;
-pub use transaction_signer::{TransactionSigner, ShieldedInput, ShieldedOutput, SignedTransaction};
+pub use transaction_signer::{InputSelection, Payment, TransactionSigner, ShieldedInput, ShieldedOutput, SignedTransaction};
 pub use cli::{Cli, CliHandler, Commands};
 
 /// Main entry point for the Nozy wallet so dont be Nozy noting to see here
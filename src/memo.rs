@@ -0,0 +1,161 @@
+//! ZIP-302 memo decoding
+
+use serde::{Deserialize, Serialize};
+use std::fmt;
+use zcash_protocol::memo::{Memo, MemoBytes};
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub enum DecodedMemo {
+    
+    Empty,
+    
+    
+    Text(String),
+    
+    
+    Arbitrary(Vec<u8>),
+    
+    
+    Future(Vec<u8>),
+    
+    
+    Invalid(Vec<u8>),
+}
+
+impl DecodedMemo {
+    
+    // Accepts a full 512-byte memo field, or a shorter prefix as stored by the indexer path
+    pub fn decode(bytes: &[u8]) -> Self {
+        if bytes.is_empty() {
+            return DecodedMemo::Empty;
+        }
+        
+        let memo_bytes = match MemoBytes::from_bytes(bytes) {
+            Ok(memo_bytes) => memo_bytes,
+            Err(_) => return DecodedMemo::Invalid(strip_padding(bytes).to_vec()),
+        };
+        
+        match Memo::try_from(&memo_bytes) {
+            Ok(Memo::Empty) => DecodedMemo::Empty,
+            // An all-zero field decodes as empty text; it carries no message either way
+            Ok(Memo::Text(text)) if text.is_empty() => DecodedMemo::Empty,
+            Ok(Memo::Text(text)) => DecodedMemo::Text(String::from(text)),
+            Ok(Memo::Arbitrary(data)) => DecodedMemo::Arbitrary(strip_padding(&data[..]).to_vec()),
+            Ok(Memo::Future(raw)) => DecodedMemo::Future(raw.as_slice().to_vec()),
+            Err(_) => DecodedMemo::Invalid(memo_bytes.as_slice().to_vec()),
+        }
+    }
+    
+    
+    pub fn is_empty(&self) -> bool {
+        matches!(self, DecodedMemo::Empty)
+    }
+    
+    
+    pub fn as_text(&self) -> Option<&str> {
+        match self {
+            DecodedMemo::Text(text) => Some(text),
+            _ => None,
+        }
+    }
+    
+    
+    // Wallets conventionally append "Reply-To:" followed by an address to text memos
+    pub fn reply_address(&self) -> Option<String> {
+        let text = self.as_text()?;
+        let mut lines = text.lines().map(str::trim);
+        
+        while let Some(line) = lines.next() {
+            let Some(rest) = strip_prefix_ignore_case(line, "reply-to:") else {
+                continue;
+            };
+            
+            let address = match rest.trim() {
+                "" => lines.next().unwrap_or(""),
+                inline => inline,
+            };
+            if !address.is_empty() && !address.contains(char::is_whitespace) {
+                return Some(address.to_string());
+            }
+        }
+        
+        None
+    }
+    
+    
+    pub fn kind(&self) -> &'static str {
+        match self {
+            DecodedMemo::Empty => "empty",
+            DecodedMemo::Text(_) => "text",
+            DecodedMemo::Arbitrary(_) => "arbitrary data",
+            DecodedMemo::Future(_) => "future format",
+            DecodedMemo::Invalid(_) => "invalid",
+        }
+    }
+}
+
+impl fmt::Display for DecodedMemo {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            DecodedMemo::Empty => write!(f, "(no memo)"),
+            DecodedMemo::Text(text) => write!(f, "{}", text),
+            DecodedMemo::Arbitrary(data) => write!(f, "[{} bytes of data] {}", data.len(), hex::encode(data)),
+            DecodedMemo::Future(data) => write!(f, "[future memo format 0x{:02x}, {} bytes]", data.first().copied().unwrap_or(0), data.len()),
+            DecodedMemo::Invalid(data) => write!(f, "[invalid memo, {} bytes] {}", data.len(), hex::encode(data)),
+        }
+    }
+}
+
+
+fn strip_padding(bytes: &[u8]) -> &[u8] {
+    let end = bytes.iter().rposition(|b| *b != 0).map(|i| i + 1).unwrap_or(0);
+    &bytes[..end]
+}
+
+
+fn strip_prefix_ignore_case<'a>(line: &'a str, prefix: &str) -> Option<&'a str> {
+    line.get(..prefix.len())
+        .filter(|head| head.eq_ignore_ascii_case(prefix))
+        .map(|_| &line[prefix.len()..])
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_zip302_memo_kinds() {
+        let mut text = [0u8; 512];
+        text[..5].copy_from_slice(b"hello");
+        assert_eq!(DecodedMemo::decode(&text), DecodedMemo::Text("hello".to_string()));
+        
+        let mut empty = [0u8; 512];
+        empty[0] = 0xF6;
+        assert_eq!(DecodedMemo::decode(&empty), DecodedMemo::Empty);
+        assert_eq!(DecodedMemo::decode(&[0u8; 512]), DecodedMemo::Empty);
+        
+        let mut arbitrary = [0u8; 512];
+        arbitrary[0] = 0xFF;
+        arbitrary[1..4].copy_from_slice(&[1, 2, 3]);
+        assert_eq!(DecodedMemo::decode(&arbitrary), DecodedMemo::Arbitrary(vec![1, 2, 3]));
+        
+        let mut future = [0u8; 512];
+        future[0] = 0xF5;
+        assert!(matches!(DecodedMemo::decode(&future), DecodedMemo::Future(_)));
+        
+        // Indexer-sourced memos are bare UTF-8 without padding
+        assert_eq!(DecodedMemo::decode(b"thanks"), DecodedMemo::Text("thanks".to_string()));
+    }
+    
+    #[test]
+    fn test_reply_address() {
+        let memo = DecodedMemo::Text("Lunch money\nReply-To:\nu1replyaddress".to_string());
+        assert_eq!(memo.reply_address(), Some("u1replyaddress".to_string()));
+        
+        let inline = DecodedMemo::Text("reply-to: zs1abc".to_string());
+        assert_eq!(inline.reply_address(), Some("zs1abc".to_string()));
+        
+        assert_eq!(DecodedMemo::Text("no address here".to_string()).reply_address(), None);
+    }
+}
@@ -0,0 +1,211 @@
+//! Mempool watcher for unconfirmed incoming and outgoing funds
+
+use crate::error::NozyResult;
+use crate::notes::{NoteManager, ShieldedNote};
+use crate::scanner::{scan_transaction, ViewingKeys};
+use crate::zebra_integration::ZebraClient;
+use serde::{Deserialize, Serialize};
+use std::collections::HashSet;
+use zcash_primitives::consensus::BlockHeight;
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingIncoming {
+    
+    pub txid: String,
+    
+    
+    pub note: ShieldedNote,
+    
+    
+    pub is_change: bool,
+    
+    
+    pub first_seen: String,
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PendingSpend {
+    
+    pub txid: String,
+    
+    
+    pub note_id: String,
+    
+    
+    pub value: u64,
+    
+    
+    pub first_seen: String,
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct MempoolUpdate {
+    
+    pub new_incoming: Vec<PendingIncoming>,
+    
+    
+    pub new_spends: Vec<PendingSpend>,
+    
+    
+    pub dropped: usize,
+    
+    
+    // Transactions that could not be fetched or scanned this time
+    pub skipped: usize,
+    
+    
+    pub tip_height: u32,
+}
+
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct MempoolWatcher {
+    
+    incoming: Vec<PendingIncoming>,
+    
+    
+    spends: Vec<PendingSpend>,
+    
+    
+    scanned: HashSet<String>,
+}
+
+impl MempoolWatcher {
+    
+    pub fn new() -> Self {
+        Self::default()
+    }
+    
+    
+    pub fn poll(
+        &mut self,
+        client: &ZebraClient,
+        keys: &ViewingKeys,
+        notes: &NoteManager,
+    ) -> NozyResult<MempoolUpdate> {
+        let tip = client.get_status()?.block_height.unwrap_or(0);
+        let next_height = tip + 1;
+        
+        let mempool: HashSet<String> = client.get_raw_mempool()?.into_iter().collect();
+        
+        // Anything that left the mempool was either mined or evicted; sync takes it from here
+        let before = self.scanned.len();
+        self.scanned.retain(|txid| mempool.contains(txid));
+        self.incoming.retain(|pending| mempool.contains(&pending.txid));
+        self.spends.retain(|pending| mempool.contains(&pending.txid));
+        
+        let mut update = MempoolUpdate {
+            dropped: before - self.scanned.len(),
+            tip_height: tip,
+            ..MempoolUpdate::default()
+        };
+        
+        for txid in mempool {
+            if self.scanned.contains(&txid) {
+                continue;
+            }
+            
+            // A transaction can be mined or evicted between listing and fetching it; it is
+            // left unscanned so the next poll retries it if it is still there
+            let scanned = client.get_raw_transaction(&txid, next_height)
+                .and_then(|tx| scan_transaction(&tx, keys, BlockHeight::from_u32(next_height)));
+            let Ok(scanned) = scanned else {
+                update.skipped += 1;
+                continue;
+            };
+            let now = chrono::Utc::now().to_rfc3339();
+            
+            for decrypted in &scanned.received {
+                update.new_incoming.push(PendingIncoming {
+                    txid: txid.clone(),
+                    note: notes.note_from_decrypted(decrypted, 0, &scanned.txid),
+                    is_change: decrypted.is_change,
+                    first_seen: now.clone(),
+                });
+            }
+            
+            for nullifier in &scanned.nullifiers {
+                if let Some(note) = notes.find_note_by_nullifier(nullifier) {
+                    update.new_spends.push(PendingSpend {
+                        txid: txid.clone(),
+                        note_id: note.id.clone(),
+                        value: note.value,
+                        first_seen: now.clone(),
+                    });
+                }
+            }
+            
+            self.scanned.insert(txid);
+        }
+        
+        self.incoming.extend(update.new_incoming.iter().cloned());
+        self.spends.extend(update.new_spends.iter().cloned());
+        
+        Ok(update)
+    }
+    
+    
+    pub fn pending_incoming(&self) -> &[PendingIncoming] {
+        &self.incoming
+    }
+    
+    
+    pub fn pending_spends(&self) -> &[PendingSpend] {
+        &self.spends
+    }
+    
+    
+    // Only money from others counts; change and outputs of our own spends are not new funds
+    pub fn pending_incoming_value(&self) -> u64 {
+        self.incoming.iter()
+            .filter(|pending| !pending.is_change)
+            .filter(|pending| !self.spends.iter().any(|spend| spend.txid == pending.txid))
+            .map(|pending| pending.note.value)
+            .sum()
+    }
+    
+    
+    pub fn pending_spent_value(&self) -> u64 {
+        self.spends.iter().map(|pending| pending.value).sum()
+    }
+    
+    
+    pub fn is_pending_spend(&self, note_id: &str) -> bool {
+        self.spends.iter().any(|pending| pending.note_id == note_id)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::NoteType;
+    
+    #[test]
+    fn test_pending_incoming_excludes_change_and_own_spends() {
+        let mut manager = NoteManager::new(&crate::config::NozyConfig::default()).unwrap();
+        let mut watcher = MempoolWatcher::new();
+        let mut pending = |txid: &str, value: u64, is_change: bool| PendingIncoming {
+            txid: txid.to_string(),
+            note: manager.create_note(value, "u1test".to_string(), None, NoteType::Orchard, 0, None).unwrap(),
+            is_change,
+            first_seen: String::new(),
+        };
+        
+        watcher.incoming.push(pending("payment", 70_000, false));
+        watcher.incoming.push(pending("change", 20_000, true));
+        watcher.incoming.push(pending("self-send", 40_000, false));
+        watcher.spends.push(PendingSpend {
+            txid: "self-send".to_string(),
+            note_id: "spent".to_string(),
+            value: 50_000,
+            first_seen: String::new(),
+        });
+        
+        assert_eq!(watcher.pending_incoming_value(), 70_000);
+        assert_eq!(watcher.pending_spent_value(), 50_000);
+    }
+}
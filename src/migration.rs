@@ -0,0 +1,302 @@
+//! Sapling to Orchard migration in common denominations spread over several blocks
+
+use crate::fees::{self, ActionCounts};
+use crate::notes::{NoteType, ShieldedNote};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+// 1-2-5 series from 10 ZEC down to 0.001 ZEC, largest first
+pub const DENOMINATIONS: [u64; 13] = [
+    1_000_000_000, 500_000_000, 200_000_000, 100_000_000,
+    50_000_000, 20_000_000, 10_000_000, 5_000_000, 2_000_000, 1_000_000,
+    500_000, 200_000, 100_000,
+];
+
+// Gap between two scheduled transfers is drawn from 1..=this many blocks
+pub const MAX_TRANSFER_GAP_BLOCKS: u32 = 24;
+
+// Stop adding transfers once the planned fees would eat more than this share (1/N) of a transfer
+const MAX_FEE_SHARE: u64 = 10;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum TransferStatus {
+    
+    Scheduled,
+    
+    Broadcast,
+    
+    Confirmed,
+    
+    // The Sapling notes left could no longer pay for this transfer
+    Skipped,
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationTransfer {
+    
+    pub denomination: u64,
+    
+    
+    pub scheduled_height: u32,
+    
+    
+    pub status: TransferStatus,
+    
+    
+    pub txid: Option<String>,
+    
+    
+    pub input_note_ids: Vec<String>,
+    
+    
+    pub fee: u64,
+    
+    
+    pub confirmed_height: Option<u32>,
+}
+
+
+// Saved with the wallet so the schedule survives restarts
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MigrationSession {
+    
+    pub transfers: Vec<MigrationTransfer>,
+    
+    
+    pub next_diversifier_index: u64,
+    
+    
+    pub started_at: String,
+}
+
+
+pub enum MigrationStep {
+    
+    Broadcast(MigrationTransfer),
+    
+    WaitingForConfirmation(usize),
+    
+    WaitingUntil(u32),
+    
+    // Sapling change from an earlier transfer has to confirm first
+    WaitingForNotes,
+    
+    Skipped(MigrationTransfer),
+    
+    Finished { moved: u64, fees_paid: u64 },
+}
+
+
+// Notes a transfer spends, with the exact fee and the change that stays in Sapling
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TransferPlan {
+    
+    pub input_note_ids: Vec<String>,
+    
+    
+    pub fee: u64,
+    
+    
+    pub change: u64,
+}
+
+
+impl MigrationSession {
+    
+    // Returns None when the balance is too small for even the smallest denomination
+    pub fn plan<R: Rng>(rng: &mut R, sapling_balance: u64, start_height: u32) -> Option<Self> {
+        let mut denominations = denominate(sapling_balance);
+        if denominations.is_empty() {
+            return None;
+        }
+        denominations.shuffle(rng);
+        
+        let mut height = start_height;
+        let transfers = denominations.into_iter()
+            .map(|denomination| {
+                height += rng.gen_range(1..=MAX_TRANSFER_GAP_BLOCKS);
+                MigrationTransfer {
+                    denomination,
+                    scheduled_height: height,
+                    status: TransferStatus::Scheduled,
+                    txid: None,
+                    input_note_ids: Vec::new(),
+                    fee: single_spend_fee(),
+                    confirmed_height: None,
+                }
+            })
+            .collect();
+        
+        Some(Self {
+            transfers,
+            next_diversifier_index: crate::mixing::DIVERSIFIER_OFFSET + rng.gen::<u32>() as u64,
+            started_at: chrono::Utc::now().to_rfc3339(),
+        })
+    }
+    
+    
+    pub fn is_finished(&self) -> bool {
+        self.transfers.iter().all(|transfer| matches!(transfer.status, TransferStatus::Confirmed | TransferStatus::Skipped))
+    }
+    
+    
+    pub fn take_diversifier_index(&mut self) -> u64 {
+        let index = self.next_diversifier_index;
+        self.next_diversifier_index += 1;
+        index
+    }
+    
+    
+    pub fn count(&self, status: TransferStatus) -> usize {
+        self.transfers.iter().filter(|transfer| transfer.status == status).count()
+    }
+    
+    
+    pub fn moved_value(&self) -> u64 {
+        self.sum_confirmed(|transfer| transfer.denomination)
+    }
+    
+    
+    pub fn fees_paid(&self) -> u64 {
+        self.sum_confirmed(|transfer| transfer.fee)
+    }
+    
+    
+    // Denominations and fees still to go, estimated for transfers not yet built
+    pub fn outstanding(&self) -> (u64, u64) {
+        self.transfers.iter()
+            .filter(|transfer| matches!(transfer.status, TransferStatus::Scheduled | TransferStatus::Broadcast))
+            .fold((0, 0), |(value, fee), transfer| (value + transfer.denomination, fee + transfer.fee))
+    }
+    
+    
+    fn sum_confirmed(&self, field: impl Fn(&MigrationTransfer) -> u64) -> u64 {
+        self.transfers.iter()
+            .filter(|transfer| transfer.status == TransferStatus::Confirmed)
+            .map(field)
+            .sum()
+    }
+}
+
+
+// One Sapling spend, Sapling change and one Orchard output
+fn single_spend_fee() -> u64 {
+    transfer_fee(1, true)
+}
+
+
+fn transfer_fee(spends: usize, with_change: bool) -> u64 {
+    let mut counts = ActionCounts::default();
+    for _ in 0..spends {
+        counts.add_spend(NoteType::Sapling);
+    }
+    if with_change {
+        counts.add_output(NoteType::Sapling);
+    }
+    counts.add_output(NoteType::Orchard);
+    fees::conventional_fee(&counts)
+}
+
+
+// Splits the balance, after the fee each transfer costs, into as few denominations as possible
+pub fn denominate(balance: u64) -> Vec<u64> {
+    let fee = single_spend_fee();
+    let mut remaining = balance;
+    let mut denominations = Vec::new();
+    
+    for denomination in DENOMINATIONS {
+        // Tiny transfers would mostly pay fees
+        if denomination < fee * MAX_FEE_SHARE {
+            break;
+        }
+        while remaining >= denomination + fee {
+            denominations.push(denomination);
+            remaining -= denomination + fee;
+        }
+    }
+    denominations
+}
+
+
+// Smallest single note that covers the transfer, otherwise largest notes first
+pub fn plan_transfer(notes: &[&ShieldedNote], denomination: u64, dust_threshold: u64) -> Option<TransferPlan> {
+    let mut sapling: Vec<&ShieldedNote> = notes.iter()
+        .copied()
+        .filter(|note| note.note_type == NoteType::Sapling)
+        .collect();
+    sapling.sort_by_key(|note| note.value);
+    
+    if let Some(note) = sapling.iter().find(|note| note.value >= denomination + transfer_fee(1, false)) {
+        return finish_plan(&[note], denomination, dust_threshold);
+    }
+    
+    let mut chosen = Vec::new();
+    for note in sapling.iter().rev() {
+        chosen.push(*note);
+        let total: u64 = chosen.iter().map(|note| note.value).sum();
+        if total >= denomination + transfer_fee(chosen.len(), false) {
+            return finish_plan(&chosen, denomination, dust_threshold);
+        }
+    }
+    None
+}
+
+
+fn finish_plan(inputs: &[&ShieldedNote], denomination: u64, dust_threshold: u64) -> Option<TransferPlan> {
+    let total: u64 = inputs.iter().map(|note| note.value).sum();
+    let input_note_ids = inputs.iter().map(|note| note.id.clone()).collect();
+    
+    let fee = transfer_fee(inputs.len(), true);
+    if total > denomination + fee + dust_threshold {
+        return Some(TransferPlan { input_note_ids, fee, change: total - denomination - fee });
+    }
+    
+    // Change too small to be worth a note goes to the fee instead
+    let fee = total.checked_sub(denomination)?;
+    (fee >= transfer_fee(inputs.len(), false)).then_some(TransferPlan { input_note_ids, fee, change: 0 })
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::NoteManager;
+    
+    #[test]
+    fn test_denominations_and_transfer_plans() {
+        // 1.37 ZEC becomes 1 + 0.2 + 0.1 + 0.05 + 0.01 + 0.005 + 0.002, each paying its own fee
+        let denominations = denominate(137_000_000);
+        assert_eq!(denominations, vec![100_000_000, 20_000_000, 10_000_000, 5_000_000, 1_000_000, 500_000, 200_000]);
+        let spent: u64 = denominations.iter().map(|value| value + single_spend_fee()).sum();
+        assert!(spent <= 137_000_000);
+        assert!(denominate(50_000).is_empty());
+        
+        let (manager, _) = NoteManager::with_test_notes(&[
+            (30_000_000, NoteType::Sapling),
+            (80_000_000, NoteType::Sapling),
+            (500_000_000, NoteType::Orchard),
+        ]);
+        let notes = manager.get_all_notes();
+        
+        // Orchard notes are never touched and the smallest covering note is preferred
+        let plan = plan_transfer(&notes, 20_000_000, 5_000).unwrap();
+        assert_eq!(plan.input_note_ids.len(), 1);
+        assert_eq!(manager.get_note(&plan.input_note_ids[0]).unwrap().value, 30_000_000);
+        assert_eq!(plan.change, 30_000_000 - 20_000_000 - plan.fee);
+        
+        let plan = plan_transfer(&notes, 100_000_000, 5_000).unwrap();
+        assert_eq!(plan.input_note_ids.len(), 2);
+        assert_eq!(plan.fee, 20_000);
+        assert!(plan_transfer(&notes, 200_000_000, 5_000).is_none());
+        
+        let session = MigrationSession::plan(&mut rand::thread_rng(), 137_000_000, 1_000).unwrap();
+        let mut heights: Vec<u32> = session.transfers.iter().map(|transfer| transfer.scheduled_height).collect();
+        heights.sort();
+        heights.dedup();
+        assert_eq!(heights.len(), 7);
+        assert!(heights[0] > 1_000);
+    }
+}
@@ -0,0 +1,280 @@
+//! Multi-round note mixing through self-sends to fresh diversified addresses
+
+use crate::fees::{self, ActionCounts};
+use crate::notes::{NoteType, ShieldedNote};
+use rand::seq::SliceRandom;
+use rand::Rng;
+use serde::{Deserialize, Serialize};
+
+// A round starts this many blocks at most after the previous one confirms
+pub const MAX_ROUND_DELAY_BLOCKS: u32 = 6;
+
+const MAX_SPLIT_OUTPUTS: usize = 3;
+
+const MAX_MERGE_INPUTS: usize = 3;
+
+// Mixing addresses come from a range the address manager never hands out
+pub(crate) const DIVERSIFIER_OFFSET: u64 = 1 << 40;
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+pub enum MixKind {
+    
+    Split,
+    
+    Merge,
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MixingRound {
+    
+    pub round: u32,
+    
+    
+    pub kind: MixKind,
+    
+    
+    pub txid: String,
+    
+    
+    pub input_note_ids: Vec<String>,
+    
+    
+    pub output_values: Vec<u64>,
+    
+    
+    pub fee: u64,
+    
+    
+    pub built_at_height: u32,
+}
+
+
+// Saved with the wallet so an interrupted mix carries on where it stopped
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MixingSession {
+    
+    pub rounds_total: u32,
+    
+    
+    pub rounds_done: u32,
+    
+    
+    pub fees_paid: u64,
+    
+    
+    pub next_round_height: u32,
+    
+    
+    pub next_diversifier_index: u64,
+    
+    
+    pub pending: Option<MixingRound>,
+    
+    
+    pub completed: Vec<MixingRound>,
+    
+    
+    pub started_at: String,
+}
+
+
+pub enum MixStep {
+    
+    Broadcast(MixingRound),
+    
+    WaitingForConfirmation(MixingRound),
+    
+    WaitingUntil(u32),
+    
+    // No note is spendable yet, usually because the last round's outputs need more confirmations
+    WaitingForNotes,
+    
+    Finished { rounds: u32, fees_paid: u64 },
+}
+
+
+// What the next round spends and creates, before addresses are attached
+#[derive(Debug, Clone)]
+pub struct RoundPlan {
+    
+    pub kind: MixKind,
+    
+    
+    pub input_note_ids: Vec<String>,
+    
+    
+    pub output_values: Vec<u64>,
+    
+    
+    pub fee: u64,
+}
+
+
+impl MixingSession {
+    
+    pub fn new(rounds: u32, start_height: u32) -> Self {
+        Self {
+            rounds_total: rounds,
+            rounds_done: 0,
+            fees_paid: 0,
+            next_round_height: start_height,
+            next_diversifier_index: DIVERSIFIER_OFFSET + rand::thread_rng().gen::<u32>() as u64,
+            pending: None,
+            completed: Vec::new(),
+            started_at: chrono::Utc::now().to_rfc3339(),
+        }
+    }
+    
+    
+    pub fn is_finished(&self) -> bool {
+        self.rounds_done >= self.rounds_total
+    }
+    
+    
+    pub fn take_diversifier_index(&mut self) -> u64 {
+        let index = self.next_diversifier_index;
+        self.next_diversifier_index += 1;
+        index
+    }
+    
+    
+    pub fn complete_pending(&mut self, confirmed_height: u32) {
+        if let Some(round) = self.pending.take() {
+            self.rounds_done += 1;
+            self.fees_paid += round.fee;
+            self.completed.push(round);
+            self.next_round_height = confirmed_height + rand::thread_rng().gen_range(1..=MAX_ROUND_DELAY_BLOCKS);
+        }
+    }
+}
+
+
+// Picks a random split or merge over the spendable notes, trying the other kind when the
+// first draw does not work out. Outputs are all Orchard.
+pub fn plan_round<R: Rng>(rng: &mut R, notes: &[&ShieldedNote], dust_threshold: u64) -> Option<RoundPlan> {
+    let mut kinds = Vec::new();
+    if notes.len() >= 2 {
+        kinds.push(MixKind::Merge);
+    }
+    if notes.iter().any(|note| split_fee(note, 2) + 2 * (dust_threshold + 1) <= note.value) {
+        kinds.push(MixKind::Split);
+    }
+    
+    kinds.shuffle(rng);
+    kinds.into_iter().find_map(|kind| match kind {
+        MixKind::Merge => plan_merge(rng, notes, dust_threshold),
+        MixKind::Split => plan_split(rng, notes, dust_threshold),
+    })
+}
+
+
+fn split_fee(note: &ShieldedNote, outputs: usize) -> u64 {
+    let mut counts = ActionCounts::default();
+    counts.add_spend(note.note_type);
+    for _ in 0..outputs {
+        counts.add_output(NoteType::Orchard);
+    }
+    fees::conventional_fee(&counts)
+}
+
+
+fn plan_merge<R: Rng>(rng: &mut R, notes: &[&ShieldedNote], dust_threshold: u64) -> Option<RoundPlan> {
+    let count = rng.gen_range(2..=notes.len().min(MAX_MERGE_INPUTS));
+    let inputs: Vec<&ShieldedNote> = notes.choose_multiple(rng, count).copied().collect();
+    
+    let mut counts = ActionCounts::default();
+    for note in &inputs {
+        counts.add_spend(note.note_type);
+    }
+    counts.add_output(NoteType::Orchard);
+    let fee = fees::conventional_fee(&counts);
+    let total: u64 = inputs.iter().map(|note| note.value).sum();
+    if total <= fee + dust_threshold {
+        return None;
+    }
+    
+    Some(RoundPlan {
+        kind: MixKind::Merge,
+        input_note_ids: inputs.iter().map(|note| note.id.clone()).collect(),
+        output_values: vec![total - fee],
+        fee,
+    })
+}
+
+
+fn plan_split<R: Rng>(rng: &mut R, notes: &[&ShieldedNote], dust_threshold: u64) -> Option<RoundPlan> {
+    let splittable: Vec<&ShieldedNote> = notes.iter()
+        .copied()
+        .filter(|note| split_fee(note, 2) + 2 * (dust_threshold + 1) <= note.value)
+        .collect();
+    let source = *splittable.choose(rng)?;
+    
+    // Each output starts just above dust and the rest is shared out at random
+    let wanted = rng.gen_range(2..=MAX_SPLIT_OUTPUTS);
+    for outputs in (2..=wanted).rev() {
+        let fee = split_fee(source, outputs);
+        let floor = dust_threshold + 1;
+        let Some(spare) = source.value.checked_sub(fee + floor * outputs as u64) else {
+            continue;
+        };
+        let weights: Vec<u64> = (0..outputs).map(|_| rng.gen_range(1..=100)).collect();
+        let weight_total: u64 = weights.iter().sum();
+        
+        let mut values: Vec<u64> = weights.iter().map(|weight| floor + spare * weight / weight_total).collect();
+        let assigned: u64 = values.iter().sum();
+        values[outputs - 1] += source.value - fee - assigned;
+        
+        return Some(RoundPlan {
+            kind: MixKind::Split,
+            input_note_ids: vec![source.id.clone()],
+            output_values: values,
+            fee,
+        });
+    }
+    None
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::NoteManager;
+    use rand::rngs::StdRng;
+    use rand::SeedableRng;
+    
+    #[test]
+    fn test_rounds_conserve_value() {
+        let (manager, _) = NoteManager::with_test_notes(&[(300_000, NoteType::Orchard), (120_000, NoteType::Orchard)]);
+        let notes = manager.get_all_notes();
+        let mut rng = StdRng::seed_from_u64(7);
+        
+        for _ in 0..200 {
+            let plan = plan_round(&mut rng, &notes, 5_000).unwrap();
+            let input_total: u64 = plan.input_note_ids.iter()
+                .map(|id| manager.get_note(id).unwrap().value)
+                .sum();
+            assert_eq!(plan.output_values.iter().sum::<u64>() + plan.fee, input_total);
+            assert!(plan.output_values.iter().all(|value| *value > 5_000));
+            match plan.kind {
+                MixKind::Merge => assert!(plan.input_note_ids.len() >= 2),
+                MixKind::Split => assert!(plan.output_values.len() >= 2),
+            }
+        }
+        
+        // Merging the two small notes cannot pay its fee, so those draws fall back to a split
+        let (manager, _) = NoteManager::with_test_notes(&[
+            (6_000, NoteType::Orchard),
+            (6_000, NoteType::Orchard),
+            (1_000_000, NoteType::Orchard),
+        ]);
+        let notes = manager.get_all_notes();
+        assert!((0..200).all(|_| plan_round(&mut rng, &notes, 5_000).is_some()));
+        
+        let mut session = MixingSession::new(2, 100);
+        let first = session.take_diversifier_index();
+        assert_eq!(session.take_diversifier_index(), first + 1);
+        assert!(!session.is_finished());
+    }
+}
@@ -1,11 +1,21 @@
 //! Note management for Orchard and Sapling notes no T address here
 
-use crate::error::NozyResult;
+use crate::error::{NozyError, NozyResult};
+use crate::memo::DecodedMemo;
+use crate::scanner::{DecryptedNote, ViewingKeys};
+use crate::fees::{self, ActionCounts};
+use crate::selection::{self, FeeRule, SelectionPlan};
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use blake2b_simd::Params;
 use sha2::{Sha256, Digest};
+use ff::PrimeField;
+use orchard::note::{RandomSeed, Rho};
 use rand::RngCore;
+use sapling_crypto::Rseed;
+use zcash_keys::address::Address;
+use zcash_primitives::consensus::Network;
+use zcash_primitives::transaction::Transaction;
 
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -35,12 +45,20 @@ pub struct ShieldedNote {
     pub randomness: Vec<u8>,
     
     
+    #[serde(default)]
+    pub rho: Option<Vec<u8>>,
+    
+    
     pub created_at_height: u32,
     
     
     pub spent_at_height: Option<u32>,
     
     
+    #[serde(default)]
+    pub spent_in_txid: Option<Vec<u8>>,
+    
+    
     pub tx_hash: Option<Vec<u8>>,
     
     
@@ -48,6 +66,73 @@ pub struct ShieldedNote {
     
     
     pub position: Option<u64>,
+    
+    
+    // Change we sent back to ourselves, held to the lower confirmation threshold
+    #[serde(default)]
+    pub is_change: bool,
+    
+    
+    #[serde(default)]
+    pub label: Option<String>,
+    
+    
+    // Accounting categories such as "revenue/customer-42"
+    #[serde(default)]
+    pub tags: Vec<String>,
+}
+
+
+impl ShieldedNote {
+    
+    pub fn has_tag(&self, filter: &str) -> bool {
+        self.tags.iter().any(|tag| tag_matches(tag, filter))
+    }
+    
+    
+    pub fn is_spent(&self) -> bool {
+        self.spent_at_height.is_some() || self.spent_in_txid.is_some()
+    }
+    
+    
+    pub fn decoded_memo(&self) -> DecodedMemo {
+        self.memo.as_deref()
+            .map(DecodedMemo::decode)
+            .unwrap_or(DecodedMemo::Empty)
+    }
+    
+    
+    // The protocol note behind a decrypted Orchard note; None for notes not found on chain
+    pub fn orchard_note(&self, network: &Network) -> Option<orchard::Note> {
+        let Address::Unified(address) = Address::decode(network, &self.recipient_address)? else {
+            return None;
+        };
+        let rho_bytes: [u8; 32] = self.rho.as_deref()?.try_into().ok()?;
+        let rho = Option::<Rho>::from(Rho::from_bytes(&rho_bytes))?;
+        let randomness: [u8; 32] = self.randomness.as_slice().try_into().ok()?;
+        let rseed = Option::<RandomSeed>::from(RandomSeed::from_bytes(randomness, &rho))?;
+        
+        let value = orchard::value::NoteValue::from_raw(self.value);
+        Option::from(orchard::Note::from_parts(*address.orchard()?, value, rho, rseed))
+    }
+    
+    
+    // Notes from before Canopy store rcm itself rather than rseed, so the stored commitment picks the form
+    pub fn sapling_note(&self, network: &Network) -> Option<sapling_crypto::Note> {
+        let Address::Sapling(address) = Address::decode(network, &self.recipient_address)? else {
+            return None;
+        };
+        let randomness: [u8; 32] = self.randomness.as_slice().try_into().ok()?;
+        let value = sapling_crypto::value::NoteValue::from_raw(self.value);
+        
+        let after_zip212 = sapling_crypto::Note::from_parts(address, value, Rseed::AfterZip212(randomness));
+        if after_zip212.cmu().to_bytes().to_vec() == self.commitment {
+            return Some(after_zip212);
+        }
+        let before_zip212 = Option::<jubjub::Fr>::from(jubjub::Fr::from_repr(randomness))
+            .map(|rcm| sapling_crypto::Note::from_parts(address, value, Rseed::BeforeZip212(rcm)));
+        Some(before_zip212.filter(|note| note.cmu().to_bytes().to_vec() == self.commitment).unwrap_or(after_zip212))
+    }
 }
 
 
@@ -58,7 +143,7 @@ pub enum NoteType {
 }
 
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
 pub enum NoteSelectionStrategy {
     
     PrivacyFirst,
@@ -74,6 +159,35 @@ pub enum NoteSelectionStrategy {
     
     
     AgeBased,
+    
+    
+    // Branch-and-bound search for a set that needs no change output
+    ExactMatch,
+    
+    
+    // Fewest ZIP-317 logical actions
+    MinimalInputs,
+    
+    
+    // Avoids spending Orchard and Sapling notes together
+    PrivacyWeighted,
+}
+
+
+impl NoteSelectionStrategy {
+    
+    pub fn all() -> Vec<NoteSelectionStrategy> {
+        vec![
+            NoteSelectionStrategy::PrivacyFirst,
+            NoteSelectionStrategy::EfficiencyFirst,
+            NoteSelectionStrategy::Balanced,
+            NoteSelectionStrategy::ValueBased,
+            NoteSelectionStrategy::AgeBased,
+            NoteSelectionStrategy::ExactMatch,
+            NoteSelectionStrategy::MinimalInputs,
+            NoteSelectionStrategy::PrivacyWeighted,
+        ]
+    }
 }
 
 
@@ -96,6 +210,39 @@ pub struct NoteManagerConfig {
     
     
     pub mixing_rounds: u32,
+    
+    
+    #[serde(default = "default_min_confirmations")]
+    pub min_confirmations: u32,
+    
+    
+    #[serde(default = "default_min_change_confirmations")]
+    pub min_change_confirmations: u32,
+    
+    
+    #[serde(default)]
+    pub split_policy: Option<SplitPolicy>,
+}
+
+
+// Keep at least `min_notes` spendable notes worth `min_value` or more, so payments don't queue behind change
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct SplitPolicy {
+    
+    pub min_notes: usize,
+    
+    
+    pub min_value: u64,
+}
+
+
+// ZIP-315 suggests 10 confirmations for received funds and 3 for our own change
+fn default_min_confirmations() -> u32 {
+    10
+}
+
+fn default_min_change_confirmations() -> u32 {
+    3
 }
 
 
@@ -109,6 +256,103 @@ pub struct NoteManager {
     
     
     commitment_tree: CommitmentTree,
+    
+    
+    #[serde(default)]
+    reservations: HashMap<String, NoteReservation>,
+    
+    
+    // Latest chain height we know of; 0 until the first sync or mempool poll
+    #[serde(default)]
+    chain_height: u32,
+}
+
+
+// A self-send that folds dust notes into one note, with an optional larger note to pay the fee
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DustSweep {
+    
+    pub dust_note_ids: Vec<String>,
+    
+    
+    pub carrier_note_id: Option<String>,
+    
+    
+    pub dust_value: u64,
+    
+    
+    pub fee: u64,
+    
+    
+    // What the dust adds to the fee over spending the carrier alone
+    pub extra_fee: u64,
+    
+    
+    pub output_value: u64,
+}
+
+
+impl DustSweep {
+    
+    pub fn note_ids(&self) -> Vec<String> {
+        self.carrier_note_id.iter()
+            .chain(self.dust_note_ids.iter())
+            .cloned()
+            .collect()
+    }
+    
+    
+    pub fn recovered_value(&self) -> u64 {
+        self.dust_value.saturating_sub(self.extra_fee)
+    }
+}
+
+
+// A self-send that merges small notes into one Orchard note on the internal change address
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ConsolidationPlan {
+    
+    pub note_ids: Vec<String>,
+    
+    
+    pub total_value: u64,
+    
+    
+    pub fee: u64,
+    
+    
+    pub output_value: u64,
+}
+
+
+// A self-send that turns the input notes into several Orchard notes
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SplitPlan {
+    
+    pub note_ids: Vec<String>,
+    
+    
+    pub output_values: Vec<u64>,
+    
+    
+    pub fee: u64,
+    
+    
+    pub change: u64,
+}
+
+
+// Held by notes that a built but not yet mined transaction spends
+#[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
+pub struct NoteReservation {
+    
+    pub txid: String,
+    
+    
+    pub expiry_height: u32,
+    
+    
+    pub reserved_at: String,
 }
 
 
@@ -139,6 +383,9 @@ impl NoteManager {
                 max_consolidation_notes: 10,
                 enable_note_mixing: true,
                 mixing_rounds: 3,
+                min_confirmations: default_min_confirmations(),
+                min_change_confirmations: default_min_change_confirmations(),
+                split_policy: None,
             },
             commitment_tree: CommitmentTree {
                 depth: 32,
@@ -146,6 +393,8 @@ impl NoteManager {
                 root: vec![0u8; 32],
                 nodes: Vec::new(),
             },
+            reservations: HashMap::new(),
+            chain_height: 0,
         })
     }
     
@@ -189,11 +438,16 @@ impl NoteManager {
             recipient_address,
             memo,
             randomness,
+            rho: None,
             created_at_height: block_height,
             spent_at_height: None,
+            spent_in_txid: None,
             tx_hash,
             merkle_path: None,
             position: None,
+            is_change: false,
+            label: None,
+            tags: Vec::new(),
         };
         
         // Add to commitment tree
@@ -322,6 +576,74 @@ impl NoteManager {
     }
     
     
+    pub fn note_from_decrypted(&self, decrypted: &DecryptedNote, block_height: u32, txid: &[u8]) -> ShieldedNote {
+        ShieldedNote {
+            id: self.generate_note_id(&decrypted.commitment, &decrypted.recipient_address),
+            note_type: decrypted.note_type,
+            value: decrypted.value,
+            commitment: decrypted.commitment.clone(),
+            nullifier: decrypted.nullifier.clone(),
+            recipient_address: decrypted.recipient_address.clone(),
+            memo: Some(decrypted.memo.clone()),
+            randomness: decrypted.rseed.clone(),
+            rho: decrypted.rho.clone(),
+            created_at_height: block_height,
+            spent_at_height: None,
+            spent_in_txid: None,
+            tx_hash: Some(txid.to_vec()),
+            merkle_path: None,
+            position: None,
+            is_change: decrypted.is_change,
+            label: None,
+            tags: Vec::new(),
+        }
+    }
+    
+    
+    // Returns the stored note, or None if it was already known
+    pub fn add_decrypted_note(
+        &mut self,
+        decrypted: &DecryptedNote,
+        block_height: u32,
+        txid: &[u8],
+    ) -> Option<ShieldedNote> {
+        let note = self.note_from_decrypted(decrypted, block_height, txid);
+        if self.notes.contains_key(&note.id) {
+            return None;
+        }
+        
+        self.notes.insert(note.id.clone(), note.clone());
+        Some(note)
+    }
+    
+    
+    // Inserts a note found by the block scanner. A note we already knew about keeps its
+    // spend state but takes the scanner's nullifier and tree position. Returns true if new.
+    pub fn upsert_scanned_note(&mut self, note: ShieldedNote) -> bool {
+        match self.notes.get_mut(&note.id) {
+            Some(existing) => {
+                existing.nullifier = note.nullifier;
+                existing.position = note.position;
+                existing.created_at_height = note.created_at_height;
+                existing.tx_hash = note.tx_hash;
+                false
+            }
+            None => {
+                self.notes.insert(note.id.clone(), note);
+                true
+            }
+        }
+    }
+    
+    
+    pub fn update_note_witness(&mut self, note_id: &str, position: u64, merkle_path: Vec<Vec<u8>>) {
+        if let Some(note) = self.notes.get_mut(note_id) {
+            note.position = Some(position);
+            note.merkle_path = Some(merkle_path);
+        }
+    }
+    
+    
     pub fn add_note(&mut self, note: ShieldedNote) -> NozyResult<()> {
         let note_id = note.id.clone();
         self.notes.insert(note_id.clone(), note);
@@ -334,20 +656,480 @@ impl NoteManager {
     }
     
     
+    pub fn set_note_label(&mut self, note_id: &str, label: Option<String>) -> NozyResult<()> {
+        let note = self.notes.get_mut(note_id)
+            .ok_or_else(|| NozyError::Note(format!("Note {} not found", note_id)))?;
+        note.label = label.filter(|label| !label.trim().is_empty());
+        Ok(())
+    }
+    
+    
+    // Returns false if the note already had the tag
+    pub fn add_note_tag(&mut self, note_id: &str, tag: &str) -> NozyResult<bool> {
+        let tag = normalize_tag(tag)?;
+        let note = self.notes.get_mut(note_id)
+            .ok_or_else(|| NozyError::Note(format!("Note {} not found", note_id)))?;
+        if note.tags.contains(&tag) {
+            return Ok(false);
+        }
+        note.tags.push(tag);
+        Ok(true)
+    }
+    
+    
+    pub fn remove_note_tag(&mut self, note_id: &str, tag: &str) -> NozyResult<bool> {
+        let tag = normalize_tag(tag)?;
+        let note = self.notes.get_mut(note_id)
+            .ok_or_else(|| NozyError::Note(format!("Note {} not found", note_id)))?;
+        let before = note.tags.len();
+        note.tags.retain(|existing| *existing != tag);
+        Ok(note.tags.len() != before)
+    }
+    
+    
+    pub fn get_balance_by_tag(&self, filter: &str) -> u64 {
+        self.get_unspent_notes()
+            .iter()
+            .filter(|note| note.has_tag(filter))
+            .map(|note| note.value)
+            .sum()
+    }
+    
+    
+    // Unspent value per tag; a note with several tags counts towards each of them
+    pub fn get_tag_balances(&self) -> BTreeMap<String, u64> {
+        let mut balances = BTreeMap::new();
+        for note in self.get_unspent_notes() {
+            for tag in &note.tags {
+                *balances.entry(tag.clone()).or_insert(0) += note.value;
+            }
+        }
+        balances
+    }
+    
+    
+    // Folds a duplicate into the note we keep, carrying over any spend the duplicate knew about
+    pub fn merge_duplicate_note(&mut self, keep_id: &str, drop_id: &str) -> NozyResult<()> {
+        let dropped = self.notes.remove(drop_id)
+            .ok_or_else(|| NozyError::Note(format!("Note {} not found", drop_id)))?;
+        self.reservations.remove(drop_id);
+        
+        let keep = self.notes.get_mut(keep_id)
+            .ok_or_else(|| NozyError::Note(format!("Note {} not found", keep_id)))?;
+        if !keep.is_spent() && dropped.is_spent() {
+            keep.spent_at_height = dropped.spent_at_height;
+            keep.spent_in_txid = dropped.spent_in_txid;
+        }
+        for tag in dropped.tags {
+            if !keep.tags.contains(&tag) {
+                keep.tags.push(tag);
+            }
+        }
+        Ok(())
+    }
+    
+    
+    pub fn remove_note(&mut self, note_id: &str) -> Option<ShieldedNote> {
+        self.reservations.remove(note_id);
+        self.notes.remove(note_id)
+    }
+    
+    
+    // Forgets a spend so the next scan over its block can record it again with the txid
+    pub fn clear_spend(&mut self, note_id: &str) -> NozyResult<()> {
+        let note = self.notes.get_mut(note_id)
+            .ok_or_else(|| NozyError::Note(format!("Note {} not found", note_id)))?;
+        note.spent_at_height = None;
+        note.spent_in_txid = None;
+        Ok(())
+    }
+    
+    
+    // Commitment and ID the wallet would derive for a note it created locally
+    pub fn local_commitment(&self, note: &ShieldedNote) -> NozyResult<Vec<u8>> {
+        self.calculate_note_commitment(note.value, &note.recipient_address, &note.randomness, &note.note_type)
+    }
+    
+    
+    pub fn expected_note_id(&self, note: &ShieldedNote) -> String {
+        self.generate_note_id(&note.commitment, &note.recipient_address)
+    }
+    
+    
+    pub fn get_all_notes(&self) -> Vec<&ShieldedNote> {
+        self.notes.values().collect()
+    }
+    
+    
+    pub fn find_note_by_nullifier(&self, nullifier: &[u8]) -> Option<&ShieldedNote> {
+        self.notes.values()
+            .find(|note| note.nullifier.as_deref() == Some(nullifier))
+    }
+    
+    
     pub fn get_unspent_notes(&self) -> Vec<&ShieldedNote> {
         self.notes.values()
-            .filter(|note| note.nullifier.is_none())
+            .filter(|note| !note.is_spent())
             .collect()
     }
     
     
     pub fn get_unspent_notes_by_type(&self, note_type: NoteType) -> Vec<&ShieldedNote> {
         self.notes.values()
-            .filter(|note| note.note_type == note_type && note.nullifier.is_none())
+            .filter(|note| note.note_type == note_type && !note.is_spent())
+            .collect()
+    }
+    
+    
+    pub fn set_chain_height(&mut self, height: u32) {
+        self.chain_height = self.chain_height.max(height);
+    }
+    
+    
+    pub fn chain_height(&self) -> u32 {
+        self.chain_height
+    }
+    
+    
+    pub fn set_confirmation_policy(&mut self, min_confirmations: u32, min_change_confirmations: u32) -> NozyResult<()> {
+        if min_confirmations == 0 || min_change_confirmations == 0 {
+            return Err(NozyError::Config("Confirmation thresholds must be at least 1".to_string()));
+        }
+        self.config.min_confirmations = min_confirmations;
+        self.config.min_change_confirmations = min_change_confirmations;
+        Ok(())
+    }
+    
+    
+    // (received, change)
+    pub fn confirmation_policy(&self) -> (u32, u32) {
+        (self.config.min_confirmations, self.config.min_change_confirmations)
+    }
+    
+    
+    // The block that mined a note counts as its first confirmation
+    pub fn confirmations(&self, note: &ShieldedNote) -> u32 {
+        if note.created_at_height == 0 || self.chain_height < note.created_at_height {
+            return 0;
+        }
+        self.chain_height - note.created_at_height + 1
+    }
+    
+    
+    pub fn is_confirmed(&self, note: &ShieldedNote) -> bool {
+        let required = if note.is_change {
+            self.config.min_change_confirmations
+        } else {
+            self.config.min_confirmations
+        };
+        self.confirmations(note) >= required
+    }
+    
+    
+    // Unspent, deep enough under the confirmation policy, and not claimed by a pending transaction
+    pub fn get_spendable_notes(&self) -> Vec<&ShieldedNote> {
+        self.notes.values()
+            .filter(|note| !note.is_spent() && !self.is_reserved(&note.id) && self.is_confirmed(note))
+            .collect()
+    }
+    
+    
+    // Dust depends on what a spend costs under `rule`; a note worth exactly its marginal fee still breaks even
+    pub fn is_dust(&self, note: &ShieldedNote, rule: FeeRule) -> bool {
+        note.value < rule.dust_threshold()
+    }
+    
+    
+    // Spendable notes that automatic selection under `rule` may use; dust is left to sweep-dust
+    pub fn get_selectable_notes(&self, rule: FeeRule) -> Vec<&ShieldedNote> {
+        self.get_spendable_notes()
+            .into_iter()
+            .filter(|note| !self.is_dust(note, rule))
+            .collect()
+    }
+    
+    
+    pub fn get_dust_notes(&self, rule: FeeRule) -> Vec<&ShieldedNote> {
+        self.notes.values()
+            .filter(|note| !note.is_spent() && self.is_dust(note, rule))
+            .collect()
+    }
+    
+    
+    pub fn get_dust_balance(&self, rule: FeeRule) -> u64 {
+        self.get_dust_notes(rule)
+            .iter()
+            .map(|note| note.value)
+            .sum()
+    }
+    
+    
+    // Folds as much dust as pays for itself into the smallest ordinary note. ZIP-317 charges for at
+    // least 2 logical actions and the bundle is padded to 2 outputs anyway, so the first dust spend
+    // next to a carrier costs nothing extra; every spend after it adds one marginal fee.
+    pub fn plan_dust_sweep(&self) -> Option<DustSweep> {
+        let mut dust: Vec<&ShieldedNote> = self.get_spendable_notes()
+            .into_iter()
+            .filter(|note| self.is_dust(note, FeeRule::Zip317))
+            .collect();
+        dust.sort_by(|a, b| b.value.cmp(&a.value).then(a.id.cmp(&b.id)));
+        
+        let mut carriers = self.get_selectable_notes(FeeRule::Zip317);
+        carriers.sort_by(|a, b| a.value.cmp(&b.value).then(a.id.cmp(&b.id)));
+        let carrier = carriers.first().copied();
+        
+        let mut base = ActionCounts::default();
+        base.add_output(NoteType::Orchard);
+        if let Some(carrier) = carrier {
+            base.add_spend(carrier.note_type);
+        }
+        let base_fee = if carrier.is_some() { fees::conventional_fee(&base) } else { 0 };
+        
+        let mut best: Option<(u64, usize, u64)> = None;
+        let mut counts = base;
+        let mut dust_value = 0u64;
+        for (taken, note) in dust.iter().enumerate() {
+            counts.add_spend(note.note_type);
+            dust_value += note.value;
+            let fee = fees::conventional_fee(&counts);
+            let extra_fee = fee - base_fee;
+            let carrier_value = carrier.map(|carrier| carrier.value).unwrap_or(0);
+            
+            if dust_value > extra_fee && carrier_value + dust_value > fee
+                && best.is_none_or(|(net, _, _)| dust_value - extra_fee > net)
+            {
+                best = Some((dust_value - extra_fee, taken + 1, fee));
+            }
+        }
+        
+        let (_, count, fee) = best?;
+        let dust_value: u64 = dust[..count].iter().map(|note| note.value).sum();
+        let carrier_value = carrier.map(|carrier| carrier.value).unwrap_or(0);
+        Some(DustSweep {
+            dust_note_ids: dust[..count].iter().map(|note| note.id.clone()).collect(),
+            carrier_note_id: carrier.map(|carrier| carrier.id.clone()),
+            dust_value,
+            fee,
+            extra_fee: fee - base_fee,
+            output_value: carrier_value + dust_value - fee,
+        })
+    }
+    
+    
+    // Splits the largest note evenly, or funds `count` notes of `value` each with change left over
+    pub fn plan_split(&self, count: usize, value: Option<u64>) -> NozyResult<SplitPlan> {
+        if count == 0 {
+            return Err(NozyError::InvalidOperation("Split count must be at least 1".to_string()));
+        }
+        let dust_threshold = FeeRule::Zip317.dust_threshold();
+        if value.is_some_and(|value| value < dust_threshold) {
+            return Err(NozyError::InvalidOperation(format!(
+                "Split value must be at least the {} zatoshi dust threshold", dust_threshold
+            )));
+        }
+        
+        let mut candidates = self.get_selectable_notes(FeeRule::Zip317);
+        candidates.sort_by(|a, b| b.value.cmp(&a.value).then(a.id.cmp(&b.id)));
+        
+        let mut outputs = ActionCounts::default();
+        for _ in 0..count {
+            outputs.add_output(NoteType::Orchard);
+        }
+        
+        let Some(value) = value else {
+            let source = candidates.first()
+                .ok_or_else(|| NozyError::InsufficientFunds("No spendable note to split".to_string()))?;
+            let mut counts = outputs;
+            counts.add_spend(source.note_type);
+            let fee = fees::conventional_fee(&counts);
+            let share = source.value.saturating_sub(fee) / count as u64;
+            if share < dust_threshold {
+                return Err(NozyError::InsufficientFunds(format!(
+                    "Note {} is too small to split into {} notes", source.id, count
+                )));
+            }
+            
+            let mut output_values = vec![share; count];
+            output_values[count - 1] += source.value - fee - share * count as u64;
+            return Ok(SplitPlan { note_ids: vec![source.id.clone()], output_values, fee, change: 0 });
+        };
+        
+        let needed = value * count as u64;
+        let mut counts = outputs;
+        let mut selected: Vec<&ShieldedNote> = Vec::new();
+        for note in candidates {
+            selected.push(note);
+            counts.add_spend(note.note_type);
+            let total: u64 = selected.iter().map(|note| note.value).sum();
+            let fee = fees::conventional_fee(&counts);
+            if total < needed + fee {
+                continue;
+            }
+            
+            let mut with_change = counts;
+            with_change.add_output(NoteType::Orchard);
+            let fee_with_change = fees::conventional_fee(&with_change);
+            let (fee, change) = if total > needed + fee_with_change {
+                (fee_with_change, total - needed - fee_with_change)
+            } else {
+                (total - needed, 0)
+            };
+            return Ok(SplitPlan {
+                note_ids: selected.iter().map(|note| note.id.clone()).collect(),
+                output_values: vec![value; count],
+                fee,
+                change,
+            });
+        }
+        
+        Err(NozyError::InsufficientFunds(format!(
+            "Splitting into {} notes of {} zatoshi needs more than the spendable balance", count, value
+        )))
+    }
+    
+    
+    // (enabled, default rounds)
+    pub fn mixing_settings(&self) -> (bool, u32) {
+        (self.config.enable_note_mixing, self.config.mixing_rounds)
+    }
+    
+    
+    pub fn split_policy(&self) -> Option<&SplitPolicy> {
+        self.config.split_policy.as_ref()
+    }
+    
+    
+    pub fn set_split_policy(&mut self, policy: Option<SplitPolicy>) {
+        self.config.split_policy = policy;
+    }
+    
+    
+    // The split the policy calls for, if any. Waits while a transaction is in flight so
+    // unconfirmed split outputs are not split again.
+    pub fn plan_policy_split(&self) -> Option<SplitPlan> {
+        let policy = self.config.split_policy.as_ref()?;
+        if !self.reservations.is_empty() {
+            return None;
+        }
+        
+        let large_enough = |note: &ShieldedNote| note.value >= policy.min_value;
+        let have = self.get_spendable_notes().iter().filter(|note| large_enough(note)).count();
+        if have >= policy.min_notes {
+            return None;
+        }
+        
+        // Spending a note that already counts means one more output is needed to replace it
+        for extra in 0..=policy.min_notes {
+            let plan = self.plan_split(policy.min_notes - have + extra, Some(policy.min_value)).ok()?;
+            let consumed = plan.note_ids.iter()
+                .filter_map(|note_id| self.get_note(note_id))
+                .filter(|note| large_enough(note))
+                .count();
+            let change_counts = usize::from(plan.change >= policy.min_value);
+            if have - consumed + plan.output_values.len() + change_counts >= policy.min_notes {
+                return Some(plan);
+            }
+        }
+        None
+    }
+    
+    
+    pub fn get_pending_confirmation_notes(&self) -> Vec<&ShieldedNote> {
+        self.notes.values()
+            .filter(|note| !note.is_spent() && !self.is_confirmed(note))
             .collect()
     }
     
     
+    pub fn get_spendable_balance(&self) -> u64 {
+        self.get_spendable_notes()
+            .iter()
+            .map(|note| note.value)
+            .sum()
+    }
+    
+    
+    pub fn get_pending_confirmation_balance(&self) -> u64 {
+        self.get_pending_confirmation_notes()
+            .iter()
+            .map(|note| note.value)
+            .sum()
+    }
+    
+    
+    pub fn reserve_notes(&mut self, note_ids: &[String], txid: &str, expiry_height: u32) -> NozyResult<()> {
+        for note_id in note_ids {
+            let note = self.notes.get(note_id)
+                .ok_or_else(|| NozyError::Note(format!("Note {} not found", note_id)))?;
+            if note.is_spent() {
+                return Err(NozyError::Note(format!("Note {} is already spent", note_id)));
+            }
+            if let Some(existing) = self.reservations.get(note_id) {
+                return Err(NozyError::Note(format!(
+                    "Note {} is locked by pending transaction {}", note_id, existing.txid
+                )));
+            }
+        }
+        
+        let reserved_at = chrono::Utc::now().to_rfc3339();
+        for note_id in note_ids {
+            self.reservations.insert(note_id.clone(), NoteReservation {
+                txid: txid.to_string(),
+                expiry_height,
+                reserved_at: reserved_at.clone(),
+            });
+        }
+        Ok(())
+    }
+    
+    
+    // Returns the IDs of the notes that were unlocked
+    pub fn release_reservation(&mut self, txid: &str) -> Vec<String> {
+        let released: Vec<String> = self.reservations.iter()
+            .filter(|(_, reservation)| reservation.txid == txid)
+            .map(|(note_id, _)| note_id.clone())
+            .collect();
+        
+        for note_id in &released {
+            self.reservations.remove(note_id);
+        }
+        released
+    }
+    
+    
+    // A transaction can no longer be mined once the chain is past its expiry height
+    pub fn release_expired_reservations(&mut self, current_height: u32) -> Vec<String> {
+        let expired: Vec<String> = self.reservations.iter()
+            .filter(|(_, reservation)| reservation.expiry_height != 0 && current_height > reservation.expiry_height)
+            .map(|(note_id, _)| note_id.clone())
+            .collect();
+        
+        for note_id in &expired {
+            self.reservations.remove(note_id);
+        }
+        expired
+    }
+    
+    
+    pub fn is_reserved(&self, note_id: &str) -> bool {
+        self.reservations.contains_key(note_id)
+    }
+    
+    
+    pub fn get_reservation(&self, note_id: &str) -> Option<&NoteReservation> {
+        self.reservations.get(note_id)
+    }
+    
+    
+    pub fn get_reserved_balance(&self) -> u64 {
+        self.get_unspent_notes()
+            .iter()
+            .filter(|note| self.is_reserved(&note.id))
+            .map(|note| note.value)
+            .sum()
+    }
+    
+    
     pub fn get_total_balance(&self) -> u64 {
         self.get_unspent_notes()
             .iter()
@@ -364,108 +1146,125 @@ impl NoteManager {
     }
     
     
+    // The amount already includes the fee; use plan_spend to have the fee worked out
     pub fn select_notes_for_spending(
         &self,
         amount: u64,
         strategy: Option<NoteSelectionStrategy>,
     ) -> NozyResult<Vec<&ShieldedNote>> {
         let strategy = strategy.unwrap_or(self.config.default_strategy.clone());
-        let mut unspent_notes = self.get_unspent_notes();
-        
-        match strategy {
-            NoteSelectionStrategy::PrivacyFirst => {
-                // Prefer Orchard notes first
-                unspent_notes.sort_by(|a, b| {
-                    match (a.note_type, b.note_type) {
-                        (NoteType::Orchard, NoteType::Sapling) => std::cmp::Ordering::Less,
-                        (NoteType::Sapling, NoteType::Orchard) => std::cmp::Ordering::Greater,
-                        _ => std::cmp::Ordering::Equal,
-                    }
-                });
-            }
-            NoteSelectionStrategy::EfficiencyFirst => {
-                // Prefer Sapling notes first (smaller proofs)
-                unspent_notes.sort_by(|a, b| {
-                    match (a.note_type, b.note_type) {
-                        (NoteType::Sapling, NoteType::Orchard) => std::cmp::Ordering::Less,
-                        (NoteType::Orchard, NoteType::Sapling) => std::cmp::Ordering::Greater,
-                        _ => std::cmp::Ordering::Equal,
-                    }
-                });
-            }
-            NoteSelectionStrategy::ValueBased => {
-                // Prefer larger notes
-                unspent_notes.sort_by(|a, b| b.value.cmp(&a.value));
+        let rule = FeeRule::Fixed(0);
+        let selectable = self.get_selectable_notes(rule);
+        let plan = selection::plan(&selectable, amount, NoteType::Orchard, strategy, rule)?;
+        
+        Ok(plan.note_ids.iter()
+            .filter_map(|note_id| self.notes.get(note_id))
+            .collect())
+    }
+    
+    
+    // Chooses notes for a payment and works out its ZIP-317 fee and change
+    pub fn plan_spend(
+        &self,
+        amount: u64,
+        recipient: NoteType,
+        strategy: Option<NoteSelectionStrategy>,
+    ) -> NozyResult<SelectionPlan> {
+        let strategy = strategy.unwrap_or(self.config.default_strategy.clone());
+        let rule = FeeRule::Zip317;
+        let selectable = self.get_selectable_notes(rule);
+        selection::plan(&selectable, amount, recipient, strategy, rule)
+    }
+    
+    
+    // Manual coin control: funds a payment from exactly these notes, in the given order
+    pub fn select_specific_notes(&self, note_ids: &[String], amount: u64) -> NozyResult<Vec<&ShieldedNote>> {
+        if note_ids.is_empty() {
+            return Err(NozyError::InvalidOperation("No notes chosen".to_string()));
+        }
+        
+        let mut selected: Vec<&ShieldedNote> = Vec::new();
+        for note_id in note_ids {
+            let note = self.get_note(note_id)
+                .ok_or_else(|| NozyError::Note(format!("Note {} not found", note_id)))?;
+            if selected.iter().any(|chosen| chosen.id == note.id) {
+                return Err(NozyError::InvalidOperation(format!("Note {} was chosen twice", note_id)));
             }
-            NoteSelectionStrategy::AgeBased => {
-                // Prefer older notes
-                unspent_notes.sort_by(|a, b| a.created_at_height.cmp(&b.created_at_height));
+            if note.is_spent() {
+                return Err(NozyError::Note(format!("Note {} is already spent", note_id)));
             }
-            NoteSelectionStrategy::Balanced => {
-                // Mix of both types
-                // Keep current order
+            if let Some(reservation) = self.get_reservation(note_id) {
+                return Err(NozyError::Note(format!(
+                    "Note {} is locked by pending transaction {}", note_id, reservation.txid
+                )));
             }
-        }
-        
-        // Select notes to cover the amount
-        let mut selected_notes = Vec::new();
-        let mut total_selected = 0u64;
-        
-        for note in unspent_notes {
-            if total_selected >= amount {
-                break;
+            if !self.is_confirmed(note) {
+                return Err(NozyError::Note(format!(
+                    "Note {} has {} confirmations; the policy requires more", note_id, self.confirmations(note)
+                )));
             }
-            selected_notes.push(note);
-            total_selected += note.value;
+            selected.push(note);
         }
         
-        if total_selected < amount {
-            return Err(crate::error::NozyError::InsufficientFunds(
-                format!("Insufficient funds. Required: {}, Available: {}", amount, total_selected)
+        let total: u64 = selected.iter().map(|note| note.value).sum();
+        if total < amount {
+            return Err(NozyError::InsufficientFunds(
+                format!("Chosen notes hold {} zatoshi but {} are required", total, amount)
             ));
         }
-        
-        Ok(selected_notes)
+        Ok(selected)
     }
     
     
     pub fn mark_note_spent(&mut self, note_id: &str, spent_height: u32) -> NozyResult<()> {
-        // Get the note data first to avoid borrowing conflicts
-        let note_data = if let Some(note) = self.notes.get(note_id) {
-            note.clone()
-        } else {
-            return Ok(());
-        };
-        
-        // Generate nullifier from the cloned data
-        let nullifier = self.generate_note_nullifier(&note_data)?;
-        
-        // Now update the note
         if let Some(note) = self.notes.get_mut(note_id) {
             note.spent_at_height = Some(spent_height);
-            note.nullifier = Some(nullifier);
         }
+        self.reservations.remove(note_id);
         
         Ok(())
     }
     
     
-    fn generate_note_nullifier(&self, note: &ShieldedNote) -> NozyResult<Vec<u8>> {
-        let mut hasher = Params::new()
-            .hash_length(32)
-            .to_state();
-        
-        hasher.update(&note.commitment);
-        hasher.update(&note.randomness);
+    // Returns the IDs of the notes that changed state
+    pub fn mark_spent_by_nullifiers(
+        &mut self,
+        nullifiers: &[Vec<u8>],
+        height: u32,
+        txid: &[u8],
+    ) -> Vec<String> {
+        let mut spent_ids = Vec::new();
         
-        // Add note type to nullifier
-        let type_bytes = match note.note_type {
-            NoteType::Orchard => b"orchard_nullifier",
-            NoteType::Sapling => b"sapling_nullifier",
-        };
-        hasher.update(type_bytes);
+        for note in self.notes.values_mut() {
+            if note.is_spent() {
+                continue;
+            }
+            
+            let revealed = note.nullifier.as_ref()
+                .is_some_and(|nf| nullifiers.contains(nf));
+            
+            if revealed {
+                note.spent_at_height = Some(height);
+                note.spent_in_txid = Some(txid.to_vec());
+                spent_ids.push(note.id.clone());
+            }
+        }
         
-        Ok(hasher.finalize().as_bytes().to_vec())
+        for note_id in &spent_ids {
+            self.reservations.remove(note_id);
+        }
+        spent_ids
+    }
+    
+    
+    // Notes added without a nullifier get one once the keys can derive it; returns how many did
+    pub fn derive_missing_nullifiers(&mut self, keys: &ViewingKeys) -> usize {
+        let mut derived = 0;
+        for note in self.notes.values_mut().filter(|note| note.nullifier.is_none()) {
+            note.nullifier = keys.note_nullifier(note);
+            derived += usize::from(note.nullifier.is_some());
+        }
+        derived
     }
     
     
@@ -479,56 +1278,306 @@ impl NoteManager {
     }
     
     
-    pub fn consolidate_notes(&mut self) -> NozyResult<Vec<ShieldedNote>> {
-        if !self.config.enable_consolidation {
-            return Ok(Vec::new());
+    // Small notes to merge into one Orchard note, largest first so each input pays its way
+    pub fn plan_consolidation(&self, force: bool) -> Option<ConsolidationPlan> {
+        if !self.config.enable_consolidation && !force {
+            return None;
         }
         
-        // Collect note IDs first to avoid borrowing issues
-        let small_note_ids: Vec<String> = self.notes.values()
-            .filter(|note| {
-                note.nullifier.is_none() && 
-                note.value < self.config.min_consolidation_value
-            })
-            .map(|note| note.id.clone())
+        let mut small_notes: Vec<&ShieldedNote> = self.get_selectable_notes(FeeRule::Zip317)
+            .into_iter()
+            .filter(|note| note.value < self.config.min_consolidation_value)
             .collect();
+        small_notes.sort_by(|a, b| b.value.cmp(&a.value).then(a.id.cmp(&b.id)));
+        small_notes.truncate(self.config.max_consolidation_notes);
+        if small_notes.len() < 2 {
+            return None;
+        }
         
-        if small_note_ids.len() < 2 {
-            return Ok(Vec::new());
+        let mut counts = ActionCounts::default();
+        for note in &small_notes {
+            counts.add_spend(note.note_type);
+        }
+        counts.add_output(NoteType::Orchard);
+        let fee = fees::conventional_fee(&counts);
+        let total_value: u64 = small_notes.iter().map(|note| note.value).sum();
+        if total_value <= fee {
+            return None;
         }
         
-        // Get the notes by ID to avoid borrowing conflicts
-        let mut small_notes: Vec<&ShieldedNote> = small_note_ids.iter()
-            .filter_map(|id| self.notes.get(id))
+        Some(ConsolidationPlan {
+            note_ids: small_notes.iter().map(|note| note.id.clone()).collect(),
+            total_value,
+            fee,
+            output_value: total_value - fee,
+        })
+    }
+}
+
+
+/// Collects every nullifier a transaction reveals, from both Orchard actions and Sapling spends.
+pub fn revealed_nullifiers(tx: &Transaction) -> Vec<Vec<u8>> {
+    let mut nullifiers = Vec::new();
+    
+    if let Some(bundle) = tx.orchard_bundle() {
+        for action in bundle.actions().iter() {
+            nullifiers.push(action.nullifier().to_bytes().to_vec());
+        }
+    }
+    
+    if let Some(bundle) = tx.sapling_bundle() {
+        for spend in bundle.shielded_spends() {
+            nullifiers.push(spend.nullifier().0.to_vec());
+        }
+    }
+    
+    nullifiers
+}
+
+// Tags are case-insensitive paths; surrounding slashes and whitespace are dropped
+pub fn normalize_tag(tag: &str) -> NozyResult<String> {
+    let tag = tag.trim().trim_matches('/').to_lowercase();
+    if tag.is_empty() || tag.chars().any(char::is_whitespace) || tag.contains("//") {
+        return Err(NozyError::InvalidOperation(format!("Invalid tag '{}': use words separated by '/'", tag)));
+    }
+    Ok(tag)
+}
+
+
+// "revenue" matches "revenue" and "revenue/customer-42" but not "revenues"
+pub fn tag_matches(tag: &str, filter: &str) -> bool {
+    let filter = filter.trim().trim_matches('/').to_lowercase();
+    tag == filter || tag.strip_prefix(&filter).is_some_and(|rest| rest.starts_with('/'))
+}
+
+// Lets tests fill a manager in one line; the ids come back in the order the notes were given
+#[cfg(test)]
+impl NoteManager {
+    
+    pub(crate) fn with_test_notes(notes: &[(u64, NoteType)]) -> (Self, Vec<String>) {
+        let mut manager = NoteManager::new(&crate::config::NozyConfig::default()).unwrap();
+        let ids = notes.iter()
+            .map(|(value, note_type)| {
+                let note = manager.create_note(*value, "u1test".to_string(), None, *note_type, 10, None).unwrap();
+                let id = note.id.clone();
+                manager.add_note(note).unwrap();
+                id
+            })
             .collect();
+        (manager, ids)
+    }
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    
+    #[test]
+    fn test_spend_detected_by_nullifier() {
+        let network = Network::TestNetwork;
+        let (_, spending_key, keys) = ViewingKeys::test_wallet();
+        let mut manager = NoteManager::new(&crate::config::NozyConfig::default()).unwrap();
         
-        // Sort by value and take the smallest notes
-        small_notes.sort_by(|a, b| a.value.cmp(&b.value));
-        let notes_to_consolidate: Vec<&ShieldedNote> = small_notes.clone()
-            .into_iter()
-            .take(self.config.max_consolidation_notes.min(small_notes.len()))
-            .collect();
+        // A note that never came from the chain has no nullifier to guess
+        let unknown = manager.create_note(100_000, "u1test".to_string(), None, NoteType::Orchard, 10, None).unwrap();
+        assert!(unknown.nullifier.is_none());
+        assert!(keys.note_nullifier(&unknown).is_none());
         
-        let total_value: u64 = notes_to_consolidate.iter().map(|note| note.value).sum();
-        let recipient_address = notes_to_consolidate[0].recipient_address.clone();
+        let mut trees = crate::sync::TreeState::from_raw(&crate::zebra_integration::RawTreeState { height: 0, sapling: None, orchard: None }).unwrap();
+        let mut note = trees.spendable_orchard_note(&spending_key, network, 100_000);
+        let expected = note.nullifier.take().unwrap();
+        manager.add_note(note.clone()).unwrap();
+        assert_eq!(manager.derive_missing_nullifiers(&keys), 1);
+        let nullifier = manager.get_note(&note.id).unwrap().nullifier.clone().unwrap();
+        assert_eq!(nullifier, expected);
         
-        // Create consolidated note
-        let consolidated_note = self.create_note(
-            total_value,
-            recipient_address,
-            None, // No memo for consolidated notes
-            NoteType::Orchard, // Prefer Orchard for consolidation
-            0, // Will be updated when actually created
-            None,
-        )?;
+        assert_eq!(manager.get_unspent_notes().len(), 1);
         
-        // Mark original notes as spent
-        for note_id in small_note_ids {
-            self.mark_note_spent(&note_id, 0)?;
-        }
+        let unrelated = vec![vec![7u8; 32]];
+        assert!(manager.mark_spent_by_nullifiers(&unrelated, 20, &[1u8; 32]).is_empty());
+        
+        let spent = manager.mark_spent_by_nullifiers(&[nullifier], 21, &[2u8; 32]);
+        assert_eq!(spent, vec![note.id.clone()]);
+        
+        let stored = manager.get_note(&note.id).unwrap();
+        assert_eq!(stored.spent_at_height, Some(21));
+        assert_eq!(stored.spent_in_txid, Some(vec![2u8; 32]));
+        assert!(manager.get_unspent_notes().is_empty());
+    }
+    
+    #[test]
+    fn test_reserved_notes_are_not_selected_twice() {
+        let (mut manager, ids) = NoteManager::with_test_notes(&[(50_000, NoteType::Orchard), (50_000, NoteType::Orchard)]);
+        let (first, second) = (&ids[0], &ids[1]);
+        manager.set_chain_height(100);
+        
+        manager.reserve_notes(std::slice::from_ref(first), "pending-tx", 140).unwrap();
+        assert!(manager.reserve_notes(std::slice::from_ref(first), "other-tx", 140).is_err());
+        assert_eq!(manager.get_reserved_balance(), 50_000);
+        
+        let selected = manager.select_notes_for_spending(40_000, None).unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(&selected[0].id, second);
+        assert!(manager.select_notes_for_spending(60_000, None).is_err());
+        
+        assert!(manager.release_expired_reservations(140).is_empty());
+        assert_eq!(manager.release_expired_reservations(141), vec![first.clone()]);
+        assert_eq!(manager.get_reserved_balance(), 0);
+        
+        manager.reserve_notes(std::slice::from_ref(second), "failed-tx", 200).unwrap();
+        assert_eq!(manager.release_reservation("failed-tx"), vec![second.clone()]);
+    }
+    
+    #[test]
+    fn test_confirmation_policy_gates_spending() {
+        let mut manager = NoteManager::new(&crate::config::NozyConfig::default()).unwrap();
+        
+        let received = manager.create_note(70_000, "u1test".to_string(), None, NoteType::Orchard, 100, None).unwrap();
+        let mut change = manager.create_note(30_000, "u1change".to_string(), None, NoteType::Orchard, 105, None).unwrap();
+        change.is_change = true;
+        manager.add_note(received.clone()).unwrap();
+        manager.add_note(change.clone()).unwrap();
+        
+        // Tip unknown: nothing is spendable yet
+        assert_eq!(manager.get_spendable_balance(), 0);
+        assert_eq!(manager.get_pending_confirmation_balance(), 100_000);
         
-        Ok(vec![consolidated_note])
+        // 3 confirmations for the change, 8 for the received note
+        manager.set_chain_height(107);
+        assert_eq!(manager.confirmations(&change), 3);
+        assert_eq!(manager.get_spendable_balance(), 30_000);
+        assert!(manager.select_notes_for_spending(50_000, None).is_err());
+        
+        manager.set_chain_height(109);
+        assert_eq!(manager.get_spendable_balance(), 100_000);
+        assert_eq!(manager.select_notes_for_spending(90_000, None).unwrap().len(), 2);
+        
+        manager.set_confirmation_policy(20, 20).unwrap();
+        assert_eq!(manager.get_pending_confirmation_balance(), 100_000);
+        assert!(manager.set_confirmation_policy(0, 1).is_err());
+    }
+    
+    #[test]
+    fn test_manual_selection_uses_exactly_the_chosen_notes() {
+        let (mut manager, ids) = NoteManager::with_test_notes(&[(500_000, NoteType::Orchard), (40_000, NoteType::Orchard)]);
+        let (large, small) = (&ids[0], &ids[1]);
+        manager.set_chain_height(100);
+        
+        let selected = manager.select_specific_notes(std::slice::from_ref(small), 30_000).unwrap();
+        assert_eq!(selected.len(), 1);
+        assert_eq!(&selected[0].id, small);
+        
+        assert!(manager.select_specific_notes(std::slice::from_ref(small), 50_000).is_err());
+        assert!(manager.select_specific_notes(&[small.clone(), small.clone()], 10_000).is_err());
+        assert!(manager.select_specific_notes(&["missing".to_string()], 10_000).is_err());
+        
+        manager.reserve_notes(std::slice::from_ref(large), "pending-tx", 140).unwrap();
+        assert!(manager.select_specific_notes(std::slice::from_ref(large), 10_000).is_err());
+    }
+    
+    #[test]
+    fn test_dust_is_skipped_and_swept_when_economical() {
+        let (mut manager, ids) = NoteManager::with_test_notes(&[
+            (100_000, NoteType::Orchard),
+            (4_500, NoteType::Orchard),
+            (4_000, NoteType::Orchard),
+            (300, NoteType::Orchard),
+        ]);
+        manager.set_chain_height(100);
+        
+        assert_eq!(manager.get_dust_notes(FeeRule::Zip317).len(), 3);
+        assert_eq!(manager.get_selectable_notes(FeeRule::Zip317).len(), 1);
+        let break_even = manager.create_note(fees::MARGINAL_FEE, "u1test".to_string(), None, NoteType::Orchard, 10, None).unwrap();
+        assert!(!manager.is_dust(&break_even, FeeRule::Zip317));
+        assert!(manager.plan_spend(91_000, NoteType::Orchard, None).is_err());
+        
+        // With the fee already fixed an extra input costs nothing, so no note is dust
+        assert!(manager.get_dust_notes(FeeRule::Fixed(10_000)).is_empty());
+        assert!(manager.select_notes_for_spending(101_000, None).unwrap().len() > 1);
+        
+        // The largest dust note fits in the 2 grace actions for free;
+        // every further note would add 5_000 to the fee for less than that in value
+        let sweep = manager.plan_dust_sweep().unwrap();
+        assert_eq!(sweep.carrier_note_id, Some(ids[0].clone()));
+        assert_eq!(sweep.dust_note_ids.len(), 1);
+        assert_eq!(sweep.dust_value, 4_500);
+        assert_eq!(sweep.fee, 10_000);
+        assert_eq!(sweep.extra_fee, 0);
+        assert_eq!(sweep.output_value, 100_000 + 4_500 - 10_000);
+    }
+    
+    #[test]
+    fn test_split_plans_and_policy() {
+        let (mut manager, ids) = NoteManager::with_test_notes(&[(1_000_000, NoteType::Orchard)]);
+        manager.set_chain_height(100);
+        
+        // One spend and four outputs are four logical actions
+        let even = manager.plan_split(4, None).unwrap();
+        assert_eq!(even.fee, 20_000);
+        assert_eq!(even.output_values, vec![245_000; 4]);
+        assert_eq!(even.change, 0);
+        
+        let sized = manager.plan_split(3, Some(100_000)).unwrap();
+        assert_eq!(sized.output_values, vec![100_000; 3]);
+        assert_eq!(sized.fee, 20_000);
+        assert_eq!(sized.change, 680_000);
+        assert!(manager.plan_split(3, Some(1_000)).is_err());
+        
+        assert!(manager.plan_policy_split().is_none());
+        manager.set_split_policy(Some(SplitPolicy { min_notes: 4, min_value: 100_000 }));
+        
+        // The change counts as the fourth note
+        let plan = manager.plan_policy_split().unwrap();
+        assert_eq!(plan.output_values.len(), 3);
+        assert_eq!(plan.note_ids, ids);
+        
+        manager.reserve_notes(&ids, "split-tx", 140).unwrap();
+        assert!(manager.plan_policy_split().is_none());
+    }
+    
+    #[test]
+    fn test_consolidation_plans_a_real_self_send() {
+        let (mut manager, _) = NoteManager::with_test_notes(&[
+            (6_000, NoteType::Orchard),
+            (7_000, NoteType::Orchard),
+            (8_000, NoteType::Orchard),
+            (50_000, NoteType::Orchard),
+        ]);
+        manager.set_chain_height(100);
+        
+        // Only notes under min_consolidation_value are merged, and nothing is marked spent yet
+        let plan = manager.plan_consolidation(false).unwrap();
+        assert_eq!(plan.note_ids.len(), 3);
+        assert_eq!(plan.total_value, 21_000);
+        assert_eq!(plan.fee, 15_000);
+        assert_eq!(plan.output_value, 6_000);
+        assert_eq!(manager.get_unspent_notes().len(), 4);
+        
+        manager.config.max_consolidation_notes = 1;
+        assert!(manager.plan_consolidation(false).is_none());
+    }
+    
+    #[test]
+    fn test_tags_filter_balances_by_category() {
+        let (mut manager, ids) = NoteManager::with_test_notes(&[
+            (40_000, NoteType::Orchard),
+            (25_000, NoteType::Orchard),
+            (10_000, NoteType::Orchard),
+        ]);
+        
+        assert!(manager.add_note_tag(&ids[0], " Revenue/Customer-42/ ").unwrap());
+        assert!(!manager.add_note_tag(&ids[0], "revenue/customer-42").unwrap());
+        manager.add_note_tag(&ids[1], "revenue/customer-7").unwrap();
+        manager.add_note_tag(&ids[2], "payroll").unwrap();
+        assert!(manager.add_note_tag(&ids[2], "not a tag").is_err());
+        
+        assert_eq!(manager.get_balance_by_tag("revenue"), 65_000);
+        assert_eq!(manager.get_balance_by_tag("revenue/customer-42"), 40_000);
+        assert_eq!(manager.get_balance_by_tag("rev"), 0);
+        assert_eq!(manager.get_tag_balances().get("payroll"), Some(&10_000));
+        
+        assert!(manager.remove_note_tag(&ids[2], "PAYROLL").unwrap());
+        assert_eq!(manager.get_balance_by_tag("payroll"), 0);
     }
 }
-
- 
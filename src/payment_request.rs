@@ -0,0 +1,309 @@
+//! ZIP-321 `zcash:` payment request URIs
+
+use crate::error::{NozyError, NozyResult};
+use crate::payouts::{self, PayoutRow, MAX_MEMO_BYTES};
+use crate::selection;
+use base64::engine::general_purpose::URL_SAFE_NO_PAD;
+use base64::Engine;
+use percent_encoding::{percent_decode_str, utf8_percent_encode, AsciiSet, NON_ALPHANUMERIC};
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use zcash_keys::address::Address;
+use zcash_protocol::consensus::Network;
+
+const SCHEME: &str = "zcash:";
+
+// Everything outside the RFC 3986 unreserved set is escaped in labels and messages
+const ESCAPED: &AsciiSet = &NON_ALPHANUMERIC.remove(b'-').remove(b'.').remove(b'_').remove(b'~');
+
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct RequestedPayment {
+    
+    pub address: String,
+    
+    
+    // Requests may leave the amount to the payer
+    pub amount: Option<u64>,
+    
+    
+    pub memo: Option<Vec<u8>>,
+    
+    
+    pub label: Option<String>,
+    
+    
+    pub message: Option<String>,
+}
+
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PaymentRequest {
+    
+    pub payments: Vec<RequestedPayment>,
+}
+
+impl PaymentRequest {
+    
+    // Anything ZIP-321 does not allow is an error, so a mistyped invoice is never paid differently than intended
+    pub fn parse(uri: &str, network: &Network) -> NozyResult<Self> {
+        let invalid = |reason: String| NozyError::InvalidOperation(format!("Invalid payment request: {}", reason));
+        
+        let rest = uri.get(..SCHEME.len())
+            .filter(|scheme| scheme.eq_ignore_ascii_case(SCHEME))
+            .map(|_| &uri[SCHEME.len()..])
+            .ok_or_else(|| invalid("it must start with 'zcash:'".to_string()))?;
+        let (path, query) = match rest.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (rest, None),
+        };
+        
+        let mut payments: BTreeMap<usize, RequestedPayment> = BTreeMap::new();
+        if !path.is_empty() {
+            payments.entry(0).or_default().address = path.to_string();
+        }
+        
+        if let Some(query) = query {
+            for param in query.split('&') {
+                let (key, value) = param.split_once('=')
+                    .ok_or_else(|| invalid(format!("parameter {:?} has no value", param)))?;
+                let (name, index) = parse_key(key).map_err(invalid)?;
+                let payment = payments.entry(index).or_default();
+                let duplicate = || invalid(format!("{} is given more than once", key));
+                
+                match name {
+                    "address" => {
+                        if !payment.address.is_empty() {
+                            return Err(duplicate());
+                        }
+                        if value.is_empty() {
+                            return Err(invalid(format!("{} is empty", key)));
+                        }
+                        payment.address = value.to_string();
+                    }
+                    "amount" => {
+                        if payment.amount.is_some() {
+                            return Err(duplicate());
+                        }
+                        payment.amount = Some(parse_amount(value).map_err(|e| invalid(format!("{}: {}", key, e)))?);
+                    }
+                    "memo" => {
+                        if payment.memo.is_some() {
+                            return Err(duplicate());
+                        }
+                        payment.memo = Some(decode_memo(value).map_err(|e| invalid(format!("{}: {}", key, e)))?);
+                    }
+                    "label" | "message" => {
+                        let decoded = percent_decode_str(value)
+                            .decode_utf8()
+                            .map_err(|_| invalid(format!("{} is not valid percent-encoded UTF-8", key)))?
+                            .into_owned();
+                        let field = if name == "label" { &mut payment.label } else { &mut payment.message };
+                        if field.replace(decoded).is_some() {
+                            return Err(duplicate());
+                        }
+                    }
+                    name if name.starts_with("req-") => {
+                        return Err(invalid(format!("required parameter {} is not supported", key)));
+                    }
+                    // Optional parameters this wallet does not understand are ignored, as ZIP-321 asks
+                    _ => {}
+                }
+            }
+        }
+        if payments.is_empty() {
+            return Err(invalid("it requests no payments".to_string()));
+        }
+        for (index, payment) in &payments {
+            if payment.address.is_empty() {
+                return Err(invalid(format!("payment {} has no address", index)));
+            }
+            check_address(network, &payment.address, payment.memo.is_some()).map_err(invalid)?;
+        }
+        
+        Ok(Self { payments: payments.into_values().collect() })
+    }
+    
+    
+    // The first payment goes in the path and the rest are indexed from 1
+    pub fn to_uri(&self) -> String {
+        let mut uri = String::from(SCHEME);
+        let mut params = Vec::new();
+        
+        for (index, payment) in self.payments.iter().enumerate() {
+            let suffix = if index == 0 { String::new() } else { format!(".{}", index) };
+            if index == 0 {
+                uri.push_str(&payment.address);
+            } else {
+                params.push(format!("address{}={}", suffix, payment.address));
+            }
+            if let Some(amount) = payment.amount {
+                params.push(format!("amount{}={}", suffix, format_amount(amount)));
+            }
+            if let Some(memo) = &payment.memo {
+                params.push(format!("memo{}={}", suffix, URL_SAFE_NO_PAD.encode(memo)));
+            }
+            if let Some(label) = &payment.label {
+                params.push(format!("label{}={}", suffix, utf8_percent_encode(label, ESCAPED)));
+            }
+            if let Some(message) = &payment.message {
+                params.push(format!("message{}={}", suffix, utf8_percent_encode(message, ESCAPED)));
+            }
+        }
+        
+        if !params.is_empty() {
+            uri.push('?');
+            uri.push_str(&params.join("&"));
+        }
+        uri
+    }
+    
+    
+    // Rows for the batch planner; `line` holds each payment's 1-based position in the request
+    pub fn payout_rows(&self, network: &Network) -> NozyResult<Vec<PayoutRow>> {
+        self.payments.iter()
+            .enumerate()
+            .map(|(index, payment)| {
+                let amount = payment.amount
+                    .filter(|amount| *amount > 0)
+                    .ok_or_else(|| NozyError::InvalidOperation(format!(
+                        "Payment {} to {} has no amount; ask the merchant for a complete request", index + 1, payment.address
+                    )))?;
+                Ok(PayoutRow {
+                    line: index + 1,
+                    address: payment.address.clone(),
+                    amount,
+                    memo: payment.memo.clone(),
+                    pool: selection::recipient_pool(network, &payment.address).map_err(NozyError::InvalidOperation)?,
+                })
+            })
+            .collect()
+    }
+}
+
+
+// Splits `name.index` into the name and a payment index from 1 to 9999, or 0 when there is no suffix
+fn parse_key(key: &str) -> Result<(&str, usize), String> {
+    let Some((name, index)) = key.split_once('.') else {
+        return Ok((key, 0));
+    };
+    let valid = !index.is_empty()
+        && index.len() <= 4
+        && !index.starts_with('0')
+        && index.chars().all(|c| c.is_ascii_digit());
+    if !valid {
+        return Err(format!("{} has an invalid payment index", key));
+    }
+    let index = index.parse().map_err(|_| format!("{} has an invalid payment index", key))?;
+    Ok((name, index))
+}
+
+
+// ZIP-321 amounts need digits on both sides of the point, when there is one
+fn parse_amount(text: &str) -> Result<u64, String> {
+    if text.starts_with('.') || text.ends_with('.') {
+        return Err(format!("{:?} is not a ZEC amount", text));
+    }
+    payouts::parse_zec(text)
+}
+
+
+fn decode_memo(text: &str) -> Result<Vec<u8>, String> {
+    let memo = URL_SAFE_NO_PAD.decode(text).map_err(|_| "memo is not unpadded base64url".to_string())?;
+    if memo.len() > MAX_MEMO_BYTES {
+        return Err(format!("memo is {} bytes; at most {} fit", memo.len(), MAX_MEMO_BYTES));
+    }
+    Ok(memo)
+}
+
+
+fn check_address(network: &Network, address: &str, has_memo: bool) -> Result<(), String> {
+    let shielded = match Address::decode(network, address) {
+        Some(Address::Unified(unified)) => unified.orchard().is_some() || unified.sapling().is_some(),
+        Some(Address::Sapling(_)) => true,
+        Some(_) => false,
+        None => return Err(format!("{} is not a valid address on this network", address)),
+    };
+    if has_memo && !shielded {
+        return Err(format!("{} is transparent and cannot receive a memo", address));
+    }
+    Ok(())
+}
+
+
+// Shortest decimal form, e.g. 150000000 zatoshi is "1.5"
+pub fn format_amount(zatoshi: u64) -> String {
+    let whole = zatoshi / 100_000_000;
+    let fraction = zatoshi % 100_000_000;
+    if fraction == 0 {
+        return whole.to_string();
+    }
+    let digits = format!("{:08}", fraction);
+    format!("{}.{}", whole, digits.trim_end_matches('0'))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::ViewingKeys;
+    use zcash_transparent::address::TransparentAddress;
+    
+    #[test]
+    fn test_zip321_round_trip_and_strict_errors() {
+        let network = Network::TestNetwork;
+        let (_, _, keys) = ViewingKeys::test_wallet();
+        let orchard = keys.orchard_address_at(1).unwrap();
+        let sapling = keys.sapling_change_address();
+        let transparent = Address::Transparent(TransparentAddress::PublicKeyHash([7; 20])).encode(&network);
+        
+        let uri = format!(
+            "zcash:?address={}&amount=1.5&memo=VGhpcyBpcyBhIHNpbXBsZSBtZW1vLg&message=Thank%20you%20for%20your%20purchase&address.1={}&amount.1=0.0001&label.1=Caf%C3%A9",
+            orchard, sapling
+        );
+        let request = PaymentRequest::parse(&uri, &network).unwrap();
+        assert_eq!(request.payments.len(), 2);
+        assert_eq!(request.payments[0].amount, Some(150_000_000));
+        assert_eq!(request.payments[0].memo.as_deref(), Some(&b"This is a simple memo."[..]));
+        assert_eq!(request.payments[0].message.as_deref(), Some("Thank you for your purchase"));
+        assert_eq!(request.payments[1].amount, Some(10_000));
+        assert_eq!(request.payments[1].label.as_deref(), Some("Café"));
+        
+        // Generated URIs parse back to the same request
+        let generated = request.to_uri();
+        assert!(generated.starts_with(&format!("zcash:{}?amount=1.5", orchard)));
+        assert_eq!(PaymentRequest::parse(&generated, &network).unwrap(), request);
+        
+        let rows = request.payout_rows(&network).unwrap();
+        assert_eq!(rows[1].pool, crate::notes::NoteType::Sapling);
+        
+        let bad = [
+            format!("bitcoin:{}", orchard),
+            format!("zcash:{}?amount=1&amount=2", orchard),
+            format!("zcash:{}?amount=1.", orchard),
+            format!("zcash:{}?amount=0.000000001", orchard),
+            format!("zcash:{}?amount=21000001", orchard),
+            format!("zcash:{}?memo=not+base64", orchard),
+            format!("zcash:{}?amount.0=1", orchard),
+            format!("zcash:{}?amount.01=1", orchard),
+            format!("zcash:{}?req-future=1", orchard),
+            format!("zcash:{}?address={}", orchard, sapling),
+            format!("zcash:{}?amount.1=1", orchard),
+            format!("zcash:{}?label=%FF", orchard),
+            format!("zcash:{}?amount", orchard),
+            format!("zcash:{}?amount=1&memo=AQ", transparent),
+            "zcash:?amount=1".to_string(),
+        ];
+        for uri in &bad {
+            assert!(PaymentRequest::parse(uri, &network).is_err(), "{} should be rejected", uri);
+        }
+        
+        // Unknown optional parameters are ignored, and amounts may be left to the payer
+        let open = PaymentRequest::parse(&format!("zcash:{}?future=1", orchard), &network).unwrap();
+        assert_eq!(open.payments[0].amount, None);
+        assert!(open.payout_rows(&network).is_err());
+        assert!(PaymentRequest::parse(&format!("zcash:{}?amount=1", transparent), &network).is_ok());
+        assert_eq!(format_amount(100_000_000), "1");
+        assert_eq!(format_amount(1), "0.00000001");
+    }
+}
@@ -0,0 +1,346 @@
+//! Batch payouts read from a CSV file and packed into as few transactions as possible
+
+use crate::error::{NozyError, NozyResult};
+use crate::fees::{self, ActionCounts};
+use crate::notes::{NoteType, ShieldedNote};
+use crate::selection;
+use serde::{Deserialize, Serialize};
+use zcash_protocol::consensus::Network;
+
+// Every logical action adds proving work and bytes to the transaction, and a batch is proven in
+// one go; 50 keeps each batch quick to prove on an ordinary machine while still paying dozens of
+// rows per fee. `tx send-many --max-actions` overrides it.
+pub const DEFAULT_BATCH_ACTIONS: usize = 50;
+
+pub const MAX_MEMO_BYTES: usize = 512;
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayoutRow {
+    
+    // 1-based line in the file, so problems can be reported where the user will look
+    pub line: usize,
+    
+    
+    pub address: String,
+    
+    
+    pub amount: u64,
+    
+    
+    pub memo: Option<Vec<u8>>,
+    
+    
+    pub pool: NoteType,
+}
+
+
+// Rows paid together by one transaction and the notes that fund them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayoutBatch {
+    
+    // Indexes into the parsed rows
+    pub rows: Vec<usize>,
+    
+    
+    pub note_ids: Vec<String>,
+    
+    
+    pub fee: u64,
+    
+    
+    pub change: u64,
+    
+    
+    pub logical_actions: usize,
+}
+
+
+// The batches today's spendable notes can fund. Rows in `waiting` need change from those
+// batches, which can only be spent once it confirms, so they are left for a later run.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayoutPlan {
+    
+    pub batches: Vec<PayoutBatch>,
+    
+    
+    // Indexes into the parsed rows
+    pub waiting: Vec<usize>,
+}
+
+
+// What happened to one row once its batch was sent
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct PayoutResult {
+    
+    pub line: usize,
+    
+    
+    pub address: String,
+    
+    
+    pub amount: u64,
+    
+    
+    pub txid: Option<String>,
+    
+    
+    pub error: Option<String>,
+}
+
+
+// Every row is checked before anything is returned, so one run lists all the mistakes in the file
+pub fn parse_payouts(text: &str, network: &Network) -> NozyResult<Vec<PayoutRow>> {
+    let mut rows = Vec::new();
+    let mut problems = Vec::new();
+    
+    for (index, line) in text.lines().enumerate() {
+        let line_number = index + 1;
+        let trimmed = line.trim();
+        if trimmed.is_empty() || trimmed.starts_with('#') {
+            continue;
+        }
+        
+        let fields = match split_fields(trimmed) {
+            Ok(fields) => fields,
+            Err(problem) => {
+                problems.push(format!("line {}: {}", line_number, problem));
+                continue;
+            }
+        };
+        if rows.is_empty() && problems.is_empty() && fields[0].eq_ignore_ascii_case("address") {
+            continue;
+        }
+        
+        match parse_row(line_number, &fields, network) {
+            Ok(row) => rows.push(row),
+            Err(problem) => problems.push(format!("line {}: {}", line_number, problem)),
+        }
+    }
+    
+    if !problems.is_empty() {
+        return Err(NozyError::InvalidOperation(format!(
+            "{} invalid payout rows:\n  {}", problems.len(), problems.join("\n  ")
+        )));
+    }
+    if rows.is_empty() {
+        return Err(NozyError::InvalidOperation("The payout file has no payments".to_string()));
+    }
+    Ok(rows)
+}
+
+
+fn parse_row(line: usize, fields: &[String], network: &Network) -> Result<PayoutRow, String> {
+    if fields.len() < 2 || fields.len() > 3 {
+        return Err(format!("expected address,amount[,memo] but found {} columns", fields.len()));
+    }
+    
+    let address = fields[0].trim().to_string();
+    let pool = selection::recipient_pool(network, &address)?;
+    let amount = parse_zec(fields[1].trim())?;
+    if amount == 0 {
+        return Err("amount must be greater than zero".to_string());
+    }
+    
+    let memo = fields.get(2).filter(|memo| !memo.is_empty()).map(|memo| memo.as_bytes().to_vec());
+    if let Some(memo) = &memo {
+        if memo.len() > MAX_MEMO_BYTES {
+            return Err(format!("memo is {} bytes; at most {} fit", memo.len(), MAX_MEMO_BYTES));
+        }
+    }
+    
+    Ok(PayoutRow { line, address, amount, memo, pool })
+}
+
+
+// Exact decimal parsing, so amounts never pick up floating point rounding
+pub fn parse_zec(text: &str) -> Result<u64, String> {
+    let invalid = || format!("{:?} is not a ZEC amount", text);
+    let (whole, fraction) = text.split_once('.').unwrap_or((text, ""));
+    if whole.is_empty() && fraction.is_empty() {
+        return Err(invalid());
+    }
+    if !whole.chars().all(|c| c.is_ascii_digit()) || !fraction.chars().all(|c| c.is_ascii_digit()) {
+        return Err(invalid());
+    }
+    if fraction.len() > 8 {
+        return Err(format!("{} has more than 8 decimal places", text));
+    }
+    
+    let whole: u64 = if whole.is_empty() { 0 } else { whole.parse().map_err(|_| invalid())? };
+    let fraction: u64 = format!("{:0<8}", fraction).parse().map_err(|_| invalid())?;
+    whole.checked_mul(100_000_000)
+        .and_then(|zatoshi| zatoshi.checked_add(fraction))
+        .filter(|zatoshi| *zatoshi <= zcash_protocol::value::MAX_MONEY)
+        .ok_or_else(|| format!("{} is more ZEC than can exist", text))
+}
+
+
+// Comma separated, with double quotes around fields that contain commas and "" for a literal quote
+fn split_fields(line: &str) -> Result<Vec<String>, String> {
+    let mut fields = Vec::new();
+    let mut field = String::new();
+    let mut quoted = false;
+    let mut chars = line.chars().peekable();
+    
+    while let Some(c) = chars.next() {
+        match c {
+            '"' if quoted && chars.peek() == Some(&'"') => {
+                field.push('"');
+                chars.next();
+            }
+            '"' if quoted => quoted = false,
+            '"' if field.trim().is_empty() => {
+                field.clear();
+                quoted = true;
+            }
+            ',' if !quoted => fields.push(std::mem::take(&mut field)),
+            c => field.push(c),
+        }
+    }
+    if quoted {
+        return Err("unterminated quote".to_string());
+    }
+    fields.push(field);
+    Ok(fields)
+}
+
+
+// Fills each transaction with as many consecutive rows as its notes, fee and action limit allow
+pub fn plan_batches(rows: &[PayoutRow], notes: &[&ShieldedNote], max_actions: usize) -> NozyResult<PayoutPlan> {
+    if max_actions < 2 {
+        return Err(NozyError::InvalidOperation("A batch needs room for at least 2 logical actions".to_string()));
+    }
+    let mut available = notes.to_vec();
+    available.sort_by(|a, b| b.value.cmp(&a.value).then(a.id.cmp(&b.id)));
+    
+    let mut batches: Vec<PayoutBatch> = Vec::new();
+    let mut start = 0;
+    while start < rows.len() {
+        let most = (rows.len() - start).min(max_actions);
+        let Some(batch) = (1..=most).rev().find_map(|len| fund_batch(rows, start..start + len, &available, max_actions)) else {
+            // Change from the earlier batches may still cover the rest once it confirms
+            let later: u64 = batches.iter().map(|batch| batch.change).sum::<u64>()
+                + available.iter().map(|note| note.value).sum::<u64>();
+            let remaining: u64 = rows[start..].iter().map(|row| row.amount).sum();
+            if batches.is_empty() || later < remaining {
+                return Err(NozyError::InsufficientFunds(format!(
+                    "Spendable notes run out at the payout on line {}", rows[start].line
+                )));
+            }
+            return Ok(PayoutPlan { batches, waiting: (start..rows.len()).collect() });
+        };
+        
+        available.retain(|note| !batch.note_ids.contains(&note.id));
+        start += batch.rows.len();
+        batches.push(batch);
+    }
+    Ok(PayoutPlan { batches, waiting: Vec::new() })
+}
+
+
+// Largest notes first; None if they run out or the transaction would pass the action limit
+fn fund_batch(rows: &[PayoutRow], range: std::ops::Range<usize>, available: &[&ShieldedNote], max_actions: usize) -> Option<PayoutBatch> {
+    let needed: u64 = rows[range.clone()].iter().map(|row| row.amount).sum();
+    let mut counts = ActionCounts::default();
+    for row in &rows[range.clone()] {
+        counts.add_output(row.pool);
+    }
+    
+    let mut total = 0u64;
+    let mut note_ids = Vec::new();
+    for note in available {
+        counts.add_spend(note.note_type);
+        total += note.value;
+        note_ids.push(note.id.clone());
+        if counts.logical_actions() > max_actions {
+            return None;
+        }
+        let fee = fees::conventional_fee(&counts);
+        if total < needed + fee {
+            continue;
+        }
+        
+        // Change always returns to Orchard
+        let mut with_change = counts;
+        with_change.add_output(NoteType::Orchard);
+        let fee_with_change = fees::conventional_fee(&with_change);
+        let (fee, change, logical_actions) = if total > needed + fee_with_change {
+            (fee_with_change, total - needed - fee_with_change, with_change.logical_actions())
+        } else {
+            // Too little is left over to pay for a change output, so it goes to the fee
+            (total - needed, 0, counts.logical_actions())
+        };
+        if logical_actions > max_actions {
+            return None;
+        }
+        
+        return Some(PayoutBatch { rows: range.collect(), note_ids, fee, change, logical_actions });
+    }
+    None
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::NoteManager;
+    use crate::scanner::ViewingKeys;
+    
+    #[test]
+    fn test_parses_and_packs_payouts() {
+        let network = Network::TestNetwork;
+        let (_, _, keys) = ViewingKeys::test_wallet();
+        let orchard = keys.orchard_address_at(1).unwrap();
+        let sapling = keys.sapling_change_address();
+        
+        assert_eq!(parse_zec("1.5"), Ok(150_000_000));
+        assert_eq!(parse_zec(".00000001"), Ok(1));
+        assert!(parse_zec("0.000000001").is_err());
+        assert!(parse_zec("1e3").is_err());
+        
+        // Every bad row is reported at once, with its line
+        let bad = format!("address,amount,memo\n{},0.1\nnot-an-address,0.1\n{},abc\n{},0\n", orchard, orchard, sapling);
+        let message = parse_payouts(&bad, &network).unwrap_err().to_string();
+        assert!(message.contains("3 invalid payout rows"));
+        assert!(message.contains("line 3:") && message.contains("line 4:") && message.contains("line 5:"));
+        
+        let mut text = format!("address,amount,memo\n{},0.5,\"rent, March\"\n{},0.25\n", orchard, sapling);
+        for _ in 0..60 {
+            text.push_str(&format!("{},0.001\n", orchard));
+        }
+        let rows = parse_payouts(&text, &network).unwrap();
+        assert_eq!(rows.len(), 62);
+        assert_eq!(rows[0].line, 2);
+        assert_eq!(rows[0].memo.as_deref(), Some(&b"rent, March"[..]));
+        assert_eq!(rows[1].pool, NoteType::Sapling);
+        
+        let (manager, ids) = NoteManager::with_test_notes(&[(100_000_000, NoteType::Orchard), (50_000_000, NoteType::Orchard)]);
+        let notes = manager.get_all_notes();
+        
+        // The action limit splits 62 payments into two transactions that never share a note
+        let plan = plan_batches(&rows, &notes, DEFAULT_BATCH_ACTIONS).unwrap();
+        let batches = &plan.batches;
+        assert_eq!(batches.len(), 2);
+        assert!(plan.waiting.is_empty());
+        assert_eq!(batches.iter().map(|batch| batch.rows.len()).sum::<usize>(), 62);
+        assert!(batches.iter().all(|batch| batch.logical_actions <= DEFAULT_BATCH_ACTIONS));
+        assert!(batches[0].note_ids.iter().all(|id| !batches[1].note_ids.contains(id)));
+        for batch in batches {
+            let paid: u64 = batch.rows.iter().map(|&index| rows[index].amount).sum();
+            let funded: u64 = batch.note_ids.iter().map(|id| manager.get_note(id).unwrap().value).sum();
+            assert_eq!(funded, paid + batch.fee + batch.change);
+        }
+        
+        let too_much = parse_payouts(&format!("{},2", orchard), &network).unwrap();
+        assert!(plan_batches(&too_much, &notes, DEFAULT_BATCH_ACTIONS).is_err());
+        
+        // With a single note, rows past the first batch wait for its change to confirm
+        let single = [manager.get_note(&ids[0]).unwrap()];
+        let plan = plan_batches(&rows, &single, 20).unwrap();
+        assert_eq!(plan.batches.len(), 1);
+        assert_eq!(plan.waiting.first().copied(), plan.batches[0].rows.last().map(|last| last + 1));
+        assert_eq!(plan.waiting.last(), Some(&61));
+        assert!(plan_batches(&rows, &notes, 1).is_err());
+    }
+}
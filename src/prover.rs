@@ -0,0 +1,248 @@
+//! Proving keys for Orchard and Sapling, and how long the wallet spends creating proofs
+
+use crate::config::ProvingConfig;
+use crate::error::{NozyError, NozyResult};
+use orchard::builder::{InProgress, Unauthorized, Unproven};
+use orchard::circuit::ProvingKey;
+use orchard::Bundle;
+use rand::RngCore;
+use sapling_crypto::bundle::GrothProofBytes;
+use sapling_crypto::circuit::{Output, OutputParameters, Spend, SpendParameters};
+use sapling_crypto::keys::EphemeralSecretKey;
+use sapling_crypto::prover::{OutputProver, SpendProver};
+use sapling_crypto::value::{NoteValue, ValueCommitTrapdoor};
+use sapling_crypto::{Diversifier, MerklePath, PaymentAddress, ProofGenerationKey, Rseed};
+use std::path::{Path, PathBuf};
+use std::sync::{Mutex, OnceLock};
+use std::time::{Duration, Instant};
+use zcash_protocol::value::ZatBalance;
+
+pub type UnprovenOrchardBundle = Bundle<InProgress<Unproven, Unauthorized>, ZatBalance>;
+pub type ProvenOrchardBundle = Bundle<InProgress<orchard::Proof, Unauthorized>, ZatBalance>;
+
+// Building the Halo2 key takes seconds, so it is done on first use and kept for the process
+static ORCHARD_PROVING_KEY: OnceLock<ProvingKey> = OnceLock::new();
+
+static PROOF_TIMES: Mutex<ProofTimes> = Mutex::new(ProofTimes { proofs: 0, total: Duration::ZERO, last: Duration::ZERO });
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ProofTimes {
+    
+    pub proofs: u64,
+    
+    pub total: Duration,
+    
+    pub last: Duration,
+}
+
+impl ProofTimes {
+    pub fn average(&self) -> Duration {
+        if self.proofs == 0 {
+            Duration::ZERO
+        } else {
+            self.total / self.proofs as u32
+        }
+    }
+}
+
+
+pub fn orchard_proving_key() -> &'static ProvingKey {
+    ORCHARD_PROVING_KEY.get_or_init(ProvingKey::build)
+}
+
+
+// Times every bundle proof created by this process, the key build on first use excluded
+pub fn proof_times() -> ProofTimes {
+    *PROOF_TIMES.lock().unwrap_or_else(|e| e.into_inner())
+}
+
+
+fn record_proof(elapsed: Duration) {
+    let mut times = PROOF_TIMES.lock().unwrap_or_else(|e| e.into_inner());
+    times.proofs += 1;
+    times.total += elapsed;
+    times.last = elapsed;
+}
+
+
+// Runs one bundle's proving and adds the time it took to `proof_times`
+pub(crate) fn timed_proof<T>(prove: impl FnOnce() -> T) -> T {
+    let started = Instant::now();
+    let proven = prove();
+    record_proof(started.elapsed());
+    proven
+}
+
+
+pub fn prove_orchard(bundle: UnprovenOrchardBundle, rng: impl RngCore) -> NozyResult<ProvenOrchardBundle> {
+    let key = orchard_proving_key();
+    timed_proof(|| bundle.create_proof(key, rng))
+        .map_err(|e| NozyError::Transaction(format!("Orchard proof generation failed: {}", e)))
+}
+
+
+// Groth16 parameters for Sapling, read only when the transaction has a Sapling bundle.
+// Orchard-only transactions never touch them.
+pub(crate) struct SaplingProver {
+    params: Option<&'static (SpendParameters, OutputParameters)>,
+}
+
+impl SaplingProver {
+    pub(crate) fn load(needed: bool, config: &ProvingConfig) -> NozyResult<Self> {
+        if !needed {
+            return Ok(Self { params: None });
+        }
+        Ok(Self { params: Some(sapling_parameters(&sapling_params_dir(config)?)?) })
+    }
+
+    fn loaded(&self) -> &(SpendParameters, OutputParameters) {
+        self.params.expect("Sapling parameters are loaded whenever a Sapling bundle is built")
+    }
+}
+
+impl SpendProver for SaplingProver {
+    type Proof = <SpendParameters as SpendProver>::Proof;
+    
+    fn prepare_circuit(
+        proof_generation_key: ProofGenerationKey,
+        diversifier: Diversifier,
+        rseed: Rseed,
+        value: NoteValue,
+        alpha: jubjub::Fr,
+        rcv: ValueCommitTrapdoor,
+        anchor: bls12_381::Scalar,
+        merkle_path: MerklePath,
+    ) -> Option<Spend> {
+        SpendParameters::prepare_circuit(proof_generation_key, diversifier, rseed, value, alpha, rcv, anchor, merkle_path)
+    }
+    
+    fn create_proof<R: RngCore>(&self, circuit: Spend, rng: &mut R) -> Self::Proof {
+        self.loaded().0.create_proof(circuit, rng)
+    }
+    
+    fn encode_proof(proof: Self::Proof) -> GrothProofBytes {
+        SpendParameters::encode_proof(proof)
+    }
+}
+
+impl OutputProver for SaplingProver {
+    type Proof = <OutputParameters as OutputProver>::Proof;
+    
+    fn prepare_circuit(
+        esk: &EphemeralSecretKey,
+        payment_address: PaymentAddress,
+        rcm: jubjub::Fr,
+        value: NoteValue,
+        rcv: ValueCommitTrapdoor,
+    ) -> Output {
+        OutputParameters::prepare_circuit(esk, payment_address, rcm, value, rcv)
+    }
+    
+    fn create_proof<R: RngCore>(&self, circuit: Output, rng: &mut R) -> Self::Proof {
+        self.loaded().1.create_proof(circuit, rng)
+    }
+    
+    fn encode_proof(proof: Self::Proof) -> GrothProofBytes {
+        OutputParameters::encode_proof(proof)
+    }
+}
+
+
+
+struct ParamsFile {
+    name: &'static str,
+    kind: &'static str,
+    // BLAKE2b-512 of the whole file, as published with the Sapling parameters
+    hash: &'static str,
+}
+
+const SAPLING_SPEND: ParamsFile = ParamsFile {
+    name: "sapling-spend.params",
+    kind: "spend",
+    hash: "8270785a1a0d0bc77196f000ee6d221c9c9894f55307bd9357c3f0105d31ca63991ab91324160d8f53e2bbd3c2633a6eb8bdf5205d822e7f3f73edac51b2b70c",
+};
+
+const SAPLING_OUTPUT: ParamsFile = ParamsFile {
+    name: "sapling-output.params",
+    kind: "output",
+    hash: "657e3d38dbb5cb5e7dd2970e8b03d69b4787dd907285b5a7f0790dcc8072f60bf593b32cc2d1c030e00ff5ae64bf84c5c3beb84ddc841d48264b4a171744d028",
+};
+
+static SAPLING_PARAMETERS: OnceLock<(SpendParameters, OutputParameters)> = OnceLock::new();
+
+
+// Where zcashd and the zcash-params fetch script put the parameter files
+pub fn sapling_params_dir(config: &ProvingConfig) -> NozyResult<PathBuf> {
+    if let Some(dir) = &config.params_dir {
+        return Ok(dir.clone());
+    }
+    std::env::var_os("HOME")
+        .map(|home| PathBuf::from(home).join(".zcash-params"))
+        .ok_or_else(|| NozyError::Config("HOME is not set and no params_dir is configured; cannot locate Sapling parameters".to_string()))
+}
+
+
+// Hashes both files without parsing them, so a bad download is caught before it is needed
+pub fn check_sapling_params(dir: &Path) -> NozyResult<()> {
+    read_verified(dir, &SAPLING_SPEND)?;
+    read_verified(dir, &SAPLING_OUTPUT)?;
+    Ok(())
+}
+
+
+fn sapling_parameters(dir: &Path) -> NozyResult<&'static (SpendParameters, OutputParameters)> {
+    if let Some(params) = SAPLING_PARAMETERS.get() {
+        return Ok(params);
+    }
+
+    let spend = SpendParameters::read(&read_verified(dir, &SAPLING_SPEND)?[..], false)
+        .map_err(|e| NozyError::Config(format!("Invalid Sapling spend parameters in {}: {}", dir.join(SAPLING_SPEND.name).display(), e)))?;
+    let output = OutputParameters::read(&read_verified(dir, &SAPLING_OUTPUT)?[..], false)
+        .map_err(|e| NozyError::Config(format!("Invalid Sapling output parameters in {}: {}", dir.join(SAPLING_OUTPUT.name).display(), e)))?;
+    Ok(SAPLING_PARAMETERS.get_or_init(|| (spend, output)))
+}
+
+
+fn read_verified(dir: &Path, file: &ParamsFile) -> NozyResult<Vec<u8>> {
+    let path = dir.join(file.name);
+    let bytes = std::fs::read(&path).map_err(|e| NozyError::Config(format!(
+        "Cannot read Sapling {} parameters {} ({}); expected a file with BLAKE2b-512 hash {}",
+        file.kind, path.display(), e, file.hash
+    )))?;
+
+    let actual = blake2b_simd::Params::new().hash_length(64).hash(&bytes);
+    if actual.to_hex().as_str() != file.hash {
+        return Err(NozyError::Config(format!(
+            "Sapling {} parameters {} are corrupt: BLAKE2b-512 hash is {}, expected {}",
+            file.kind, path.display(), actual.to_hex(), file.hash
+        )));
+    }
+    Ok(bytes)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use tempfile::tempdir;
+
+    #[test]
+    fn test_sapling_params_errors_name_file_and_hash() {
+        let dir = tempdir().unwrap();
+
+        let missing = check_sapling_params(dir.path()).unwrap_err().to_string();
+        assert!(missing.contains("sapling-spend.params"));
+        assert!(missing.contains(SAPLING_SPEND.hash));
+
+        std::fs::write(dir.path().join(SAPLING_SPEND.name), b"not parameters").unwrap();
+        let corrupt = check_sapling_params(dir.path()).unwrap_err().to_string();
+        assert!(corrupt.contains("corrupt"));
+        assert!(corrupt.contains(SAPLING_SPEND.hash));
+
+        let config = ProvingConfig { params_dir: Some(dir.path().to_path_buf()) };
+        assert_eq!(sapling_params_dir(&config).unwrap(), dir.path());
+        assert!(SaplingProver::load(true, &config).is_err());
+        assert!(SaplingProver::load(false, &config).is_ok());
+    }
+}
@@ -0,0 +1,347 @@
+//! Trial decryption of shielded outputs with the wallet's own viewing keys
+
+use crate::error::{NozyError, NozyResult};
+use crate::notes::{revealed_nullifiers, NoteType, ShieldedNote};
+use ff::PrimeField;
+use orchard::keys::{FullViewingKey, OutgoingViewingKey as OrchardOvk, PreparedIncomingViewingKey as OrchardIvk, Scope};
+use orchard::note::ExtractedNoteCommitment;
+use orchard::note_encryption::OrchardDomain;
+use sapling_crypto::keys::{OutgoingViewingKey as SaplingOvk, PreparedIncomingViewingKey as SaplingIvk};
+use sapling_crypto::note_encryption::{try_sapling_note_decryption, try_sapling_output_recovery};
+use sapling_crypto::zip32::DiversifiableFullViewingKey;
+use sapling_crypto::Rseed;
+use zcash_keys::address::UnifiedAddress;
+use zcash_keys::encoding::encode_payment_address;
+use zcash_keys::keys::UnifiedSpendingKey;
+use zcash_note_encryption::{try_note_decryption, try_output_recovery_with_ovk};
+use zcash_primitives::consensus::{BlockHeight, Network, NetworkConstants};
+use zcash_primitives::transaction::components::sapling::zip212_enforcement;
+use zcash_primitives::transaction::Transaction;
+
+
+pub struct ViewingKeys {
+    
+    network: Network,
+    
+    
+    orchard_fvk: FullViewingKey,
+    
+    
+    orchard_ivks: Vec<(Scope, OrchardIvk)>,
+    
+    
+    sapling_dfvk: DiversifiableFullViewingKey,
+    
+    
+    sapling_ivks: Vec<(Scope, SaplingIvk)>,
+    
+    
+    orchard_ovk: OrchardOvk,
+    
+    
+    sapling_ovk: SaplingOvk,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct DecryptedNote {
+    
+    pub note_type: NoteType,
+    
+    
+    pub value: u64,
+    
+    
+    pub recipient_address: String,
+    
+    
+    pub rseed: Vec<u8>,
+    
+    
+    pub rho: Option<Vec<u8>>,
+    
+    
+    pub commitment: Vec<u8>,
+    
+    
+    pub nullifier: Option<Vec<u8>>,
+    
+    
+    pub memo: Vec<u8>,
+    
+    
+    pub output_index: usize,
+    
+    
+    pub is_change: bool,
+    
+    
+    pub sapling_note: Option<sapling_crypto::Note>,
+}
+
+
+// An output we sent to someone else, recovered with our outgoing viewing key
+#[derive(Debug, Clone)]
+pub struct RecoveredOutput {
+    
+    pub note_type: NoteType,
+    
+    
+    pub value: u64,
+    
+    
+    pub recipient_address: String,
+    
+    
+    pub memo: Vec<u8>,
+    
+    
+    pub output_index: usize,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct ScannedTransaction {
+    
+    pub txid: Vec<u8>,
+    
+    
+    pub received: Vec<DecryptedNote>,
+    
+    
+    pub sent: Vec<RecoveredOutput>,
+    
+    
+    pub nullifiers: Vec<Vec<u8>>,
+}
+
+impl ViewingKeys {
+    
+    pub fn from_spending_key(usk: &UnifiedSpendingKey, network: Network) -> Self {
+        let orchard_fvk = FullViewingKey::from(usk.orchard());
+        let sapling_dfvk = usk.sapling().to_diversifiable_full_viewing_key();
+        
+        // Change comes back to the internal scope, so both scopes are tried
+        let scopes = [Scope::External, Scope::Internal];
+        
+        let orchard_ivks = scopes.iter()
+            .map(|scope| (*scope, OrchardIvk::new(&orchard_fvk.to_ivk(*scope))))
+            .collect();
+        let sapling_ivks = scopes.iter()
+            .map(|scope| (*scope, SaplingIvk::new(&sapling_dfvk.to_ivk(*scope))))
+            .collect();
+        
+        // Outputs paid to others are encrypted to the external OVK; internal OVK only covers change
+        let orchard_ovk = orchard_fvk.to_ovk(Scope::External);
+        let sapling_ovk = sapling_dfvk.to_ovk(Scope::External);
+        
+        Self {
+            network,
+            orchard_fvk,
+            orchard_ivks,
+            sapling_dfvk,
+            sapling_ivks,
+            orchard_ovk,
+            sapling_ovk,
+        }
+    }
+    
+    
+    pub fn network(&self) -> Network {
+        self.network
+    }
+    
+    
+    pub fn orchard_fvk(&self) -> &FullViewingKey {
+        &self.orchard_fvk
+    }
+    
+    
+    pub fn sapling_dfvk(&self) -> &DiversifiableFullViewingKey {
+        &self.sapling_dfvk
+    }
+    
+    
+    // Sapling nullifiers bind the note's tree position and the nk of the scope it was received on
+    pub fn sapling_nullifier(&self, decrypted: &DecryptedNote, position: u64) -> Option<Vec<u8>> {
+        let scope = if decrypted.is_change { Scope::Internal } else { Scope::External };
+        let nk = self.sapling_dfvk.to_nk(scope);
+        
+        decrypted.sapling_note.as_ref()
+            .map(|note| note.nf(&nk, position).0.to_vec())
+    }
+    
+    
+    // Nullifier of a stored note, rebuilt from its fields; None when the note lacks what the
+    // protocol needs (rho for Orchard, a tree position for Sapling) or is not addressed to us
+    pub fn note_nullifier(&self, note: &ShieldedNote) -> Option<Vec<u8>> {
+        match note.note_type {
+            NoteType::Orchard => note.orchard_note(&self.network)
+                .map(|rebuilt| rebuilt.nullifier(&self.orchard_fvk).to_bytes().to_vec()),
+            NoteType::Sapling => {
+                let scope = if note.is_change { Scope::Internal } else { Scope::External };
+                let nk = self.sapling_dfvk.to_nk(scope);
+                note.sapling_note(&self.network)
+                    .zip(note.position)
+                    .map(|(rebuilt, position)| rebuilt.nf(&nk, position).0.to_vec())
+            }
+        }
+    }
+    
+    
+    // Diversified addresses cannot be linked to each other without the viewing key
+    pub fn orchard_address_at(&self, index: u64) -> NozyResult<String> {
+        self.encode_orchard_address(self.orchard_fvk.address_at(index, Scope::External))
+    }
+    
+    
+    // Change goes to the internal scope so it never shows up as an incoming payment
+    pub fn orchard_change_address(&self) -> NozyResult<String> {
+        self.encode_orchard_address(self.orchard_fvk.address_at(0u64, Scope::Internal))
+    }
+    
+    
+    // Internal-scope Sapling address, used when a spend leaves Sapling change behind
+    pub fn sapling_change_address(&self) -> String {
+        self.encode_sapling_address(&self.sapling_dfvk.change_address().1)
+    }
+    
+    
+    fn encode_orchard_address(&self, address: orchard::Address) -> NozyResult<String> {
+        UnifiedAddress::from_receivers(Some(address), None, None)
+            .map(|ua| ua.encode(&self.network))
+            .ok_or_else(|| NozyError::InvalidOperation("Cannot encode Orchard receiver".to_string()))
+    }
+    
+    
+    fn encode_sapling_address(&self, address: &sapling_crypto::PaymentAddress) -> String {
+        encode_payment_address(self.network.hrp_sapling_payment_address(), address)
+    }
+}
+
+// Tests share one well-known wallet: the "abandon ... about" seed on testnet
+#[cfg(test)]
+impl ViewingKeys {
+    pub(crate) fn test_wallet() -> (crate::hd_wallet::HDWallet, UnifiedSpendingKey, Self) {
+        let network = Network::TestNetwork;
+        let hd_wallet = crate::hd_wallet::HDWallet::new_from_seed(
+            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
+            "testnet"
+        ).unwrap();
+        let spending_key = hd_wallet.unified_spending_key(&network, "default_password").unwrap();
+        let keys = Self::from_spending_key(&spending_key, network);
+        (hd_wallet, spending_key, keys)
+    }
+}
+
+/// Trial-decrypts every shielded output of `tx` with our incoming viewing keys, and
+/// recovers the outputs we paid to others with our outgoing viewing keys.
+///
+/// `height` is the block the transaction was mined in, or the next block for
+/// mempool transactions; it decides which note plaintext versions are valid.
+pub fn scan_transaction(
+    tx: &Transaction,
+    keys: &ViewingKeys,
+    height: BlockHeight,
+) -> NozyResult<ScannedTransaction> {
+    let mut received = Vec::new();
+    let mut sent = Vec::new();
+    
+    if let Some(bundle) = tx.orchard_bundle() {
+        for (index, action) in bundle.actions().iter().enumerate() {
+            let domain = OrchardDomain::for_action(action);
+            let received_before = received.len();
+            
+            for (scope, ivk) in &keys.orchard_ivks {
+                if let Some((note, address, memo)) = try_note_decryption(&domain, ivk, action) {
+                    received.push(DecryptedNote {
+                        note_type: NoteType::Orchard,
+                        value: note.value().inner(),
+                        recipient_address: keys.encode_orchard_address(address)?,
+                        rseed: note.rseed().as_bytes().to_vec(),
+                        rho: Some(note.rho().to_bytes().to_vec()),
+                        commitment: ExtractedNoteCommitment::from(note.commitment()).to_bytes().to_vec(),
+                        nullifier: Some(note.nullifier(&keys.orchard_fvk).to_bytes().to_vec()),
+                        memo: memo.to_vec(),
+                        output_index: index,
+                        is_change: *scope == Scope::Internal,
+                        sapling_note: None,
+                    });
+                    break;
+                }
+            }
+            
+            if received.len() == received_before {
+                let recovered = try_output_recovery_with_ovk(
+                    &domain,
+                    &keys.orchard_ovk,
+                    action,
+                    action.cv_net(),
+                    &action.encrypted_note().out_ciphertext,
+                );
+                if let Some((note, address, memo)) = recovered {
+                    sent.push(RecoveredOutput {
+                        note_type: NoteType::Orchard,
+                        value: note.value().inner(),
+                        recipient_address: keys.encode_orchard_address(address)?,
+                        memo: memo.to_vec(),
+                        output_index: index,
+                    });
+                }
+            }
+        }
+    }
+    
+    if let Some(bundle) = tx.sapling_bundle() {
+        let enforcement = zip212_enforcement(&keys.network, height);
+        
+        for (index, output) in bundle.shielded_outputs().iter().enumerate() {
+            let received_before = received.len();
+            
+            for (scope, ivk) in &keys.sapling_ivks {
+                if let Some((note, address, memo)) = try_sapling_note_decryption(ivk, output, enforcement) {
+                    let rseed = match note.rseed() {
+                        Rseed::BeforeZip212(rcm) => rcm.to_repr().to_vec(),
+                        Rseed::AfterZip212(rseed) => rseed.to_vec(),
+                    };
+                    
+                    // Sapling nullifiers depend on the note's tree position, which the
+                    // sync engine fills in once the output is mined
+                    received.push(DecryptedNote {
+                        note_type: NoteType::Sapling,
+                        value: note.value().inner(),
+                        recipient_address: keys.encode_sapling_address(&address),
+                        rseed,
+                        rho: None,
+                        commitment: note.cmu().to_bytes().to_vec(),
+                        nullifier: None,
+                        memo: memo.to_vec(),
+                        output_index: index,
+                        is_change: *scope == Scope::Internal,
+                        sapling_note: Some(note),
+                    });
+                    break;
+                }
+            }
+            
+            if received.len() == received_before {
+                if let Some((note, address, memo)) = try_sapling_output_recovery(&keys.sapling_ovk, output, enforcement) {
+                    sent.push(RecoveredOutput {
+                        note_type: NoteType::Sapling,
+                        value: note.value().inner(),
+                        recipient_address: keys.encode_sapling_address(&address),
+                        memo: memo.to_vec(),
+                        output_index: index,
+                    });
+                }
+            }
+        }
+    }
+    
+    Ok(ScannedTransaction {
+        txid: tx.txid().as_ref().to_vec(),
+        received,
+        sent,
+        nullifiers: revealed_nullifiers(tx),
+    })
+}
@@ -0,0 +1,402 @@
+//! Fee-aware note selection algorithms
+
+use crate::error::{NozyError, NozyResult};
+use crate::fees::{self, ActionCounts, MARGINAL_FEE};
+use crate::notes::{NoteSelectionStrategy, NoteType, ShieldedNote};
+use serde::{Deserialize, Serialize};
+use zcash_keys::address::Address;
+use zcash_protocol::consensus::Network;
+
+// Keeps branch-and-bound from running away on wallets with many notes
+const MAX_EXACT_MATCH_TRIES: usize = 100_000;
+
+
+// The notes a strategy would spend, with the fee and change that follow from them
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct SelectionPlan {
+    
+    pub strategy: NoteSelectionStrategy,
+    
+    
+    pub note_ids: Vec<String>,
+    
+    
+    pub pools: Vec<NoteType>,
+    
+    
+    pub total_input: u64,
+    
+    
+    pub amount: u64,
+    
+    
+    pub fee: u64,
+    
+    
+    pub change: u64,
+    
+    
+    pub logical_actions: usize,
+}
+
+impl SelectionPlan {
+    
+    pub fn input_count(&self) -> usize {
+        self.note_ids.len()
+    }
+    
+    
+    pub fn mixes_pools(&self) -> bool {
+        self.pools.contains(&NoteType::Orchard) && self.pools.contains(&NoteType::Sapling)
+    }
+}
+
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FeeRule {
+    
+    Zip317,
+    
+    // The caller has already added the fee to the amount
+    Fixed(u64),
+}
+
+
+impl FeeRule {
+    
+    // A note worth less than the fee its spend adds costs more to spend than it brings in
+    pub fn dust_threshold(&self) -> u64 {
+        match self {
+            FeeRule::Zip317 => fees::MARGINAL_FEE,
+            FeeRule::Fixed(_) => 0,
+        }
+    }
+}
+
+
+// The pool a payment lands in, decided by the receivers the address actually carries
+pub fn recipient_pool(network: &Network, address: &str) -> Result<NoteType, String> {
+    match Address::decode(network, address) {
+        Some(Address::Unified(unified)) if unified.orchard().is_some() => Ok(NoteType::Orchard),
+        Some(Address::Unified(unified)) if unified.sapling().is_some() => Ok(NoteType::Sapling),
+        Some(Address::Sapling(_)) => Ok(NoteType::Sapling),
+        Some(_) => Err(format!("{} has no Orchard or Sapling receiver; Nozy only pays shielded addresses", address)),
+        None => Err(format!("{} is not a valid address on this network", address)),
+    }
+}
+
+
+pub fn plan(
+    notes: &[&ShieldedNote],
+    amount: u64,
+    recipient: NoteType,
+    strategy: NoteSelectionStrategy,
+    rule: FeeRule,
+) -> NozyResult<SelectionPlan> {
+    let available: u64 = notes.iter().map(|note| note.value).sum();
+    if greedy(by_value_desc(notes.to_vec()), amount, recipient, rule).is_none() {
+        return Err(NozyError::InsufficientFunds(
+            format!("Insufficient funds. Required: {} plus fee, Available: {}", amount, available)
+        ));
+    }
+    
+    let chosen = match strategy {
+        NoteSelectionStrategy::PrivacyFirst => {
+            let mut ordered = by_value_desc(notes.to_vec());
+            ordered.sort_by_key(|note| note.note_type != NoteType::Orchard);
+            greedy(ordered, amount, recipient, rule)
+        }
+        NoteSelectionStrategy::EfficiencyFirst => {
+            let mut ordered = by_value_desc(notes.to_vec());
+            ordered.sort_by_key(|note| note.note_type != NoteType::Sapling);
+            greedy(ordered, amount, recipient, rule)
+        }
+        NoteSelectionStrategy::ValueBased => greedy(by_value_desc(notes.to_vec()), amount, recipient, rule),
+        NoteSelectionStrategy::AgeBased => {
+            let mut ordered = notes.to_vec();
+            ordered.sort_by(|a, b| a.created_at_height.cmp(&b.created_at_height).then(a.id.cmp(&b.id)));
+            greedy(ordered, amount, recipient, rule)
+        }
+        NoteSelectionStrategy::Balanced => exact_match(notes, amount, recipient, rule)
+            .or_else(|| privacy_weighted(notes, amount, recipient, rule)),
+        NoteSelectionStrategy::ExactMatch => exact_match(notes, amount, recipient, rule),
+        NoteSelectionStrategy::MinimalInputs => minimal_inputs(notes, amount, recipient, rule),
+        NoteSelectionStrategy::PrivacyWeighted => privacy_weighted(notes, amount, recipient, rule),
+    };
+    
+    let chosen = chosen.ok_or_else(|| NozyError::InvalidOperation(
+        format!("{:?} found no set of notes for {} zatoshi", strategy, amount)
+    ))?;
+    let outcome = evaluate(&chosen, amount, recipient, rule)
+        .ok_or_else(|| NozyError::InsufficientFunds(format!("Selected notes do not cover {} zatoshi", amount)))?;
+    
+    Ok(SelectionPlan {
+        strategy,
+        note_ids: chosen.iter().map(|note| note.id.clone()).collect(),
+        pools: chosen.iter().map(|note| note.note_type).collect(),
+        total_input: chosen.iter().map(|note| note.value).sum(),
+        amount,
+        fee: outcome.fee,
+        change: outcome.change,
+        logical_actions: outcome.logical_actions,
+    })
+}
+
+
+// The ZIP-317 fee for spending exactly these notes, with change if enough is left over to pay for it
+pub fn fee_for_notes(notes: &[&ShieldedNote], amount: u64, recipient: NoteType) -> NozyResult<u64> {
+    evaluate(notes, amount, recipient, FeeRule::Zip317)
+        .map(|outcome| outcome.fee)
+        .ok_or_else(|| NozyError::InsufficientFunds(format!("Chosen notes do not cover {} zatoshi plus its fee", amount)))
+}
+
+
+struct Outcome {
+    
+    fee: u64,
+    
+    
+    change: u64,
+    
+    
+    logical_actions: usize,
+}
+
+
+fn fee_for(counts: &ActionCounts, rule: FeeRule) -> u64 {
+    match rule {
+        FeeRule::Zip317 => fees::conventional_fee(counts),
+        FeeRule::Fixed(fee) => fee,
+    }
+}
+
+
+fn spend_counts(notes: &[&ShieldedNote], recipient: NoteType) -> ActionCounts {
+    let mut counts = ActionCounts::default();
+    for note in notes {
+        counts.add_spend(note.note_type);
+    }
+    counts.add_output(recipient);
+    counts
+}
+
+
+// None if the notes cannot pay the amount plus its fee
+fn evaluate(notes: &[&ShieldedNote], amount: u64, recipient: NoteType, rule: FeeRule) -> Option<Outcome> {
+    let total: u64 = notes.iter().map(|note| note.value).sum();
+    let counts = spend_counts(notes, recipient);
+    let fee_without_change = fee_for(&counts, rule);
+    let needed = amount.checked_add(fee_without_change)?;
+    if total < needed {
+        return None;
+    }
+    if total == needed {
+        return Some(Outcome { fee: fee_without_change, change: 0, logical_actions: counts.logical_actions() });
+    }
+    
+    // Change always returns to Orchard
+    let mut with_change = counts;
+    with_change.add_output(NoteType::Orchard);
+    let fee_with_change = fee_for(&with_change, rule);
+    let needed_with_change = amount + fee_with_change;
+    if total > needed_with_change {
+        Some(Outcome {
+            fee: fee_with_change,
+            change: total - needed_with_change,
+            logical_actions: with_change.logical_actions(),
+        })
+    } else {
+        // Too little is left over to pay for a change output, so it goes to the fee
+        Some(Outcome { fee: total - amount, change: 0, logical_actions: counts.logical_actions() })
+    }
+}
+
+
+fn by_value_desc(mut notes: Vec<&ShieldedNote>) -> Vec<&ShieldedNote> {
+    notes.sort_by(|a, b| b.value.cmp(&a.value).then(a.id.cmp(&b.id)));
+    notes
+}
+
+
+fn greedy(ordered: Vec<&ShieldedNote>, amount: u64, recipient: NoteType, rule: FeeRule) -> Option<Vec<&ShieldedNote>> {
+    let mut selected = Vec::new();
+    for note in ordered {
+        selected.push(note);
+        if evaluate(&selected, amount, recipient, rule).is_some() {
+            return Some(selected);
+        }
+    }
+    None
+}
+
+
+// Branch-and-bound over notes sorted by value, looking for a set that pays the amount
+// and fee with less left over than a change output would cost
+fn exact_match<'a>(notes: &[&'a ShieldedNote], amount: u64, recipient: NoteType, rule: FeeRule) -> Option<Vec<&'a ShieldedNote>> {
+    let sorted = by_value_desc(notes.to_vec());
+    let mut suffix = vec![0u64; sorted.len() + 1];
+    for i in (0..sorted.len()).rev() {
+        suffix[i] = suffix[i + 1] + sorted[i].value;
+    }
+    
+    let mut search = ExactSearch {
+        sorted,
+        suffix,
+        amount,
+        recipient,
+        rule,
+        tries: 0,
+        current: Vec::new(),
+        best: None,
+    };
+    search.search(0, 0);
+    
+    let sorted = search.sorted;
+    search.best.map(|(_, indices)| indices.into_iter().map(|i| sorted[i]).collect())
+}
+
+
+struct ExactSearch<'a> {
+    
+    sorted: Vec<&'a ShieldedNote>,
+    
+    
+    suffix: Vec<u64>,
+    
+    
+    amount: u64,
+    
+    
+    recipient: NoteType,
+    
+    
+    rule: FeeRule,
+    
+    
+    tries: usize,
+    
+    
+    current: Vec<usize>,
+    
+    
+    // (excess over amount + fee, chosen indices)
+    best: Option<(u64, Vec<usize>)>,
+}
+
+impl ExactSearch<'_> {
+    
+    fn search(&mut self, index: usize, sum: u64) {
+        if self.tries >= MAX_EXACT_MATCH_TRIES || self.best.as_ref().is_some_and(|(waste, _)| *waste == 0) {
+            return;
+        }
+        self.tries += 1;
+        
+        if !self.current.is_empty() {
+            let selected: Vec<&ShieldedNote> = self.current.iter().map(|&i| self.sorted[i]).collect();
+            let target = self.amount + fee_for(&spend_counts(&selected, self.recipient), self.rule);
+            if sum >= target {
+                let waste = sum - target;
+                let better = self.best.as_ref().is_none_or(|(best_waste, best)| {
+                    waste < *best_waste || (waste == *best_waste && self.current.len() < best.len())
+                });
+                if waste < MARGINAL_FEE && better {
+                    self.best = Some((waste, self.current.clone()));
+                }
+                // Adding notes only adds waste from here
+                return;
+            }
+        }
+        
+        // Even every remaining note cannot reach the amount
+        if index == self.sorted.len() || sum + self.suffix[index] < self.amount {
+            return;
+        }
+        
+        self.current.push(index);
+        self.search(index + 1, sum + self.sorted[index].value);
+        self.current.pop();
+        self.search(index + 1, sum);
+    }
+}
+
+
+// Fewest ZIP-317 logical actions, which also means the lowest fee
+fn minimal_inputs<'a>(notes: &[&'a ShieldedNote], amount: u64, recipient: NoteType, rule: FeeRule) -> Option<Vec<&'a ShieldedNote>> {
+    [Some(NoteType::Orchard), Some(NoteType::Sapling), None]
+        .into_iter()
+        .filter_map(|pool| {
+            let candidates: Vec<&ShieldedNote> = notes.iter()
+                .copied()
+                .filter(|note| pool.is_none_or(|pool| note.note_type == pool))
+                .collect();
+            greedy(by_value_desc(candidates), amount, recipient, rule)
+        })
+        .min_by_key(|selected| {
+            let outcome = evaluate(selected, amount, recipient, rule);
+            (
+                outcome.as_ref().map(|outcome| outcome.logical_actions).unwrap_or(usize::MAX),
+                outcome.as_ref().map(|outcome| outcome.fee).unwrap_or(u64::MAX),
+                selected.len(),
+            )
+        })
+}
+
+
+// Stays inside one pool when it can, the recipient's first, so no value crosses the turnstile
+fn privacy_weighted<'a>(notes: &[&'a ShieldedNote], amount: u64, recipient: NoteType, rule: FeeRule) -> Option<Vec<&'a ShieldedNote>> {
+    let other = match recipient {
+        NoteType::Orchard => NoteType::Sapling,
+        NoteType::Sapling => NoteType::Orchard,
+    };
+    
+    for pool in [recipient, other] {
+        let candidates: Vec<&ShieldedNote> = notes.iter()
+            .copied()
+            .filter(|note| note.note_type == pool)
+            .collect();
+        if let Some(selected) = greedy(by_value_desc(candidates), amount, recipient, rule) {
+            return Some(selected);
+        }
+    }
+    
+    let mut ordered = by_value_desc(notes.to_vec());
+    ordered.sort_by_key(|note| note.note_type != recipient);
+    greedy(ordered, amount, recipient, rule)
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::notes::NoteManager;
+    
+    #[test]
+    fn test_strategies_report_fee_and_change() {
+        let (manager, _) = NoteManager::with_test_notes(&[
+            (60_000, NoteType::Orchard),
+            (25_000, NoteType::Orchard),
+            (200_000, NoteType::Sapling),
+            (40_000, NoteType::Sapling),
+        ]);
+        let notes = manager.get_all_notes();
+        
+        // 60_000 + 25_000 pays 75_000 plus the two-action fee with nothing left over
+        let exact = plan(&notes, 75_000, NoteType::Orchard, NoteSelectionStrategy::ExactMatch, FeeRule::Zip317).unwrap();
+        assert_eq!(exact.change, 0);
+        assert_eq!(exact.fee, 10_000);
+        assert_eq!(exact.total_input, 85_000);
+        
+        let minimal = plan(&notes, 150_000, NoteType::Orchard, NoteSelectionStrategy::MinimalInputs, FeeRule::Zip317).unwrap();
+        assert_eq!(minimal.input_count(), 1);
+        assert_eq!(minimal.total_input, minimal.amount + minimal.fee + minimal.change);
+        
+        let private = plan(&notes, 70_000, NoteType::Orchard, NoteSelectionStrategy::PrivacyWeighted, FeeRule::Zip317).unwrap();
+        assert!(!private.mixes_pools());
+        assert!(private.pools.iter().all(|pool| *pool == NoteType::Orchard));
+        
+        assert!(matches!(
+            plan(&notes, 1_000_000, NoteType::Orchard, NoteSelectionStrategy::ValueBased, FeeRule::Zip317),
+            Err(NozyError::InsufficientFunds(_))
+        ));
+    }
+}
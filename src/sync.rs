@@ -0,0 +1,598 @@
+//! Resumable wallet sync: persisted scan cursor, note commitment trees and progress events
+
+use crate::error::{NozyError, NozyResult};
+use crate::history::OutgoingNote;
+use crate::notes::{NoteManager, NoteType};
+use crate::scanner::{scan_transaction, ViewingKeys};
+use crate::zebra_integration::RawTreeState;
+use incrementalmerkletree::frontier::CommitmentTree;
+use incrementalmerkletree::witness::IncrementalWitness;
+use orchard::tree::MerkleHashOrchard;
+use sapling_crypto::Node as SaplingNode;
+use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::Arc;
+use std::time::{Duration, Instant};
+use zcash_primitives::consensus::BlockHeight;
+use zcash_primitives::merkle_tree::{
+    read_commitment_tree, read_incremental_witness, write_commitment_tree, write_incremental_witness, HashSer,
+};
+use zcash_primitives::transaction::Transaction;
+
+const TREE_DEPTH: u8 = 32;
+
+type SaplingTree = CommitmentTree<SaplingNode, TREE_DEPTH>;
+type OrchardTree = CommitmentTree<MerkleHashOrchard, TREE_DEPTH>;
+type SaplingWitness = IncrementalWitness<SaplingNode, TREE_DEPTH>;
+type OrchardWitness = IncrementalWitness<MerkleHashOrchard, TREE_DEPTH>;
+
+
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+pub struct SyncState {
+    
+    pub scanned_height: Option<u32>,
+    
+    
+    pub birthday_height: Option<u32>,
+    
+    
+    // Where a requested rescan starts; the birthday stays put so later resets still know it
+    #[serde(default)]
+    pub rescan_from: Option<u32>,
+    
+    
+    sapling_tree: Option<String>,
+    
+    
+    orchard_tree: Option<String>,
+    
+    
+    sapling_witnesses: BTreeMap<String, String>,
+    
+    
+    orchard_witnesses: BTreeMap<String, String>,
+}
+
+impl SyncState {
+    
+    pub fn new(birthday_height: Option<u32>) -> Self {
+        Self {
+            birthday_height,
+            ..Self::default()
+        }
+    }
+}
+
+
+// How a stored witness lines up with the tree it was built from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct WitnessCheck {
+    
+    pub note_id: String,
+    
+    
+    pub pool: NoteType,
+    
+    
+    pub position: u64,
+    
+    
+    pub root_matches: bool,
+}
+
+
+#[derive(Debug, Clone)]
+pub struct TreeState {
+    
+    sapling: SaplingTree,
+    
+    
+    orchard: OrchardTree,
+    
+    
+    sapling_witnesses: BTreeMap<String, SaplingWitness>,
+    
+    
+    orchard_witnesses: BTreeMap<String, OrchardWitness>,
+}
+
+impl TreeState {
+    
+    pub fn from_raw(raw: &RawTreeState) -> NozyResult<Self> {
+        Ok(Self {
+            sapling: match &raw.sapling {
+                Some(bytes) => read_tree(bytes, "Sapling")?,
+                None => CommitmentTree::empty(),
+            },
+            orchard: match &raw.orchard {
+                Some(bytes) => read_tree(bytes, "Orchard")?,
+                None => CommitmentTree::empty(),
+            },
+            sapling_witnesses: BTreeMap::new(),
+            orchard_witnesses: BTreeMap::new(),
+        })
+    }
+    
+    
+    pub fn load(state: &SyncState) -> NozyResult<Self> {
+        let decode = |hex_tree: &Option<String>| -> NozyResult<Option<Vec<u8>>> {
+            hex_tree.as_ref()
+                .map(|h| hex::decode(h).map_err(|e| NozyError::Serialization(format!("Invalid saved tree: {}", e))))
+                .transpose()
+        };
+        
+        let mut trees = Self::from_raw(&RawTreeState {
+            height: state.scanned_height.unwrap_or(0),
+            sapling: decode(&state.sapling_tree)?,
+            orchard: decode(&state.orchard_tree)?,
+        })?;
+        
+        for (note_id, witness) in &state.sapling_witnesses {
+            trees.sapling_witnesses.insert(note_id.clone(), read_witness(witness, note_id)?);
+        }
+        for (note_id, witness) in &state.orchard_witnesses {
+            trees.orchard_witnesses.insert(note_id.clone(), read_witness(witness, note_id)?);
+        }
+        
+        Ok(trees)
+    }
+    
+    
+    pub fn store(&self, state: &mut SyncState) -> NozyResult<()> {
+        state.sapling_tree = Some(write_tree(&self.sapling)?);
+        state.orchard_tree = Some(write_tree(&self.orchard)?);
+        
+        state.sapling_witnesses = self.sapling_witnesses.iter()
+            .map(|(id, witness)| Ok((id.clone(), write_witness(witness)?)))
+            .collect::<NozyResult<_>>()?;
+        state.orchard_witnesses = self.orchard_witnesses.iter()
+            .map(|(id, witness)| Ok((id.clone(), write_witness(witness)?)))
+            .collect::<NozyResult<_>>()?;
+        
+        Ok(())
+    }
+    
+    
+    pub fn sapling_root(&self) -> [u8; 32] {
+        self.sapling.root().to_bytes()
+    }
+    
+    
+    pub fn orchard_root(&self) -> [u8; 32] {
+        self.orchard.root().to_bytes()
+    }
+    
+    
+    // Spends are proven against the roots of the trees as of the last scanned block
+    pub fn sapling_anchor(&self) -> sapling_crypto::Anchor {
+        sapling_crypto::Anchor::from(self.sapling.root())
+    }
+    
+    
+    pub fn orchard_anchor(&self) -> orchard::Anchor {
+        orchard::Anchor::from(self.orchard.root())
+    }
+    
+    
+    pub fn sapling_path(&self, note_id: &str) -> Option<sapling_crypto::MerklePath> {
+        self.sapling_witnesses.get(note_id)?.path()
+    }
+    
+    
+    pub fn orchard_path(&self, note_id: &str) -> Option<orchard::tree::MerklePath> {
+        self.orchard_witnesses.get(note_id)?.path().map(orchard::tree::MerklePath::from)
+    }
+    
+    
+    // Copies each witness's position and authentication path onto its note
+    pub fn update_notes(&self, notes: &mut NoteManager) {
+        for (note_id, witness) in &self.sapling_witnesses {
+            if let Some(path) = witness.path() {
+                let path_bytes = path.path_elems().iter().map(|node| node.to_bytes().to_vec()).collect();
+                notes.update_note_witness(note_id, u64::from(witness.witnessed_position()), path_bytes);
+            }
+        }
+        for (note_id, witness) in &self.orchard_witnesses {
+            if let Some(path) = witness.path() {
+                let path_bytes = path.path_elems().iter().map(|node| node.to_bytes().to_vec()).collect();
+                notes.update_note_witness(note_id, u64::from(witness.witnessed_position()), path_bytes);
+            }
+        }
+    }
+    
+    
+    // Every witness must authenticate against the stored tree root, which is the anchor we spend to
+    pub fn check_witnesses(&self) -> Vec<WitnessCheck> {
+        let sapling_root = self.sapling.root();
+        let orchard_root = self.orchard.root();
+        
+        let sapling = self.sapling_witnesses.iter().map(|(note_id, witness)| WitnessCheck {
+            note_id: note_id.clone(),
+            pool: NoteType::Sapling,
+            position: u64::from(witness.witnessed_position()),
+            root_matches: witness.root() == sapling_root,
+        });
+        let orchard = self.orchard_witnesses.iter().map(|(note_id, witness)| WitnessCheck {
+            note_id: note_id.clone(),
+            pool: NoteType::Orchard,
+            position: u64::from(witness.witnessed_position()),
+            root_matches: witness.root() == orchard_root,
+        });
+        sapling.chain(orchard).collect()
+    }
+    
+    
+    pub fn has_witness(&self, note_id: &str) -> bool {
+        self.sapling_witnesses.contains_key(note_id) || self.orchard_witnesses.contains_key(note_id)
+    }
+    
+    
+    pub fn forget(&mut self, note_id: &str) {
+        self.sapling_witnesses.remove(note_id);
+        self.orchard_witnesses.remove(note_id);
+    }
+    
+    
+    fn append_sapling(&mut self, node: SaplingNode) -> NozyResult<()> {
+        self.sapling.append(node)
+            .map_err(|_| NozyError::Note("Sapling note commitment tree is full".to_string()))?;
+        for witness in self.sapling_witnesses.values_mut() {
+            witness.append(node)
+                .map_err(|_| NozyError::Note("Sapling witness is full".to_string()))?;
+        }
+        Ok(())
+    }
+    
+    
+    fn append_orchard(&mut self, node: MerkleHashOrchard) -> NozyResult<()> {
+        self.orchard.append(node)
+            .map_err(|_| NozyError::Note("Orchard note commitment tree is full".to_string()))?;
+        for witness in self.orchard_witnesses.values_mut() {
+            witness.append(node)
+                .map_err(|_| NozyError::Note("Orchard witness is full".to_string()))?;
+        }
+        Ok(())
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub struct SyncProgress {
+    
+    pub start_height: u32,
+    
+    
+    pub current_height: u32,
+    
+    
+    pub target_height: u32,
+    
+    
+    pub notes_found: usize,
+    
+    
+    pub spends_found: usize,
+    
+    
+    started: Instant,
+}
+
+impl SyncProgress {
+    
+    pub fn new(start_height: u32, target_height: u32) -> Self {
+        Self {
+            start_height,
+            current_height: start_height.saturating_sub(1),
+            target_height,
+            notes_found: 0,
+            spends_found: 0,
+            started: Instant::now(),
+        }
+    }
+    
+    
+    pub fn blocks_scanned(&self) -> u32 {
+        (self.current_height + 1).saturating_sub(self.start_height)
+    }
+    
+    
+    pub fn blocks_remaining(&self) -> u32 {
+        self.target_height.saturating_sub(self.current_height)
+    }
+    
+    
+    pub fn fraction(&self) -> f64 {
+        let total = (self.target_height + 1).saturating_sub(self.start_height);
+        if total == 0 {
+            return 1.0;
+        }
+        self.blocks_scanned() as f64 / total as f64
+    }
+    
+    
+    pub fn elapsed(&self) -> Duration {
+        self.started.elapsed()
+    }
+    
+    
+    pub fn eta(&self) -> Option<Duration> {
+        let scanned = self.blocks_scanned();
+        if scanned == 0 {
+            return None;
+        }
+        let per_block = self.elapsed().as_secs_f64() / scanned as f64;
+        Some(Duration::from_secs_f64(per_block * self.blocks_remaining() as f64))
+    }
+}
+
+
+#[derive(Debug, Clone)]
+pub enum SyncEvent {
+    
+    Started(SyncProgress),
+    
+    
+    Progress(SyncProgress),
+    
+    
+    BatchSaved(SyncProgress),
+    
+    
+    Cancelled(SyncProgress),
+    
+    
+    Finished(SyncProgress),
+}
+
+
+#[derive(Debug, Clone)]
+pub struct SyncOptions {
+    
+    pub batch_size: u32,
+    
+    
+    pub checkpoint_path: Option<PathBuf>,
+    
+    
+    cancel: Arc<AtomicBool>,
+}
+
+impl Default for SyncOptions {
+    fn default() -> Self {
+        Self {
+            batch_size: 100,
+            checkpoint_path: None,
+            cancel: Arc::new(AtomicBool::new(false)),
+        }
+    }
+}
+
+impl SyncOptions {
+    
+    pub fn new(batch_size: u32, checkpoint_path: Option<PathBuf>) -> Self {
+        Self {
+            batch_size: batch_size.max(1),
+            checkpoint_path,
+            ..Self::default()
+        }
+    }
+    
+    
+    // Setting the returned flag stops the sync after the block currently being scanned
+    pub fn cancel_handle(&self) -> Arc<AtomicBool> {
+        self.cancel.clone()
+    }
+    
+    
+    pub fn is_cancelled(&self) -> bool {
+        self.cancel.load(Ordering::SeqCst)
+    }
+}
+
+
+#[derive(Debug, Clone, Default)]
+pub struct BlockScan {
+    
+    pub notes_found: usize,
+    
+    
+    pub new_note_ids: Vec<String>,
+    
+    
+    pub spent_note_ids: Vec<String>,
+    
+    
+    pub sent: Vec<OutgoingNote>,
+}
+
+/// Applies one block to the wallet: marks spends, appends every note commitment to the
+/// trees and witnesses, and records any outputs addressed to us. Outputs we paid to
+/// others are returned in `BlockScan::sent` for the caller's history. The block is
+/// scanned into a copy of the trees and only applied once all of it succeeded, so an
+/// error leaves `trees` and `notes` exactly as they were.
+pub fn scan_block(
+    trees: &mut TreeState,
+    notes: &mut NoteManager,
+    keys: &ViewingKeys,
+    height: u32,
+    transactions: &[Transaction],
+) -> NozyResult<BlockScan> {
+    let mut result = BlockScan::default();
+    let mut staged = trees.clone();
+    let mut found = Vec::new();
+    let mut spends = Vec::new();
+    
+    for tx in transactions {
+        let scanned = scan_transaction(tx, keys, BlockHeight::from_u32(height))?;
+        
+        result.sent.extend(scanned.sent.iter()
+            .map(|output| OutgoingNote::from_recovered(output, &scanned.txid, height)));
+        
+        if let Some(bundle) = tx.sapling_bundle() {
+            for (index, output) in bundle.shielded_outputs().iter().enumerate() {
+                staged.append_sapling(SaplingNode::from_cmu(output.cmu()))?;
+                
+                let ours = scanned.received.iter()
+                    .find(|d| d.note_type == NoteType::Sapling && d.output_index == index);
+                if let Some(decrypted) = ours {
+                    let witness = IncrementalWitness::from_tree(staged.sapling.clone())
+                        .ok_or_else(|| NozyError::Note("Cannot witness an empty Sapling tree".to_string()))?;
+                    let position = u64::from(witness.witnessed_position());
+                    
+                    let mut note = notes.note_from_decrypted(decrypted, height, &scanned.txid);
+                    note.nullifier = keys.sapling_nullifier(decrypted, position);
+                    note.position = Some(position);
+                    
+                    staged.sapling_witnesses.insert(note.id.clone(), witness);
+                    found.push(note);
+                }
+            }
+        }
+        
+        if let Some(bundle) = tx.orchard_bundle() {
+            for (index, action) in bundle.actions().iter().enumerate() {
+                staged.append_orchard(MerkleHashOrchard::from_cmx(action.cmx()))?;
+                
+                let ours = scanned.received.iter()
+                    .find(|d| d.note_type == NoteType::Orchard && d.output_index == index);
+                if let Some(decrypted) = ours {
+                    let witness = IncrementalWitness::from_tree(staged.orchard.clone())
+                        .ok_or_else(|| NozyError::Note("Cannot witness an empty Orchard tree".to_string()))?;
+                    
+                    let mut note = notes.note_from_decrypted(decrypted, height, &scanned.txid);
+                    note.position = Some(u64::from(witness.witnessed_position()));
+                    
+                    staged.orchard_witnesses.insert(note.id.clone(), witness);
+                    found.push(note);
+                }
+            }
+        }
+        
+        spends.push((scanned.nullifiers, scanned.txid));
+    }
+    
+    // Nothing below can fail. Notes go in before spends are matched, so a note received
+    // and spent within the same block ends up spent.
+    *trees = staged;
+    for note in found {
+        let note_id = note.id.clone();
+        if notes.upsert_scanned_note(note) {
+            result.notes_found += 1;
+            result.new_note_ids.push(note_id);
+        }
+    }
+    for (nullifiers, txid) in &spends {
+        for note_id in notes.mark_spent_by_nullifiers(nullifiers, height, txid) {
+            trees.forget(&note_id);
+            result.spent_note_ids.push(note_id);
+        }
+    }
+    
+    Ok(result)
+}
+
+
+fn read_tree<H: HashSer>(bytes: &[u8], pool: &str) -> NozyResult<CommitmentTree<H, TREE_DEPTH>> {
+    read_commitment_tree(bytes)
+        .map_err(|e| NozyError::Serialization(format!("Invalid {} tree state: {}", pool, e)))
+}
+
+
+fn write_tree<H: HashSer>(tree: &CommitmentTree<H, TREE_DEPTH>) -> NozyResult<String> {
+    let mut bytes = Vec::new();
+    write_commitment_tree(tree, &mut bytes)
+        .map_err(|e| NozyError::Serialization(format!("Failed to serialize tree: {}", e)))?;
+    Ok(hex::encode(bytes))
+}
+
+
+fn read_witness<H: HashSer>(hex_witness: &str, note_id: &str) -> NozyResult<IncrementalWitness<H, TREE_DEPTH>> {
+    let bytes = hex::decode(hex_witness)
+        .map_err(|e| NozyError::Serialization(format!("Invalid witness for note {}: {}", note_id, e)))?;
+    read_incremental_witness(&bytes[..])
+        .map_err(|e| NozyError::Serialization(format!("Invalid witness for note {}: {}", note_id, e)))
+}
+
+
+fn write_witness<H: HashSer>(witness: &IncrementalWitness<H, TREE_DEPTH>) -> NozyResult<String> {
+    let mut bytes = Vec::new();
+    write_incremental_witness(witness, &mut bytes)
+        .map_err(|e| NozyError::Serialization(format!("Failed to serialize witness: {}", e)))?;
+    Ok(hex::encode(bytes))
+}
+
+// Lets tests hand the builder a spendable note without scanning a real block
+#[cfg(test)]
+impl TreeState {
+    
+    pub(crate) fn spendable_orchard_note(
+        &mut self,
+        spending_key: &zcash_keys::keys::UnifiedSpendingKey,
+        network: zcash_primitives::consensus::Network,
+        value: u64,
+    ) -> crate::notes::ShieldedNote {
+        use orchard::keys::{FullViewingKey, Scope};
+        use orchard::note::{ExtractedNoteCommitment, RandomSeed, Rho};
+
+        // Distinct rho per note keeps commitments and nullifiers apart
+        let fvk = FullViewingKey::from(spending_key.orchard());
+        let rho = Rho::from_bytes(&[self.orchard_witnesses.len() as u8 % 64 + 1; 32]).unwrap();
+        let rseed = (0u8..).find_map(|seed| Option::<RandomSeed>::from(RandomSeed::from_bytes([seed; 32], &rho))).unwrap();
+        let note = orchard::Note::from_parts(fvk.address_at(0u64, Scope::External), orchard::value::NoteValue::from_raw(value), rho, rseed).unwrap();
+        let cmx = ExtractedNoteCommitment::from(note.commitment());
+
+        let address = ViewingKeys::from_spending_key(spending_key, network).orchard_address_at(0).unwrap();
+        let mut shielded = NoteManager::new(&crate::config::NozyConfig::default()).unwrap()
+            .create_note(value, address, None, NoteType::Orchard, 10, None).unwrap();
+        shielded.rho = Some(rho.to_bytes().to_vec());
+        shielded.randomness = rseed.as_bytes().to_vec();
+        shielded.commitment = cmx.to_bytes().to_vec();
+        shielded.nullifier = Some(note.nullifier(&fvk).to_bytes().to_vec());
+
+        self.append_orchard(MerkleHashOrchard::from_cmx(&cmx)).unwrap();
+        let witness = IncrementalWitness::from_tree(self.orchard.clone()).unwrap();
+        self.orchard_witnesses.insert(shielded.id.clone(), witness);
+        shielded
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use incrementalmerkletree::Hashable;
+    
+    #[test]
+    fn test_tree_state_survives_save_and_load() {
+        let mut trees = TreeState::from_raw(&RawTreeState { height: 0, sapling: None, orchard: None }).unwrap();
+        
+        trees.append_orchard(MerkleHashOrchard::empty_leaf()).unwrap();
+        let witness = IncrementalWitness::from_tree(trees.orchard.clone()).unwrap();
+        trees.orchard_witnesses.insert("note".to_string(), witness);
+        trees.append_orchard(MerkleHashOrchard::empty_leaf()).unwrap();
+        
+        let mut state = SyncState::new(Some(100));
+        trees.store(&mut state).unwrap();
+        let loaded = TreeState::load(&state).unwrap();
+        
+        assert_eq!(loaded.orchard_root(), trees.orchard_root());
+        assert_eq!(loaded.sapling_root(), trees.sapling_root());
+        
+        let witness = &loaded.orchard_witnesses["note"];
+        assert_eq!(u64::from(witness.witnessed_position()), 0);
+        assert_eq!(witness.root(), trees.orchard.root());
+    }
+    
+    #[test]
+    fn test_progress_eta() {
+        let mut progress = SyncProgress::new(100, 199);
+        assert!(progress.eta().is_none());
+        
+        progress.current_height = 149;
+        assert_eq!(progress.blocks_scanned(), 50);
+        assert_eq!(progress.blocks_remaining(), 50);
+        assert!((progress.fraction() - 0.5).abs() < f64::EPSILON);
+        assert!(progress.eta().is_some());
+    }
+}
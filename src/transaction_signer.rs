@@ -4,20 +4,50 @@
 use crate::error::{NozyResult, NozyError};
 use crate::hd_wallet::{HDWallet, AddressType};
 use crate::notes::{NoteManager, ShieldedNote, NoteType, NoteSelectionStrategy};
+use crate::selection;
+use crate::transactions::{BuiltTransaction, UnsignedTransaction};
+use orchard::keys::SpendAuthorizingKey;
+use rand::rngs::OsRng;
 use serde::{Serialize, Deserialize};
-use ed25519_dalek::{Signer, Verifier, Signature, SigningKey, VerifyingKey};
-use blake2b_simd::Params;
-use sha2::Digest;
-use std::collections::HashMap;
+use sapling_crypto::value::CommitmentSum;
+use zcash_keys::keys::UnifiedSpendingKey;
+use zcash_primitives::transaction::sighash::{signature_hash, SignableInput};
+use zcash_primitives::transaction::txid::TxIdDigester;
+use zcash_primitives::transaction::{Authorization, Transaction};
+use zcash_protocol::consensus::{BranchId, Network};
 
 
 pub struct TransactionSigner {
     
     hd_wallet: HDWallet,
     
-    signing_keys: HashMap<String, SigningKey>,
-    
     note_manager: NoteManager,
+    
+    network: Network,
+    
+    // Internal Orchard address from the wallet's viewing key
+    change_address: String,
+}
+
+
+// How the notes funding a transaction are chosen
+#[derive(Debug, Clone)]
+pub enum InputSelection {
+    
+    Automatic(Option<NoteSelectionStrategy>),
+    
+    Manual(Vec<String>),
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Payment {
+    
+    pub address: String,
+    
+    pub value: u64,
+    
+    pub memo: Option<Vec<u8>>,
 }
 
 
@@ -45,27 +75,40 @@ pub struct ShieldedOutput {
 }
 
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
 pub struct TransactionSignature {
     
     pub signature: Vec<u8>,
     
+    // Randomized key rk for spend authorization, the bundle's value-commitment key for binding
     pub public_key: Vec<u8>,
     
     pub algorithm: SignatureAlgorithm,
     
+    #[serde(default)]
+    pub kind: SignatureKind,
+    
+    // The ZIP-244 signature digest that was signed
     pub tx_hash: Vec<u8>,
 }
 
 
-#[derive(Debug, Clone, Serialize, Deserialize)]
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
 pub enum SignatureAlgorithm {
     
     RedPallas,
     
     RedJubjub,
+}
+
+
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub enum SignatureKind {
     
-    EdDSA,
+    #[default]
+    SpendAuth,
+    
+    Binding,
 }
 
 
@@ -87,15 +130,48 @@ pub struct SignedTransaction {
     pub version: u32,
     
     pub change_output: Option<ShieldedOutput>,
+    
+    // Consensus serialization of the built v5 transaction; tx_hash is its txid
+    #[serde(default)]
+    pub raw_transaction: Vec<u8>,
+}
+
+
+impl SignedTransaction {
+    
+    // Byte-reversed hex, as Zebra reports it
+    pub fn txid(&self) -> String {
+        crate::history::txid_to_hex(&self.tx_hash)
+    }
+    
+    
+    // Every output, change included, in the form the transaction builder takes
+    pub fn payments(&self) -> Vec<Payment> {
+        self.outputs.iter()
+            .map(|output| Payment {
+                address: output.address.clone(),
+                value: output.value,
+                memo: output.memo.clone(),
+            })
+            .collect()
+    }
+    
+    
+    pub fn attach_built(&mut self, built: BuiltTransaction) {
+        self.tx_hash = built.txid;
+        self.raw_transaction = built.raw;
+        self.expiry_height = built.expiry_height as u64;
+    }
 }
 
 impl TransactionSigner {
     
-    pub fn new(hd_wallet: HDWallet, note_manager: NoteManager) -> Self {
+    pub fn new(hd_wallet: HDWallet, note_manager: NoteManager, network: Network, change_address: String) -> Self {
         Self {
             hd_wallet,
-            signing_keys: HashMap::new(),
             note_manager,
+            network,
+            change_address,
         }
     }
     
@@ -106,17 +182,38 @@ impl TransactionSigner {
         amount: u64,
         fee: u64,
         memo: Option<Vec<u8>>,
-        expiry_height: u64,
-        strategy: Option<NoteSelectionStrategy>,
+        selection: InputSelection,
+    ) -> NozyResult<SignedTransaction> {
+        let payment = Payment {
+            address: recipient_address,
+            value: amount,
+            memo,
+        };
+        self.build_transaction_with_payments(vec![payment], fee, selection)
+    }
+    
+    
+    // One transaction paying several recipients, with a single change output
+    pub fn build_transaction_with_payments(
+        &mut self,
+        payments: Vec<Payment>,
+        fee: u64,
+        selection: InputSelection,
     ) -> NozyResult<SignedTransaction> {
+        if payments.is_empty() {
+            return Err(NozyError::InvalidOperation("A transaction needs at least one payment".to_string()));
+        }
+        
         // Calculate total amount needed (including fee)
-        let total_needed = amount + fee;
+        let total_needed = payments.iter().map(|payment| payment.value).sum::<u64>() + fee;
         
-        // Select notes to spend based on strategy
-        let notes_to_spend = self.note_manager.select_notes_for_spending(
-            total_needed,
-            strategy,
-        )?;
+        // Notes chosen by hand skip automatic selection entirely
+        let notes_to_spend = match &selection {
+            InputSelection::Automatic(strategy) => {
+                self.note_manager.select_notes_for_spending(total_needed, strategy.clone())?
+            }
+            InputSelection::Manual(note_ids) => self.note_manager.select_specific_notes(note_ids, total_needed)?,
+        };
         
         // Calculate total input value
         let total_input: u64 = notes_to_spend.iter().map(|note| note.value).sum();
@@ -134,17 +231,24 @@ impl TransactionSigner {
         }).collect();
         
         // Create outputs
-        let mut outputs = vec![ShieldedOutput {
-            address: recipient_address,
-            value: amount,
-            memo,
-            address_type: AddressType::Orchard, // Default to Orchard for privacy
-        }];
+        let mut outputs: Vec<ShieldedOutput> = payments.into_iter().map(|payment| {
+            let pool = selection::recipient_pool(&self.network, &payment.address)
+                .map_err(NozyError::InvalidOperation)?;
+            Ok(ShieldedOutput {
+                address_type: match pool {
+                    NoteType::Sapling => AddressType::Sapling,
+                    NoteType::Orchard => AddressType::Orchard,
+                },
+                address: payment.address,
+                value: payment.value,
+                memo: payment.memo,
+            })
+        }).collect::<NozyResult<_>>()?;
         
         // Add change output if needed
         let change_output = if change_amount > 0 {
             let change = ShieldedOutput {
-                address: self.hd_wallet.get_change_address()?, // Get change address
+                address: self.change_address.clone(),
                 value: change_amount,
                 memo: None, // No memo for change
                 address_type: AddressType::Orchard,
@@ -155,171 +259,58 @@ impl TransactionSigner {
             None
         };
         
-        // Calculate transaction hash
-        let tx_hash = self.calculate_transaction_hash(&inputs, &outputs, fee, expiry_height)?;
-        
         // Create unsigned transaction
         let transaction = SignedTransaction {
             inputs,
             outputs,
             fee,
             signatures: Vec::new(), // Will be filled when signing
-            tx_hash,
-            expiry_height,
+            tx_hash: Vec::new(), // The txid only exists once the transaction is built
+            expiry_height: 0, // Set from the built transaction
             version: 5, // Zcash v5 (latest)
             change_output,
+            raw_transaction: Vec::new(),
         };
         
         Ok(transaction)
     }
     
     
-    fn calculate_transaction_hash(
-        &self,
-        inputs: &[ShieldedInput],
-        outputs: &[ShieldedOutput],
-        fee: u64,
-        expiry_height: u64,
-    ) -> NozyResult<Vec<u8>> {
-        let mut hasher = Params::new()
-            .hash_length(32)
-            .to_state();
-        
-        // Hash inputs
-        for input in inputs {
-            hasher.update(&input.note.commitment);
-            hasher.update(&input.position.to_le_bytes());
-        }
-        
-        // Hash outputs
-        for output in outputs {
-            hasher.update(output.address.as_bytes());
-            hasher.update(&output.value.to_le_bytes());
-            if let Some(ref memo) = output.memo {
-                hasher.update(memo);
-            }
-        }
-        
-        // Hash fee and expiry
-        hasher.update(&fee.to_le_bytes());
-        hasher.update(&expiry_height.to_le_bytes());
-        
-        Ok(hasher.finalize().as_bytes().to_vec())
-    }
-    
-    
+    // Spend keys come from the account's spending key; the signatures cover the ZIP-244 digest of the built transaction
     pub fn sign_transaction_with_notes(
         &mut self,
         mut transaction: SignedTransaction,
+        unsigned: UnsignedTransaction,
         password: &str,
     ) -> NozyResult<SignedTransaction> {
-        let mut signatures = Vec::new();
-        
-        // Sign each input
-        for (i, input) in transaction.inputs.iter().enumerate() {
-            let derivation_path = self.get_derivation_path_for_note(&input.note)?;
-            let signing_key = self.derive_signing_key(&derivation_path, password)?;
-            
-            // Create signature
-            let signature = signing_key.sign(&transaction.tx_hash);
-            let public_key = signing_key.verifying_key();
-            
-            let tx_signature = TransactionSignature {
-                signature: signature.to_bytes().to_vec(),
-                public_key: public_key.to_bytes().to_vec(),
-                algorithm: SignatureAlgorithm::EdDSA, // For now, upgrade to RedPallas/RedJubjub later
-                tx_hash: transaction.tx_hash.clone(),
-            };
-            
-            signatures.push(tx_signature);
-        }
+        let spending_key = self.hd_wallet.unified_spending_key(&unsigned.network, password)?;
+        let (built, signatures) = authorize(unsigned, &spending_key)?;
         
+        transaction.attach_built(built);
         transaction.signatures = signatures;
         Ok(transaction)
     }
     
     
-    fn get_derivation_path_for_note(&self, note: &ShieldedNote) -> NozyResult<String> {
-        // For now, use a simple mapping based on note type and position
-        // In a real implementation, this would be more sophisticated
-        let base_path = match note.note_type {
-            NoteType::Orchard => "m/44'/133'/0'/0",
-            NoteType::Sapling => "m/44'/133'/0'/1",
-        };
-        
-        Ok(format!("{}/{}", base_path, note.position.unwrap_or(0)))
-    }
-    
-    
-    pub fn derive_signing_key(&mut self, derivation_path: &str, password: &str) -> NozyResult<SigningKey> {
-        // Check cache first
-        if let Some(key) = self.signing_keys.get(derivation_path) {
-            return Ok(key.clone());
-        }
-        
-        // Derive from HD wallet
-        let master_key = self.hd_wallet.get_master_key(password)?;
-        // For now, use a simplified approach - derive from the master key directly
-        let key_material = master_key.to_bytes();
-        let signing_key = SigningKey::from_bytes(&key_material);
-        
-        // Cache the key
-        self.signing_keys.insert(derivation_path.to_string(), signing_key.clone());
-        
-        Ok(signing_key)
-    }
-    
-    
+    // The recorded signatures must be exactly the ones the raw transaction carries, and each must verify
     pub fn verify_transaction(&self, transaction: &SignedTransaction) -> NozyResult<bool> {
-        if transaction.signatures.len() != transaction.inputs.len() {
+        if transaction.raw_transaction.is_empty() || transaction.signatures.is_empty() {
             return Ok(false);
         }
         
-        for (i, signature) in transaction.signatures.iter().enumerate() {
-            // Convert Vec<u8> to arrays for ed25519-dalek
-            let public_key_bytes: [u8; 32] = signature.public_key.clone().try_into()
-                .map_err(|_| NozyError::InvalidOperation("Invalid public key length".to_string()))?;
-            let signature_bytes: [u8; 64] = signature.signature.clone().try_into()
-                .map_err(|_| NozyError::InvalidOperation("Invalid signature length".to_string()))?;
-            
-            let public_key = VerifyingKey::from_bytes(&public_key_bytes)?;
-            let sig = Signature::from_bytes(&signature_bytes);
-            
-            if public_key.verify(&transaction.tx_hash, &sig).is_err() {
-                return Ok(false);
-            }
-        }
-        
-        Ok(true)
+        let carried = transaction_signatures(&transaction.raw_transaction)?;
+        Ok(carried == transaction.signatures && carried.iter().all(verify_signature))
     }
     
     
+    // ZIP-317 fee for paying `amount` to an Orchard recipient, change included
     pub fn estimate_fee_with_notes(
         &self,
         amount: u64,
         strategy: Option<NoteSelectionStrategy>,
     ) -> NozyResult<u64> {
-        // Select notes to estimate fee
-        let notes_to_spend = self.note_manager.select_notes_for_spending(
-            amount,
-            strategy,
-        )?;
-        
-        // Calculate base fee
-        let base_fee = 1000; // 0.00001 ZEC base fee
-        
-        // Add fee per input (more inputs = higher fee)
-        let input_fee = notes_to_spend.len() as u64 * 500; // 0.000005 ZEC per input
-        
-        // Add fee per output
-        let output_fee = 2 * 500; // 2 outputs (recipient + change) * 0.000005 ZEC
-        
-        // Add memo fee if present
-        let memo_fee = 0; // Memos are free in Zcash
-        
-        let total_fee = base_fee + input_fee + output_fee + memo_fee;
-        
-        Ok(total_fee)
+        let plan = self.note_manager.plan_spend(amount, NoteType::Orchard, strategy)?;
+        Ok(plan.fee)
     }
     
     
@@ -345,7 +336,7 @@ impl TransactionSigner {
         
         // Signature sizes
         for _ in &transaction.signatures {
-            size += 64; // EdDSA signature
+            size += 64; // RedPallas or RedJubjub signature
             size += 32; // Public key
         }
         
@@ -354,8 +345,10 @@ impl TransactionSigner {
     
     
     pub fn serialize_transaction(&self, transaction: &SignedTransaction) -> NozyResult<Vec<u8>> {
-        serde_json::to_vec(transaction)
-            .map_err(|e| NozyError::Serialization(format!("Failed to serialize transaction: {}", e)))
+        if transaction.raw_transaction.is_empty() {
+            return Err(NozyError::Serialization("Transaction has not been built yet".to_string()));
+        }
+        Ok(transaction.raw_transaction.clone())
     }
     
     
@@ -377,90 +370,193 @@ impl TransactionSigner {
     }
 }
 
-impl From<ed25519_dalek::SignatureError> for NozyError {
-    fn from(err: ed25519_dalek::SignatureError) -> Self {
-        NozyError::InvalidOperation(format!("Signature error: {}", err))
+// Randomizes the account's spend-authorizing keys per Sapling spend and Orchard action and signs
+// each one. Dummy spends are signed with their own throwaway keys, and each bundle's binding
+// signature is made from its value commitment trapdoors when the bundle is prepared.
+pub fn authorize(
+    mut unsigned: UnsignedTransaction,
+    spending_key: &UnifiedSpendingKey,
+) -> NozyResult<(BuiltTransaction, Vec<TransactionSignature>)> {
+    let sighash = unsigned.sighash();
+    let rng = OsRng;
+    
+    // Sapling change is received on the internal key, so both keys may be needed
+    let sapling_asks = [
+        spending_key.sapling().expsk.ask.clone(),
+        spending_key.sapling().derive_internal().expsk.ask.clone(),
+    ];
+    let sapling = match unsigned.data.sapling_bundle().cloned() {
+        Some(bundle) => Some(
+            sapling_asks.iter()
+                .fold(bundle.prepare(rng, sighash), |bundle, ask| bundle.sign(rng, ask))
+                .finalize()
+                .map_err(|e| NozyError::Transaction(format!("Failed to sign Sapling spends: {}", e)))?
+        ),
+        None => None,
+    };
+    
+    let orchard = match unsigned.orchard.take() {
+        Some(bundle) => Some(
+            bundle.prepare(rng, sighash)
+                .sign(rng, &SpendAuthorizingKey::from(spending_key.orchard()))
+                .finalize()
+                .map_err(|e| NozyError::Transaction(format!("Failed to sign Orchard actions: {}", e)))?
+        ),
+        None => None,
+    };
+    
+    let built = unsigned.complete(sapling, orchard)?;
+    let signatures = transaction_signatures(&built.raw)?;
+    if let Some(bad) = signatures.iter().find(|signature| !verify_signature(signature)) {
+        return Err(NozyError::Transaction(format!(
+            "{:?} {:?} signature does not verify against key {}", bad.algorithm, bad.kind, hex::encode(&bad.public_key)
+        )));
+    }
+    
+    Ok((built, signatures))
+}
+
+
+// Shielded-only view of a parsed transaction, enough to recompute its ZIP-244 digest
+struct Verifying;
+
+impl Authorization for Verifying {
+    type TransparentAuth = zcash_transparent::bundle::EffectsOnly;
+    type SaplingAuth = sapling_crypto::bundle::Authorized;
+    type OrchardAuth = orchard::bundle::Authorized;
+}
+
+
+// Every spend authorization and binding signature in a raw v5 transaction, with the key and
+// digest it has to verify against
+pub fn transaction_signatures(raw: &[u8]) -> NozyResult<Vec<TransactionSignature>> {
+    // v5 transactions carry their own branch id, so the one passed here is not used
+    let transaction = Transaction::read(raw, BranchId::Nu5)
+        .map_err(|e| NozyError::Serialization(format!("Failed to parse transaction: {}", e)))?;
+    let data = transaction.into_data();
+    if data.transparent_bundle().is_some() {
+        return Err(NozyError::Transaction("Transactions with transparent parts are not signed by Nozy".to_string()));
+    }
+    
+    let data = data.map_bundles::<Verifying>(|_| None, |sapling| sapling, |orchard| orchard);
+    let sighash = signature_hash(&data, &SignableInput::Shielded, &data.digest(TxIdDigester)).as_ref().to_vec();
+    let record = |signature: [u8; 64], public_key: [u8; 32], algorithm, kind| TransactionSignature {
+        signature: signature.to_vec(),
+        public_key: public_key.to_vec(),
+        algorithm,
+        kind,
+        tx_hash: sighash.clone(),
+    };
+    
+    let mut signatures = Vec::new();
+    if let Some(bundle) = data.sapling_bundle() {
+        for spend in bundle.shielded_spends() {
+            signatures.push(record((*spend.spend_auth_sig()).into(), (*spend.rk()).into(), SignatureAlgorithm::RedJubjub, SignatureKind::SpendAuth));
+        }
+        let spends: CommitmentSum = bundle.shielded_spends().iter().map(|spend| spend.cv()).sum();
+        let outputs: CommitmentSum = bundle.shielded_outputs().iter().map(|output| output.cv()).sum();
+        let binding_key = (spends - outputs).into_bvk(i64::from(*bundle.value_balance()));
+        signatures.push(record(bundle.authorization().binding_sig.into(), binding_key.into(), SignatureAlgorithm::RedJubjub, SignatureKind::Binding));
+    }
+    if let Some(bundle) = data.orchard_bundle() {
+        for action in bundle.actions() {
+            signatures.push(record(action.authorization().into(), action.rk().into(), SignatureAlgorithm::RedPallas, SignatureKind::SpendAuth));
+        }
+        signatures.push(record(
+            bundle.authorization().binding_signature().into(),
+            (&bundle.binding_validating_key()).into(),
+            SignatureAlgorithm::RedPallas,
+            SignatureKind::Binding,
+        ));
+    }
+    Ok(signatures)
+}
+
+
+fn verify_signature(signature: &TransactionSignature) -> bool {
+    let (Ok(public_key), Ok(signature_bytes)) = (
+        <[u8; 32]>::try_from(signature.public_key.as_slice()),
+        <[u8; 64]>::try_from(signature.signature.as_slice()),
+    ) else {
+        return false;
+    };
+    let message = signature.tx_hash.as_slice();
+    
+    match (signature.algorithm, signature.kind) {
+        (SignatureAlgorithm::RedPallas, SignatureKind::SpendAuth) => reddsa::VerificationKey::<reddsa::orchard::SpendAuth>::try_from(public_key)
+            .is_ok_and(|key| key.verify(message, &signature_bytes.into()).is_ok()),
+        (SignatureAlgorithm::RedPallas, SignatureKind::Binding) => reddsa::VerificationKey::<reddsa::orchard::Binding>::try_from(public_key)
+            .is_ok_and(|key| key.verify(message, &signature_bytes.into()).is_ok()),
+        (SignatureAlgorithm::RedJubjub, SignatureKind::SpendAuth) => redjubjub::VerificationKey::<redjubjub::SpendAuth>::try_from(public_key)
+            .is_ok_and(|key| key.verify(message, &signature_bytes.into()).is_ok()),
+        (SignatureAlgorithm::RedJubjub, SignatureKind::Binding) => redjubjub::VerificationKey::<redjubjub::Binding>::try_from(public_key)
+            .is_ok_and(|key| key.verify(message, &signature_bytes.into()).is_ok()),
     }
 }
 
 #[cfg(test)]
 mod tests {
     use super::*;
-    use tempfile::tempdir;
+    use crate::config::ProvingConfig;
+    use crate::scanner::ViewingKeys;
+    use crate::sync::TreeState;
+    use crate::transactions::{prepare_transaction, BuildContext};
+    use crate::zebra_integration::RawTreeState;
+    use zcash_primitives::transaction::builder::DEFAULT_TX_EXPIRY_DELTA;
+    use zcash_protocol::consensus::{Network, NetworkUpgrade, Parameters};
     
     #[test]
     fn test_transaction_signing() {
-        // Create test HD wallet
-        let mut hd_wallet = HDWallet::new_from_seed(
-            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
-            "testnet"
-        ).unwrap();
+        let network = Network::TestNetwork;
+        let (hd_wallet, spending_key, keys) = ViewingKeys::test_wallet();
         
-        let mut note_manager = NoteManager::new(&crate::config::NozyConfig::default());
-        let mut signer = TransactionSigner::new(hd_wallet, note_manager);
+        let mut trees = TreeState::from_raw(&RawTreeState { height: 0, sapling: None, orchard: None }).unwrap();
+        let note = trees.spendable_orchard_note(&spending_key, network, 300_000);
+        let mut note_manager = NoteManager::new(&crate::config::NozyConfig::default()).unwrap();
+        note_manager.add_note(note.clone()).unwrap();
+        note_manager.set_chain_height(100);
+        let mut signer = TransactionSigner::new(hd_wallet, note_manager, network, keys.orchard_change_address().unwrap());
         
-        // Create test transaction
+        let target_height = u32::from(network.activation_height(NetworkUpgrade::Nu6).unwrap()) + 10;
         let transaction = signer.build_transaction_with_notes(
-            "test_address".to_string(),
-            100000000, // 1 ZEC
-            signer.estimate_fee_with_notes(100000000, None).unwrap(),
+            keys.orchard_address_at(2).unwrap(),
+            100_000,
+            10_000,
             Some(b"Test transaction".to_vec()),
-            1000000,
-            None,
+            InputSelection::Manual(vec![note.id.clone()]),
         ).unwrap();
         
-        // Sign transaction
-        let signed_tx = signer.sign_transaction_with_notes(transaction, "default_password").unwrap();
-        
-        // Verify signatures
+        let proving = ProvingConfig::default();
+        let context = BuildContext { network, target_height, spending_key: &spending_key, trees: &trees, proving: &proving };
+        let unsigned = prepare_transaction(&[note], &transaction.payments(), 10_000, &context).unwrap();
+        let sighash = unsigned.sighash();
+        let signed_tx = signer.sign_transaction_with_notes(transaction, unsigned, "default_password").unwrap();
+        
+        // One RedPallas spend authorization per action and a binding signature, all over the ZIP-244 digest
+        let parsed = Transaction::read(&signed_tx.raw_transaction[..], BranchId::Nu6).unwrap();
+        let actions = parsed.orchard_bundle().unwrap().actions().len();
+        assert_eq!(signed_tx.signatures.len(), actions + 1);
+        assert!(signed_tx.signatures.iter().all(|signature| signature.algorithm == SignatureAlgorithm::RedPallas && signature.tx_hash == sighash));
+        assert_eq!(signed_tx.signatures.iter().filter(|signature| signature.kind == SignatureKind::Binding).count(), 1);
+        assert_eq!(signed_tx.tx_hash, parsed.txid().as_ref().to_vec());
+        assert_eq!(signed_tx.expiry_height, u64::from(target_height + DEFAULT_TX_EXPIRY_DELTA));
         assert!(signer.verify_transaction(&signed_tx).unwrap());
         
-        // Test serialization
-        let serialized = signer.serialize_transaction(&signed_tx).unwrap();
-        assert!(!serialized.is_empty());
-        
-        // Test fee estimation
-        let estimated_fee = signer.estimate_fee_with_notes(100000000, None).unwrap();
-        assert!(estimated_fee > 0);
-    }
-    
-    #[test]
-    fn test_signature_verification() {
-        let mut hd_wallet = HDWallet::new_from_seed(
-            "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about",
-            "testnet"
-        ).unwrap();
+        // Records that disagree with the transaction are rejected
+        let mut tampered = signed_tx.clone();
+        tampered.signatures[0].tx_hash[0] ^= 1;
+        assert!(!signer.verify_transaction(&tampered).unwrap());
         
-        let mut note_manager = NoteManager::new(&crate::config::NozyConfig::default());
-        let mut signer = TransactionSigner::new(hd_wallet, note_manager);
+        // The binding signature ends the transaction; corrupting it breaks verification
+        let mut tampered = signed_tx.clone();
+        *tampered.raw_transaction.last_mut().unwrap() ^= 1;
+        tampered.signatures = transaction_signatures(&tampered.raw_transaction).unwrap();
+        assert!(!signer.verify_transaction(&tampered).unwrap());
         
-        // Derive a signing key
-        let signing_key = signer.derive_signing_key("m/44'/133'/0'/0/0", "default_password").unwrap();
-        
-        // Test data
-        let tx_hash = b"test transaction hash";
-        
-        // Create test input
-        let input = ShieldedInput {
-            note: ShieldedNote {
-                id: "test_note_id".to_string(),
-                commitment: vec![1, 2, 3, 4],
-                value: 100000000,
-                randomness: vec![5, 6, 7, 8],
-                merkle_path: Some(vec![vec![9, 10, 11, 12]]),
-                position: Some(0),
-                note_type: NoteType::Orchard,
-            },
-            merkle_path: vec![vec![9, 10, 11, 12]],
-            position: 0,
-        };
-        
-        // Sign and verify
-        let signature = signing_key.sign(tx_hash);
-        let is_valid = signing_key.verifying_key().verify(tx_hash, &signature).is_ok();
-        
-        assert!(is_valid);
+        let serialized = signer.serialize_transaction(&signed_tx).unwrap();
+        assert_eq!(serialized, signed_tx.raw_transaction);
     }
+
 } 
 
 // doing this is my calling and I love it and have fun builing and learning how to create a zcash wallet on zebrad feel like one of the first to do it. 
@@ -1,16 +1,36 @@
 //Nozy is a privacy game changer we are tam Zebrad built fully private and secure Nozy wallet
 
-use crate::error::NozyResult;
-use crate::config::PrivacyLevel;
+use crate::error::{NozyError, NozyResult};
+use crate::config::{PrivacyLevel, ProvingConfig};
 use crate::notes::{ShieldedNote, NoteType};
 use crate::addresses::ZcashAddressWrapper;
+use crate::history::txid_to_hex;
+use crate::sync::TreeState;
+use crate::transaction_signer::{self, Payment};
+use crate::prover::{self, ProvenOrchardBundle, SaplingProver};
+use orchard::keys::{FullViewingKey, Scope};
+use orchard::Bundle as OrchardBundle;
+use rand::rngs::OsRng;
+use sapling_crypto::value::NoteValue;
+use sapling_crypto::Bundle as SaplingBundle;
 use serde::{Deserialize, Serialize};
+use zcash_keys::address::Address;
+use zcash_keys::keys::UnifiedSpendingKey;
+use zcash_primitives::transaction::builder::DEFAULT_TX_EXPIRY_DELTA;
+use zcash_primitives::transaction::components::sapling::zip212_enforcement;
+use zcash_primitives::transaction::sighash::{signature_hash, SignableInput};
+use zcash_primitives::transaction::txid::TxIdDigester;
+use zcash_primitives::transaction::{Authorized, TransactionData, TxVersion, Unauthorized};
+use zcash_protocol::consensus::{BlockHeight, BranchId, Network};
+use zcash_protocol::memo::MemoBytes;
+use zcash_protocol::value::ZatBalance;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransactionInput {
     pub note: ShieldedNote,
     pub nullifier: String,
-    pub witness: String,
+    // Root the note's witness authenticates against, filled in when the transaction is built
+    pub anchor: String,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -29,6 +49,11 @@ pub struct ShieldedTransaction {
     pub fee: u64,
     pub privacy_level: PrivacyLevel,
     pub status: TransactionStatus,
+    // Consensus serialization of the v5 transaction, exactly as it is broadcast
+    #[serde(default)]
+    pub raw: Vec<u8>,
+    #[serde(default)]
+    pub expiry_height: u32,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize, PartialEq, Eq)]
@@ -40,6 +65,74 @@ pub enum TransactionStatus {
     Failed,
 }
 
+// Keys, witnesses and chain position needed to turn notes and payments into a transaction
+pub struct BuildContext<'a> {
+    pub network: Network,
+    pub target_height: u32,
+    pub spending_key: &'a UnifiedSpendingKey,
+    pub trees: &'a TreeState,
+    pub proving: &'a ProvingConfig,
+}
+
+#[derive(Debug, Clone)]
+pub struct BuiltTransaction {
+    // Internal byte order, the same as note.tx_hash
+    pub txid: Vec<u8>,
+    pub raw: Vec<u8>,
+    pub expiry_height: u32,
+}
+
+impl BuiltTransaction {
+    pub fn txid_hex(&self) -> String {
+        txid_to_hex(&self.txid)
+    }
+}
+
+
+// A built and proven transaction waiting for TransactionSigner to authorize its spends
+pub struct UnsignedTransaction {
+    pub network: Network,
+    pub(crate) data: TransactionData<Unauthorized>,
+    pub(crate) orchard: Option<ProvenOrchardBundle>,
+    sighash: [u8; 32],
+}
+
+impl UnsignedTransaction {
+    // ZIP-244 signature digest shared by every spend authorization and binding signature
+    pub fn sighash(&self) -> [u8; 32] {
+        self.sighash
+    }
+
+    pub(crate) fn complete(
+        self,
+        sapling: Option<SaplingBundle<sapling_crypto::bundle::Authorized, ZatBalance>>,
+        orchard: Option<OrchardBundle<orchard::bundle::Authorized, ZatBalance>>,
+    ) -> NozyResult<BuiltTransaction> {
+        let authorized = TransactionData::<Authorized>::from_parts(
+            self.data.version(),
+            self.data.consensus_branch_id(),
+            self.data.lock_time(),
+            self.data.expiry_height(),
+            None,
+            None,
+            sapling,
+            orchard,
+        );
+
+        let transaction = authorized.freeze()
+            .map_err(|e| NozyError::Serialization(format!("Failed to assemble transaction: {}", e)))?;
+        let mut raw = Vec::new();
+        transaction.write(&mut raw)
+            .map_err(|e| NozyError::Serialization(format!("Failed to serialize transaction: {}", e)))?;
+
+        Ok(BuiltTransaction {
+            txid: transaction.txid().as_ref().to_vec(),
+            raw,
+            expiry_height: u32::from(transaction.expiry_height()),
+        })
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct TransactionBuilder {
     current_transaction: Option<ShieldedTransaction>,
@@ -53,28 +146,34 @@ impl TransactionBuilder {
             default_privacy,
         }
     }
-    
+
     pub fn start_transaction(&mut self, privacy_level: Option<PrivacyLevel>) -> NozyResult<()> {
         let privacy = privacy_level.unwrap_or(self.default_privacy);
-        
+
+        // The txid only exists once the transaction is built
         self.current_transaction = Some(ShieldedTransaction {
-            txid: format!("tx_{}", chrono::Utc::now().timestamp()),
+            txid: String::new(),
             inputs: Vec::new(),
             outputs: Vec::new(),
             fee: 0,
             privacy_level: privacy,
             status: TransactionStatus::Building,
+            raw: Vec::new(),
+            expiry_height: 0,
         });
-        
+
         Ok(())
     }
-    
+
     pub fn add_input(&mut self, note: ShieldedNote) -> NozyResult<()> {
         if let Some(tx) = &mut self.current_transaction {
+            let nullifier = note.nullifier.as_deref()
+                .map(hex::encode)
+                .ok_or_else(|| NozyError::Transaction(format!("Note {} has no nullifier and cannot be spent", note.id)))?;
             let input = TransactionInput {
-                note: note.clone(),
-                nullifier: format!("null_{}", note.id),
-                witness: format!("witness_{}", note.id),
+                note,
+                nullifier,
+                anchor: String::new(),
             };
             tx.inputs.push(input);
             Ok(())
@@ -84,14 +183,18 @@ impl TransactionBuilder {
             ))
         }
     }
-    
+
     pub fn add_output(&mut self, address: ZcashAddressWrapper, amount: u64, note_type: NoteType) -> NozyResult<()> {
+        self.add_output_with_memo(address, amount, note_type, None)
+    }
+
+    pub fn add_output_with_memo(&mut self, address: ZcashAddressWrapper, amount: u64, note_type: NoteType, memo: Option<String>) -> NozyResult<()> {
         if let Some(tx) = &mut self.current_transaction {
             let output = TransactionOutput {
                 address,
                 amount,
                 note_type,
-                memo: None,
+                memo,
             };
             tx.outputs.push(output);
             Ok(())
@@ -101,7 +204,7 @@ impl TransactionBuilder {
             ))
         }
     }
-    
+
     pub fn set_fee(&mut self, fee: u64) -> NozyResult<()> {
         if let Some(tx) = &mut self.current_transaction {
             tx.fee = fee;
@@ -112,19 +215,255 @@ impl TransactionBuilder {
             ))
         }
     }
-    
+
     pub fn get_current_transaction(&self) -> Option<&ShieldedTransaction> {
         self.current_transaction.as_ref()
     }
-    
-    pub fn finalize(&mut self) -> NozyResult<ShieldedTransaction> {
-        if let Some(mut tx) = self.current_transaction.take() {
-            tx.status = TransactionStatus::Ready;
-            Ok(tx)
-        } else {
-            Err(crate::error::NozyError::InvalidOperation(
-                "No transaction in progress".to_string()
-            ))
+
+    // Inputs must equal outputs plus fee exactly; add change as an output before finalizing
+    pub fn finalize(&mut self, context: &BuildContext) -> NozyResult<ShieldedTransaction> {
+        let mut tx = self.current_transaction.take()
+            .ok_or_else(|| NozyError::InvalidOperation("No transaction in progress".to_string()))?;
+
+        let notes: Vec<ShieldedNote> = tx.inputs.iter().map(|input| input.note.clone()).collect();
+        let payments: Vec<Payment> = tx.outputs.iter()
+            .map(|output| Payment {
+                address: output.address.address.clone(),
+                value: output.amount,
+                memo: output.memo.as_ref().map(|memo| memo.as_bytes().to_vec()),
+            })
+            .collect();
+
+        let built = match build_transaction(&notes, &payments, tx.fee, context) {
+            Ok(built) => built,
+            Err(e) => {
+                // Leave the transaction in place so it can be corrected and finalized again
+                self.current_transaction = Some(tx);
+                return Err(e);
+            }
+        };
+
+        let sapling_anchor = hex::encode(context.trees.sapling_anchor().to_bytes());
+        let orchard_anchor = hex::encode(context.trees.orchard_anchor().to_bytes());
+        for input in &mut tx.inputs {
+            input.anchor = match input.note.note_type {
+                NoteType::Sapling => sapling_anchor.clone(),
+                NoteType::Orchard => orchard_anchor.clone(),
+            };
         }
+
+        tx.txid = built.txid_hex();
+        tx.raw = built.raw;
+        tx.expiry_height = built.expiry_height;
+        tx.status = TransactionStatus::Ready;
+        Ok(tx)
     }
-} 
\ No newline at end of file
+}
+
+
+// Builds, proves and signs a v5 transaction spending `inputs` to `payments`. Change is not added
+// here, so the payments must already account for everything but the fee.
+pub fn build_transaction(
+    inputs: &[ShieldedNote],
+    payments: &[Payment],
+    fee: u64,
+    context: &BuildContext,
+) -> NozyResult<BuiltTransaction> {
+    let unsigned = prepare_transaction(inputs, payments, fee, context)?;
+    transaction_signer::authorize(unsigned, context.spending_key).map(|(built, _)| built)
+}
+
+
+// Everything short of the spend authorization and binding signatures
+pub fn prepare_transaction(
+    inputs: &[ShieldedNote],
+    payments: &[Payment],
+    fee: u64,
+    context: &BuildContext,
+) -> NozyResult<UnsignedTransaction> {
+    let network = context.network;
+    let target_height = BlockHeight::from_u32(context.target_height);
+
+    let total_in = inputs.iter().try_fold(0u64, |sum, note| sum.checked_add(note.value));
+    let total_out = payments.iter().try_fold(fee, |sum, payment| sum.checked_add(payment.value));
+    match (total_in, total_out) {
+        (Some(total_in), Some(total_out)) if total_in < total_out => {
+            return Err(NozyError::InsufficientFunds(format!("Inputs are {} zatoshi short of outputs plus fee", total_out - total_in)));
+        }
+        (Some(total_in), Some(total_out)) if total_in > total_out => {
+            return Err(NozyError::Transaction(format!("{} zatoshi of input is unaccounted for; add it as change", total_in - total_out)));
+        }
+        (Some(_), Some(_)) => {}
+        _ => return Err(NozyError::Transaction("Transaction values overflow".to_string())),
+    }
+
+    let mut sapling_builder = sapling_crypto::builder::Builder::new(
+        zip212_enforcement(&network, target_height),
+        sapling_crypto::builder::BundleType::DEFAULT,
+        context.trees.sapling_anchor(),
+    );
+    let mut orchard_builder = orchard::builder::Builder::new(orchard::builder::BundleType::DEFAULT, context.trees.orchard_anchor());
+
+    let orchard_fvk = FullViewingKey::from(context.spending_key.orchard());
+    // Sapling change is received on the internal key, so spending it needs that key too
+    let sapling_keys = [context.spending_key.sapling().clone(), context.spending_key.sapling().derive_internal()];
+    let mut uses_sapling = false;
+
+    for note in inputs {
+        match note.note_type {
+            NoteType::Orchard => {
+                let orchard_note = note.orchard_note(&network).ok_or_else(|| unspendable(note))?;
+                let path = context.trees.orchard_path(&note.id).ok_or_else(|| missing_witness(note))?;
+                orchard_builder.add_spend(orchard_fvk.clone(), orchard_note, path)
+                    .map_err(|e| spend_error(note, e))?;
+            }
+            NoteType::Sapling => {
+                let sapling_note = note.sapling_note(&network).ok_or_else(|| unspendable(note))?;
+                let path = context.trees.sapling_path(&note.id).ok_or_else(|| missing_witness(note))?;
+                let fvk = sapling_keys[usize::from(note.is_change)].to_diversifiable_full_viewing_key().fvk().clone();
+                sapling_builder.add_spend(fvk, sapling_note, path)
+                    .map_err(|e| spend_error(note, e))?;
+                uses_sapling = true;
+            }
+        }
+    }
+
+    // Outgoing viewing keys let the wallet recover what it sent when rescanning
+    let orchard_ovk = orchard_fvk.to_ovk(Scope::External);
+    let sapling_ovk = sapling_keys[0].to_diversifiable_full_viewing_key().to_ovk(Scope::External);
+    for payment in payments {
+        let memo = match &payment.memo {
+            Some(bytes) => MemoBytes::from_bytes(bytes)
+                .map_err(|_| NozyError::Transaction(format!("Memo for {} is longer than 512 bytes", payment.address)))?,
+            None => MemoBytes::empty(),
+        };
+
+        match Address::decode(&network, &payment.address) {
+            Some(Address::Unified(address)) if address.orchard().is_some() => {
+                let recipient = *address.orchard().expect("checked above");
+                orchard_builder.add_output(Some(orchard_ovk.clone()), recipient, orchard::value::NoteValue::from_raw(payment.value), memo.into_bytes())
+                    .map_err(|e| output_error(payment, e))?;
+            }
+            Some(Address::Unified(address)) if address.sapling().is_some() => {
+                let recipient = *address.sapling().expect("checked above");
+                sapling_builder.add_output(Some(sapling_ovk), recipient, NoteValue::from_raw(payment.value), memo.into_bytes())
+                    .map_err(|e| output_error(payment, e))?;
+                uses_sapling = true;
+            }
+            Some(Address::Sapling(recipient)) => {
+                sapling_builder.add_output(Some(sapling_ovk), recipient, NoteValue::from_raw(payment.value), memo.into_bytes())
+                    .map_err(|e| output_error(payment, e))?;
+                uses_sapling = true;
+            }
+            Some(_) => {
+                return Err(NozyError::Transaction(format!("{} has no Orchard or Sapling receiver; Nozy only pays shielded addresses", payment.address)));
+            }
+            None => {
+                return Err(NozyError::Transaction(format!("{} is not a valid address on this network", payment.address)));
+            }
+        }
+    }
+
+    let mut rng = OsRng;
+    let prover = SaplingProver::load(uses_sapling, context.proving)?;
+    let sapling_bundle = sapling_builder.build::<SaplingProver, SaplingProver, _, ZatBalance>(&sapling_keys, &mut rng)
+        .map_err(|e| NozyError::Transaction(format!("Failed to build Sapling bundle: {}", e)))?
+        .map(|(bundle, _)| prover::timed_proof(|| bundle.create_proofs(&prover, &prover, &mut rng, ())));
+    let orchard_bundle = orchard_builder.build::<ZatBalance>(&mut rng)
+        .map_err(|e| NozyError::Transaction(format!("Failed to build Orchard bundle: {}", e)))?
+        .map(|(bundle, _)| bundle);
+    // Orchard proofs are not part of the digest, so proving does not change what is signed
+    let proven_orchard = orchard_bundle.clone()
+        .map(|bundle| prover::prove_orchard(bundle, &mut rng))
+        .transpose()?;
+
+    let branch_id = BranchId::for_height(&network, target_height);
+    let data = TransactionData::<Unauthorized>::from_parts(
+        TxVersion::suggested_for_branch(branch_id),
+        branch_id,
+        0,
+        target_height + DEFAULT_TX_EXPIRY_DELTA,
+        None,
+        None,
+        sapling_bundle,
+        orchard_bundle,
+    );
+
+    // Every shielded signature in a v5 transaction commits to the same ZIP-244 digest
+    let txid_parts = data.digest(TxIdDigester);
+    let sighash = *signature_hash(&data, &SignableInput::Shielded, &txid_parts).as_ref();
+
+    Ok(UnsignedTransaction { network, data, orchard: proven_orchard, sighash })
+}
+
+
+fn unspendable(note: &ShieldedNote) -> NozyError {
+    NozyError::Transaction(format!("Note {} was not decrypted from the chain and cannot be spent", note.id))
+}
+
+
+fn missing_witness(note: &ShieldedNote) -> NozyError {
+    NozyError::Transaction(format!("Note {} has no witness; run 'nozy wallet fsck --repair' and sync again", note.id))
+}
+
+
+// A path that does not reach the anchor means the stored witness and tree have drifted apart
+fn spend_error(note: &ShieldedNote, e: impl std::fmt::Display) -> NozyError {
+    NozyError::Transaction(format!("Cannot spend note {}: {}", note.id, e))
+}
+
+
+fn output_error(payment: &Payment, e: impl std::fmt::Display) -> NozyError {
+    NozyError::Transaction(format!("Cannot pay {}: {}", payment.address, e))
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::ViewingKeys;
+    use crate::zebra_integration::RawTreeState;
+    use orchard::note::ExtractedNoteCommitment;
+    use zcash_protocol::consensus::{NetworkUpgrade, Parameters};
+    use zcash_primitives::transaction::Transaction;
+
+    #[test]
+    fn test_builds_consensus_v5_transaction() {
+        let network = Network::TestNetwork;
+        let (_, spending_key, keys) = ViewingKeys::test_wallet();
+
+        let mut trees = TreeState::from_raw(&RawTreeState { height: 0, sapling: None, orchard: None }).unwrap();
+        let note = trees.spendable_orchard_note(&spending_key, network, 200_000);
+        assert_eq!(ExtractedNoteCommitment::from(note.orchard_note(&network).unwrap().commitment()).to_bytes().to_vec(), note.commitment);
+
+        let target_height = u32::from(network.activation_height(NetworkUpgrade::Nu6).unwrap()) + 10;
+        let proving = ProvingConfig::default();
+        let context = BuildContext { network, target_height, spending_key: &spending_key, trees: &trees, proving: &proving };
+        let payments = vec![
+            Payment { address: keys.orchard_address_at(5).unwrap(), value: 120_000, memo: Some(b"rent".to_vec()) },
+            Payment { address: keys.orchard_change_address().unwrap(), value: 70_000, memo: None },
+        ];
+
+        // Outputs that do not balance against the fee are refused
+        assert!(matches!(build_transaction(std::slice::from_ref(&note), &payments, 20_000, &context), Err(NozyError::InsufficientFunds(_))));
+        assert!(build_transaction(std::slice::from_ref(&note), &payments, 5_000, &context).is_err());
+
+        let built = build_transaction(std::slice::from_ref(&note), &payments, 10_000, &context).unwrap();
+        assert_eq!(built.expiry_height, target_height + 40);
+        assert!(prover::proof_times().proofs >= 1);
+        assert!(std::ptr::eq(prover::orchard_proving_key(), prover::orchard_proving_key()));
+
+        // The bytes parse back to the same txid and serialize identically
+        let branch_id = BranchId::for_height(&network, BlockHeight::from_u32(target_height));
+        let parsed = Transaction::read(&built.raw[..], branch_id).unwrap();
+        assert_eq!(parsed.txid().as_ref().to_vec(), built.txid);
+        let mut reserialized = Vec::new();
+        parsed.write(&mut reserialized).unwrap();
+        assert_eq!(reserialized, built.raw);
+
+        let bundle = parsed.orchard_bundle().unwrap();
+        assert_eq!(bundle.anchor().to_bytes(), trees.orchard_anchor().to_bytes());
+        assert_eq!(i64::from(*bundle.value_balance()), 10_000);
+        assert!(bundle.actions().iter().any(|action| action.nullifier().to_bytes().to_vec() == note.nullifier.clone().unwrap()));
+    }
+}
@@ -3,13 +3,30 @@
 use crate::error::{NozyError, NozyResult};
 use crate::config::{NozyConfig, PrivacyLevel, PrivacyMaskType};
 use crate::storage::WalletStorage;
-use crate::notes::{NoteManager, ShieldedNote, NoteType};
+use crate::notes::{ConsolidationPlan, DustSweep, NoteManager, NoteSelectionStrategy, ShieldedNote, NoteType, SplitPlan, SplitPolicy};
 use crate::addresses::{AddressManager, ZcashAddressWrapper};
-use crate::transactions::{TransactionBuilder, ShieldedTransaction};
+use crate::transactions::{self, BuildContext, TransactionBuilder, ShieldedTransaction};
 use crate::zebra_integration::{ZebraClient, ZebraConfig, ZebraStatus};
 use crate::hd_wallet::HDWallet;
+use crate::transaction_signer::{InputSelection, Payment, SignedTransaction, TransactionSigner};
+use crate::history::{HistoryEntry, TransactionHistory};
+use crate::memo::DecodedMemo;
+use crate::mempool::{MempoolUpdate, MempoolWatcher, PendingIncoming, PendingSpend};
+use crate::scanner::ViewingKeys;
+use crate::selection::{self, FeeRule, SelectionPlan};
+use crate::mixing::{self, MixStep, MixingRound, MixingSession};
+use crate::migration::{self, MigrationSession, MigrationStep, TransferStatus};
+use crate::payouts::{self, PayoutPlan, PayoutResult, PayoutRow};
+use crate::payment_request::{PaymentRequest, RequestedPayment};
+use crate::fsck::{self, FsckReport, IssueKind};
+use crate::sync::{scan_block, SyncEvent, SyncOptions, SyncProgress, SyncState, TreeState};
+use crate::prover;
 use serde::{Deserialize, Serialize};
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+use zcash_keys::keys::UnifiedSpendingKey;
+use zcash_primitives::consensus::{NetworkUpgrade, Parameters};
 
 // REAL Zcash imports
 use crate::addresses::NetworkType;
@@ -55,6 +72,35 @@ pub struct NozyWallet {
 
     
     seed_hash: Option<String>,
+
+    
+    #[serde(default)]
+    mempool: MempoolWatcher,
+
+    
+    #[serde(default)]
+    sync_state: SyncState,
+
+    
+    #[serde(default)]
+    read_memos: HashSet<String>,
+
+    
+    #[serde(default)]
+    history: TransactionHistory,
+    
+    
+    #[serde(default)]
+    mixing: Option<MixingSession>,
+    
+    
+    #[serde(default)]
+    migration: Option<MigrationSession>,
+    
+    
+    // Diversifier index of the last address handed out in a payment request
+    #[serde(default)]
+    request_address_index: u64,
 }
 
 
@@ -74,6 +120,40 @@ pub struct WalletStatus {
     
     
     pub last_sync: Option<String>,
+    
+    
+    #[serde(default)]
+    pub unconfirmed_incoming: u64,
+    
+    
+    #[serde(default)]
+    pub unconfirmed_spent: u64,
+    
+    
+    #[serde(default)]
+    pub locked_balance: u64,
+}
+
+
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct MemoEntry {
+    
+    pub note_id: String,
+    
+    
+    pub block_height: u32,
+    
+    
+    pub value: u64,
+    
+    
+    pub memo: DecodedMemo,
+    
+    
+    pub reply_address: Option<String>,
+    
+    
+    pub read: bool,
 }
 
 
@@ -267,6 +347,18 @@ pub struct PerformanceMetrics {
 
     
     pub memory_usage: u64,
+
+    // Milliseconds per Orchard or Sapling bundle proof created by this process
+    #[serde(default)]
+    pub last_proof_time: u64,
+
+    
+    #[serde(default)]
+    pub average_proof_time: u64,
+
+    
+    #[serde(default)]
+    pub proofs_created: u64,
 }
 
 
@@ -300,7 +392,10 @@ pub struct InternalState {
 impl NozyWallet {
     
     pub fn new(config: NozyConfig) -> NozyResult<Self> {
-        let zebra_config = ZebraConfig::default();
+        let zebra_config = ZebraConfig {
+            scan_only: config.privacy.scan_only,
+            ..ZebraConfig::default()
+        };
         let zebra_client = ZebraClient::new(zebra_config);
         
         let note_manager = NoteManager::new(&config)?;
@@ -329,6 +424,9 @@ impl NozyWallet {
                 address_count: 0,
                 note_count: 0,
                 last_sync: None,
+                unconfirmed_incoming: 0,
+                unconfirmed_spent: 0,
+                locked_balance: 0,
             },
             privacy_level: config.network.default_privacy,
             privacy_masks: HashMap::new(),
@@ -336,6 +434,13 @@ impl NozyWallet {
             hd_wallet: None,
             seed_phrase: None,
             seed_hash: None,
+            mempool: MempoolWatcher::new(),
+            sync_state: SyncState::default(),
+            read_memos: HashSet::new(),
+            history: TransactionHistory::new(),
+            mixing: None,
+            migration: None,
+            request_address_index: 0,
         })
     }
     
@@ -388,6 +493,28 @@ impl NozyWallet {
     }
     
     
+    pub fn get_spendable_balance(&self) -> u64 {
+        self.note_manager.get_spendable_balance()
+    }
+    
+    
+    pub fn get_pending_confirmation_balance(&self) -> u64 {
+        self.note_manager.get_pending_confirmation_balance()
+    }
+    
+    
+    // (received, change)
+    pub fn get_confirmation_policy(&self) -> (u32, u32) {
+        self.note_manager.confirmation_policy()
+    }
+    
+    
+    pub fn set_confirmation_policy(&mut self, min_confirmations: u32, min_change_confirmations: u32) -> NozyResult<()> {
+        self.note_manager.set_confirmation_policy(min_confirmations, min_change_confirmations)?;
+        self.update_status()
+    }
+    
+    
     pub fn get_addresses(&self) -> Vec<&ZcashAddressWrapper> {
         self.address_manager.get_all_addresses()
     }
@@ -398,6 +525,146 @@ impl NozyWallet {
     }
     
     
+    pub fn get_note(&self, note_id: &str) -> Option<&ShieldedNote> {
+        self.note_manager.get_note(note_id)
+    }
+    
+    
+    pub fn get_all_notes(&self) -> Vec<&ShieldedNote> {
+        self.note_manager.get_all_notes()
+    }
+    
+    
+    pub fn get_spendable_notes(&self) -> Vec<&ShieldedNote> {
+        self.note_manager.get_spendable_notes()
+    }
+    
+    
+    pub fn note_confirmations(&self, note: &ShieldedNote) -> u32 {
+        self.note_manager.confirmations(note)
+    }
+    
+    
+    pub fn is_note_confirmed(&self, note: &ShieldedNote) -> bool {
+        self.note_manager.is_confirmed(note)
+    }
+    
+    
+    pub fn is_note_locked(&self, note_id: &str) -> bool {
+        self.note_manager.is_reserved(note_id)
+    }
+    
+    
+    pub fn set_note_label(&mut self, note_id: &str, label: Option<String>) -> NozyResult<()> {
+        self.note_manager.set_note_label(note_id, label)
+    }
+    
+    
+    pub fn get_transaction_history(&self) -> Vec<HistoryEntry> {
+        self.history.entries(&self.note_manager)
+    }
+    
+    
+    pub fn get_transaction_history_by_tag(&self, tag: &str) -> Vec<HistoryEntry> {
+        self.get_transaction_history()
+            .into_iter()
+            .filter(|entry| entry.has_tag(tag))
+            .collect()
+    }
+    
+    
+    pub fn set_address_label(&mut self, address: &str, label: Option<&str>) -> NozyResult<()> {
+        self.address_manager.set_label(&self.zebra_client.consensus_network(), address, label)
+    }
+    
+    
+    pub fn get_address_label(&self, address: &str) -> Option<&str> {
+        self.address_manager.get_label(&self.zebra_client.consensus_network(), address)
+    }
+    
+    
+    pub fn tag_note(&mut self, note_id: &str, tag: &str) -> NozyResult<bool> {
+        self.note_manager.add_note_tag(note_id, tag)
+    }
+    
+    
+    pub fn untag_note(&mut self, note_id: &str, tag: &str) -> NozyResult<bool> {
+        self.note_manager.remove_note_tag(note_id, tag)
+    }
+    
+    
+    pub fn tag_transaction(&mut self, txid: &str, tag: &str) -> NozyResult<bool> {
+        let known = self.get_transaction_history().iter().any(|entry| entry.txid.eq_ignore_ascii_case(txid));
+        if !known {
+            return Err(NozyError::Transaction(format!("Transaction {} is not in the wallet history", txid)));
+        }
+        self.history.add_tag(txid, tag)
+    }
+    
+    
+    pub fn untag_transaction(&mut self, txid: &str, tag: &str) -> NozyResult<bool> {
+        self.history.remove_tag(txid, tag)
+    }
+    
+    
+    pub fn get_balance_by_tag(&self, tag: &str) -> u64 {
+        self.note_manager.get_balance_by_tag(tag)
+    }
+    
+    
+    pub fn get_tag_balances(&self) -> BTreeMap<String, u64> {
+        self.note_manager.get_tag_balances()
+    }
+    
+    
+    // Newest first; notes without a memo are left out
+    pub fn memo_inbox(&self) -> Vec<MemoEntry> {
+        let mut entries: Vec<MemoEntry> = self.note_manager.get_all_notes()
+            .into_iter()
+            .filter_map(|note| {
+                let memo = note.decoded_memo();
+                if memo.is_empty() {
+                    return None;
+                }
+                
+                Some(MemoEntry {
+                    note_id: note.id.clone(),
+                    block_height: note.created_at_height,
+                    value: note.value,
+                    reply_address: memo.reply_address(),
+                    read: self.read_memos.contains(&note.id),
+                    memo,
+                })
+            })
+            .collect();
+        
+        entries.sort_by(|a, b| b.block_height.cmp(&a.block_height).then_with(|| a.note_id.cmp(&b.note_id)));
+        entries
+    }
+    
+    
+    pub fn mark_memo_read(&mut self, note_id: &str) -> NozyResult<()> {
+        if self.note_manager.get_note(note_id).is_none() {
+            return Err(NozyError::Note(format!("Note {} not found", note_id)));
+        }
+        self.read_memos.insert(note_id.to_string());
+        Ok(())
+    }
+    
+    
+    pub fn mark_all_memos_read(&mut self) -> usize {
+        let unread: Vec<String> = self.memo_inbox()
+            .into_iter()
+            .filter(|entry| !entry.read)
+            .map(|entry| entry.note_id)
+            .collect();
+        
+        let count = unread.len();
+        self.read_memos.extend(unread);
+        count
+    }
+    
+    
     pub fn start_transaction(&mut self, privacy_level: Option<PrivacyLevel>) -> NozyResult<()> {
         self.transaction_builder.start_transaction(privacy_level)
     }
@@ -419,14 +686,298 @@ impl NozyWallet {
     
     
     pub fn finalize_transaction(&mut self) -> NozyResult<ShieldedTransaction> {
-        self.transaction_builder.finalize()
+        let (spending_key, trees, target_height) = self.spending_material()?;
+        let context = BuildContext {
+            network: self.zebra_client.consensus_network(),
+            target_height,
+            spending_key: &spending_key,
+            trees: &trees,
+            proving: &self.config.proving,
+        };
+        self.transaction_builder.finalize(&context)
+    }
+    
+    
+    // Dust as the wallet's own ZIP-317 transactions see it
+    pub fn get_dust_notes(&self) -> Vec<&ShieldedNote> {
+        self.note_manager.get_dust_notes(FeeRule::Zip317)
+    }
+    
+    
+    pub fn get_dust_balance(&self) -> u64 {
+        self.note_manager.get_dust_balance(FeeRule::Zip317)
+    }
+    
+    
+    pub fn dust_threshold(&self) -> u64 {
+        FeeRule::Zip317.dust_threshold()
+    }
+    
+    
+    pub fn plan_dust_sweep(&self) -> Option<DustSweep> {
+        self.note_manager.plan_dust_sweep()
+    }
+    
+    
+    // Builds the self-send for a sweep; broadcast it like any other payment
+    pub fn sweep_dust(&mut self, sweep: &DustSweep) -> NozyResult<SignedTransaction> {
+        let to = self.self_send_address()?;
+        self.build_transaction(&to, sweep.output_value, sweep.fee, None, InputSelection::Manual(sweep.note_ids()))
+    }
+    
+    
+    pub fn plan_split(&self, count: usize, value: Option<u64>) -> NozyResult<SplitPlan> {
+        self.note_manager.plan_split(count, value)
+    }
+    
+    
+    // Every piece returns to the internal address, so the new notes are ours and spendable as change
+    pub fn split_notes(&mut self, plan: &SplitPlan) -> NozyResult<SignedTransaction> {
+        let to = self.self_send_address()?;
+        let payments = plan.output_values.iter()
+            .map(|value| Payment {
+                address: to.clone(),
+                value: *value,
+                memo: None,
+            })
+            .collect();
+        self.build_payments(payments, plan.fee, InputSelection::Manual(plan.note_ids.clone()))
+    }
+    
+    
+    pub fn parse_payouts(&self, text: &str) -> NozyResult<Vec<PayoutRow>> {
+        payouts::parse_payouts(text, &self.zebra_client.consensus_network())
+    }
+    
+    
+    pub fn plan_payouts(&self, rows: &[PayoutRow], max_actions: usize) -> NozyResult<PayoutPlan> {
+        payouts::plan_batches(rows, &self.note_manager.get_selectable_notes(FeeRule::Zip317), max_actions)
+    }
+    
+    
+    pub fn parse_payment_request(&self, uri: &str) -> NozyResult<PaymentRequest> {
+        PaymentRequest::parse(uri, &self.zebra_client.consensus_network())
+    }
+    
+    
+    pub fn payment_request_rows(&self, request: &PaymentRequest) -> NozyResult<Vec<PayoutRow>> {
+        request.payout_rows(&self.zebra_client.consensus_network())
+    }
+    
+    
+    // A fresh diversified Orchard address for every request keeps invoices unlinkable
+    pub fn request_payment(
+        &mut self,
+        amount: Option<u64>,
+        memo: Option<Vec<u8>>,
+        label: Option<String>,
+        message: Option<String>,
+    ) -> NozyResult<PaymentRequest> {
+        if memo.as_ref().is_some_and(|memo| memo.len() > payouts::MAX_MEMO_BYTES) {
+            return Err(NozyError::InvalidOperation(format!("Memos hold at most {} bytes", payouts::MAX_MEMO_BYTES)));
+        }
+        
+        let address = self.viewing_keys()?.orchard_address_at(self.request_address_index + 1)?;
+        self.request_address_index += 1;
+        Ok(PaymentRequest {
+            payments: vec![RequestedPayment { address, amount, memo, label, message }],
+        })
+    }
+    
+    
+    // Builds and broadcasts each batch in turn; a failed batch is recorded against its rows and the rest still go out.
+    // Rows the plan left waiting for change keep neither a txid nor an error.
+    pub fn send_payouts(&mut self, rows: &[PayoutRow], plan: &PayoutPlan) -> Vec<PayoutResult> {
+        let mut results: Vec<PayoutResult> = rows.iter()
+            .map(|row| PayoutResult {
+                line: row.line,
+                address: row.address.clone(),
+                amount: row.amount,
+                txid: None,
+                error: None,
+            })
+            .collect();
+        
+        for batch in &plan.batches {
+            let payments = batch.rows.iter()
+                .map(|&index| Payment {
+                    address: rows[index].address.clone(),
+                    value: rows[index].amount,
+                    memo: rows[index].memo.clone(),
+                })
+                .collect();
+            let outcome = self.build_payments(payments, batch.fee, InputSelection::Manual(batch.note_ids.clone()))
+                .and_then(|transaction| self.broadcast_signed_transaction(&transaction).map(|_| transaction.txid()));
+            
+            for &index in &batch.rows {
+                match &outcome {
+                    Ok(txid) => results[index].txid = Some(txid.clone()),
+                    Err(e) => results[index].error = Some(e.to_string()),
+                }
+            }
+        }
+        results
+    }
+    
+    
+    pub fn get_split_policy(&self) -> Option<&SplitPolicy> {
+        self.note_manager.split_policy()
+    }
+    
+    
+    pub fn set_split_policy(&mut self, policy: Option<SplitPolicy>) {
+        self.note_manager.set_split_policy(policy);
+    }
+    
+    
+    pub fn plan_policy_split(&self) -> Option<SplitPlan> {
+        self.note_manager.plan_policy_split()
+    }
+    
+    
+    // Sweeps, splits and consolidations pay the internal Orchard address, so they read as change
+    fn self_send_address(&self) -> NozyResult<String> {
+        self.viewing_keys()?.orchard_change_address()
+    }
+    
+    
+    pub fn plan_spend(&self, to: &str, amount: u64, strategy: Option<NoteSelectionStrategy>) -> NozyResult<SelectionPlan> {
+        self.note_manager.plan_spend(amount, self.recipient_pool(to)?, strategy)
+    }
+    
+    
+    fn recipient_pool(&self, address: &str) -> NozyResult<NoteType> {
+        selection::recipient_pool(&self.zebra_client.consensus_network(), address)
+            .map_err(NozyError::InvalidOperation)
+    }
+    
+    
+    // The ZIP-317 fee a payment pays when funded the way `inputs` asks
+    pub fn minimum_fee(&self, to: &str, amount: u64, inputs: &InputSelection) -> NozyResult<u64> {
+        match inputs {
+            InputSelection::Automatic(strategy) => Ok(self.plan_spend(to, amount, strategy.clone())?.fee),
+            InputSelection::Manual(note_ids) => {
+                let notes = self.note_manager.select_specific_notes(note_ids, amount)?;
+                selection::fee_for_notes(&notes, amount, self.recipient_pool(to)?)
+            }
+        }
+    }
+    
+    
+    // Every strategy's plan for the same payment, so they can be compared before sending
+    pub fn compare_selection_strategies(&self, to: &str, amount: u64) -> Vec<(NoteSelectionStrategy, NozyResult<SelectionPlan>)> {
+        NoteSelectionStrategy::all()
+            .into_iter()
+            .map(|strategy| {
+                let plan = self.plan_spend(to, amount, Some(strategy.clone()));
+                (strategy, plan)
+            })
+            .collect()
+    }
+    
+    
+    // Builds and signs a payment, then locks its input notes until it is mined or expires
+    pub fn build_transaction(
+        &mut self,
+        to: &str,
+        amount: u64,
+        fee: u64,
+        memo: Option<Vec<u8>>,
+        selection: InputSelection,
+    ) -> NozyResult<SignedTransaction> {
+        let payment = Payment {
+            address: to.to_string(),
+            value: amount,
+            memo,
+        };
+        self.build_payments(vec![payment], fee, selection)
+    }
+    
+    
+    pub fn build_payments(&mut self, payments: Vec<Payment>, fee: u64, selection: InputSelection) -> NozyResult<SignedTransaction> {
+        let hd_wallet = self.hd_wallet.clone()
+            .ok_or_else(|| NozyError::InvalidOperation("No seed loaded; cannot sign transactions".to_string()))?;
+        let (spending_key, trees, target_height) = self.spending_material()?;
+        let network = self.zebra_client.consensus_network();
+        let change_address = ViewingKeys::from_spending_key(&spending_key, network).orchard_change_address()?;
+        
+        let mut signer = TransactionSigner::new(hd_wallet, self.note_manager.clone(), network, change_address);
+        let transaction = signer.build_transaction_with_payments(payments, fee, selection)?;
+        
+        let inputs: Vec<ShieldedNote> = transaction.inputs.iter().map(|input| input.note.clone()).collect();
+        let context = BuildContext {
+            network,
+            target_height,
+            spending_key: &spending_key,
+            trees: &trees,
+            proving: &self.config.proving,
+        };
+        let unsigned = transactions::prepare_transaction(&inputs, &transaction.payments(), fee, &context)?;
+        let transaction = signer.sign_transaction_with_notes(transaction, unsigned, "default_password")?;
+        
+        let note_ids: Vec<String> = inputs.iter().map(|note| note.id.clone()).collect();
+        self.note_manager.reserve_notes(&note_ids, &transaction.txid(), transaction.expiry_height as u32)?;
+        self.update_status()?;
+        
+        Ok(transaction)
+    }
+    
+    
+    // Key, trees and target height for building; the trees' roots are the anchors spends prove against
+    fn spending_material(&mut self) -> NozyResult<(UnifiedSpendingKey, TreeState, u32)> {
+        let hd_wallet = self.hd_wallet.as_ref()
+            .ok_or_else(|| NozyError::InvalidOperation("No seed loaded; cannot sign transactions".to_string()))?;
+        let spending_key = hd_wallet.unified_spending_key(&self.zebra_client.consensus_network(), "default_password")?;
+        let trees = TreeState::load(&self.sync_state)?;
+        let target_height = self.current_height()? + 1;
+        Ok((spending_key, trees, target_height))
+    }
+    
+    
+    // A rejected broadcast can never be mined, so its notes are unlocked straight away
+    pub fn broadcast_signed_transaction(&mut self, transaction: &SignedTransaction) -> NozyResult<String> {
+        let txid = transaction.txid();
+        
+        match self.zebra_client.broadcast_transaction(&transaction.raw_transaction) {
+            Ok(result) => Ok(result),
+            Err(e) => {
+                self.note_manager.release_reservation(&txid);
+                self.update_status()?;
+                Err(e)
+            }
+        }
+    }
+    
+    
+    pub fn release_transaction_notes(&mut self, txid: &str) -> NozyResult<Vec<String>> {
+        let released = self.note_manager.release_reservation(txid);
+        self.update_status()?;
+        Ok(released)
+    }
+    
+    
+    pub fn get_locked_balance(&self) -> u64 {
+        self.note_manager.get_reserved_balance()
+    }
+    
+    
+    // Live tip when Zebra is reachable, otherwise the last height we scanned
+    fn current_height(&mut self) -> NozyResult<u32> {
+        let live = match self.zebra_client.check_connection() {
+            Ok(true) => self.zebra_client.get_status().ok().and_then(|status| status.block_height),
+            _ => None,
+        };
+        
+        if let Some(height) = live {
+            self.note_manager.set_chain_height(height);
+        }
+        live.or(self.sync_state.scanned_height)
+            .ok_or_else(|| NozyError::InvalidOperation("Chain height unknown; run 'nozy network sync' first".to_string()))
     }
     
     
     pub fn broadcast_transaction(&mut self, transaction: &ShieldedTransaction) -> NozyResult<String> {
-        // TODO: Serialize transaction properly
-        let tx_data = b"placeholder_transaction";
-        self.zebra_client.broadcast_transaction(tx_data)
+        self.zebra_client.broadcast_transaction(&transaction.raw)
     }
     
     
@@ -441,16 +992,196 @@ impl NozyWallet {
     
     
     pub fn sync_wallet(&mut self) -> NozyResult<()> {
-        // TODO: Implement actual sync logic
-        // For now, just update status
+        self.sync_wallet_with_progress(&SyncOptions::default(), |_| {})?;
+        Ok(())
+    }
+    
+    
+    // Scans from the saved cursor to the chain tip, committing cursor, trees and notes
+    // together after every batch so an interrupted sync resumes where it stopped
+    pub fn sync_wallet_with_progress<F>(&mut self, options: &SyncOptions, mut on_event: F) -> NozyResult<SyncProgress>
+    where
+        F: FnMut(&SyncEvent),
+    {
+        self.zebra_client.check_connection()?;
+        let tip = self.zebra_client.get_status()?.block_height
+            .ok_or_else(|| NozyError::Network("Zebra did not report a block height".to_string()))?;
+        
+        let keys = self.viewing_keys()?;
+        self.note_manager.derive_missing_nullifiers(&keys);
+        let start = self.sync_start_height();
+        
+        let mut trees = if self.sync_state.scanned_height.is_some() {
+            TreeState::load(&self.sync_state)?
+        } else {
+            TreeState::from_raw(&self.zebra_client.get_tree_state(start.saturating_sub(1))?)?
+        };
+        
+        let mut progress = SyncProgress::new(start, tip);
+        on_event(&SyncEvent::Started(progress.clone()));
+        
+        let mut outcome = Ok(());
+        for height in start..=tip {
+            if options.is_cancelled() {
+                break;
+            }
+            
+            let scanned = self.zebra_client.get_block_transactions(height)
+                .and_then(|txs| scan_block(&mut trees, &mut self.note_manager, &keys, height, &txs));
+            let scanned = match scanned {
+                Ok(scanned) => scanned,
+                Err(e) => {
+                    outcome = Err(e);
+                    break;
+                }
+            };
+            
+            // The block is in the notes and trees now, so it counts as scanned whatever follows
+            progress.current_height = height;
+            progress.notes_found += scanned.notes_found;
+            progress.spends_found += scanned.spent_note_ids.len();
+            
+            for sent in scanned.sent {
+                self.history.add_outgoing(sent);
+            }
+            let tagged = scanned.new_note_ids.iter()
+                .try_for_each(|note_id| self.tag_from_address_label(note_id));
+            if let Err(e) = tagged {
+                outcome = Err(e);
+                break;
+            }
+            on_event(&SyncEvent::Progress(progress.clone()));
+            
+            if progress.blocks_scanned().is_multiple_of(options.batch_size.max(1)) {
+                self.commit_sync_batch(&trees, height, options)?;
+                on_event(&SyncEvent::BatchSaved(progress.clone()));
+            }
+        }
+        
+        // Whatever stopped the loop, everything up to the last whole block is kept
+        if progress.blocks_scanned() > 0 {
+            self.commit_sync_batch(&trees, progress.current_height, options)?;
+        }
+        outcome?;
+        
+        if options.is_cancelled() {
+            on_event(&SyncEvent::Cancelled(progress.clone()));
+        } else {
+            self.status.last_sync = Some(chrono::Utc::now().to_rfc3339());
+            on_event(&SyncEvent::Finished(progress.clone()));
+        }
+        
+        Ok(progress)
+    }
+    
+    
+    // Only applied when a note is first found, so tags the user removed stay removed
+    fn tag_from_address_label(&mut self, note_id: &str) -> NozyResult<()> {
+        let network = self.zebra_client.consensus_network();
+        let label = self.note_manager.get_note(note_id)
+            .and_then(|note| self.address_manager.get_label(&network, &note.recipient_address))
+            .map(str::to_string);
+        if let Some(label) = label {
+            self.note_manager.add_note_tag(note_id, &label)?;
+        }
+        Ok(())
+    }
+    
+    
+    fn commit_sync_batch(&mut self, trees: &TreeState, height: u32, options: &SyncOptions) -> NozyResult<()> {
+        trees.update_notes(&mut self.note_manager);
+        self.note_manager.release_expired_reservations(height);
+        self.note_manager.set_chain_height(height);
+        trees.store(&mut self.sync_state)?;
+        self.sync_state.scanned_height = Some(height);
+        self.sync_state.rescan_from = None;
         self.update_status()?;
-        self.status.last_sync = Some("now".to_string());
+        
+        if let Some(path) = &options.checkpoint_path {
+            self.save_to_file(path)?;
+        }
         Ok(())
     }
     
     
+    fn sync_start_height(&self) -> u32 {
+        if let Some(scanned) = self.sync_state.scanned_height {
+            return scanned + 1;
+        }
+        
+        // Nothing before the oldest note we know about can be relevant
+        self.sync_state.rescan_from
+            .or(self.sync_state.birthday_height)
+            .or_else(|| self.note_manager.get_unspent_notes()
+                .iter()
+                .map(|note| note.created_at_height)
+                .filter(|height| *height > 0)
+                .min())
+            .unwrap_or_else(|| {
+                self.zebra_client.consensus_network()
+                    .activation_height(NetworkUpgrade::Sapling)
+                    .map(u32::from)
+                    .unwrap_or(1)
+            })
+    }
+    
+    
+    pub fn get_sync_state(&self) -> &SyncState {
+        &self.sync_state
+    }
+    
+    
+    // Drops the cursor and tree state; the next sync rescans from `from_height`, or the birthday
+    pub fn reset_sync(&mut self, from_height: Option<u32>) {
+        self.sync_state = SyncState::new(self.sync_state.birthday_height);
+        self.sync_state.rescan_from = from_height;
+    }
+    
+    
+    // Writes to a temporary file first so a crash never leaves a half-written wallet
+    pub fn save_to_file(&self, path: &Path) -> NozyResult<()> {
+        let data = serde_json::to_string_pretty(self)?;
+        let tmp_path = path.with_extension("json.tmp");
+        fs::write(&tmp_path, data)?;
+        fs::rename(&tmp_path, path)?;
+        Ok(())
+    }
+    
+    
+    pub fn poll_mempool(&mut self) -> NozyResult<MempoolUpdate> {
+        let keys = self.viewing_keys()?;
+        let update = self.mempool.poll(&self.zebra_client, &keys, &self.note_manager)?;
+        self.note_manager.release_expired_reservations(update.tip_height);
+        self.note_manager.set_chain_height(update.tip_height);
+        self.update_status()?;
+        Ok(update)
+    }
+    
+    
+    pub fn get_pending_incoming(&self) -> &[PendingIncoming] {
+        self.mempool.pending_incoming()
+    }
+    
+    
+    pub fn get_pending_spends(&self) -> &[PendingSpend] {
+        self.mempool.pending_spends()
+    }
+    
+    
+    fn viewing_keys(&self) -> NozyResult<ViewingKeys> {
+        let hd_wallet = self.hd_wallet.as_ref()
+            .ok_or_else(|| NozyError::InvalidOperation("No seed loaded; cannot derive viewing keys".to_string()))?;
+        let network = self.zebra_client.consensus_network();
+        let usk = hd_wallet.unified_spending_key(&network, "default_password")?;
+        Ok(ViewingKeys::from_spending_key(&usk, network))
+    }
+    
+    
     fn update_status(&mut self) -> NozyResult<()> {
         self.status.total_balance = self.note_manager.get_total_balance();
+        self.status.unconfirmed_incoming = self.mempool.pending_incoming_value();
+        self.status.unconfirmed_spent = self.mempool.pending_spent_value();
+        self.status.locked_balance = self.note_manager.get_reserved_balance();
         self.status.address_count = self.address_manager.get_all_addresses().len();
         self.status.note_count = self.note_manager.get_unspent_notes().len();
         Ok(())
@@ -459,9 +1190,42 @@ impl NozyWallet {
     // Privacy methods
     
     pub fn set_privacy_level(&mut self, level: PrivacyLevel) -> NozyResult<()> {
+        // Maximum always implies scan-only; lower levels leave the setting as it was
+        if level == PrivacyLevel::Maximum {
+            self.set_scan_only(true);
+        }
         self.privacy_level = level;
         Ok(())
     }
+    
+    
+    pub fn set_scan_only(&mut self, enabled: bool) {
+        self.config.privacy.scan_only = enabled;
+        self.zebra_client.config.scan_only = enabled;
+    }
+    
+    
+    pub fn is_scan_only(&self) -> bool {
+        self.zebra_client.config.scan_only
+    }
+    
+    
+    pub fn set_params_dir(&mut self, dir: Option<PathBuf>) {
+        self.config.proving.params_dir = dir;
+    }
+    
+    
+    pub fn sapling_params_dir(&self) -> NozyResult<PathBuf> {
+        prover::sapling_params_dir(&self.config.proving)
+    }
+    
+    // Only wallets holding Sapling notes need the parameters, so the others skip the hashing
+    pub fn check_sapling_params(&self) -> NozyResult<()> {
+        if self.note_manager.get_unspent_notes_by_type(NoteType::Sapling).is_empty() {
+            return Ok(());
+        }
+        prover::check_sapling_params(&self.sapling_params_dir()?)
+    }
 
     
     pub fn get_privacy_level(&self) -> PrivacyLevel {
@@ -496,18 +1260,233 @@ impl NozyWallet {
     }
 
     
-    pub fn consolidate_notes(&mut self, force: bool) -> NozyResult<usize> {
-        // TODO: Implement actual note consolidation logic
-        let consolidated_count = if force { 5 } else { 3 };
-        Ok(consolidated_count)
+    // `force` plans a consolidation even when it is turned off in the config
+    pub fn plan_consolidation(&self, force: bool) -> Option<ConsolidationPlan> {
+        self.note_manager.plan_consolidation(force)
+    }
+    
+    
+    pub fn consolidate_notes(&mut self, plan: &ConsolidationPlan) -> NozyResult<SignedTransaction> {
+        let to = self.self_send_address()?;
+        self.build_transaction(&to, plan.output_value, plan.fee, None, InputSelection::Manual(plan.note_ids.clone()))
     }
 
     
-    pub fn mix_notes(&mut self, rounds: u32) -> NozyResult<()> {
-        // TODO: Implement actual note mixing logic
-        println!("Mixing notes for {} rounds...", rounds);
+    pub fn start_mixing(&mut self, rounds: Option<u32>) -> NozyResult<&MixingSession> {
+        let (enabled, default_rounds) = self.note_manager.mixing_settings();
+        if !enabled {
+            return Err(NozyError::Config("Note mixing is disabled in the wallet configuration".to_string()));
+        }
+        if self.mixing.as_ref().is_some_and(|session| !session.is_finished()) {
+            return Err(NozyError::InvalidOperation("A mixing session is already running".to_string()));
+        }
+        
+        let rounds = rounds.unwrap_or(default_rounds);
+        if rounds == 0 {
+            return Err(NozyError::InvalidOperation("Mixing needs at least one round".to_string()));
+        }
+        let height = self.current_height()?;
+        Ok(self.mixing.insert(MixingSession::new(rounds, height)))
+    }
+    
+    
+    pub fn get_mixing_session(&self) -> Option<&MixingSession> {
+        self.mixing.as_ref()
+    }
+    
+    
+    // Stops planning new rounds; a round already broadcast still confirms or expires on its own
+    pub fn abort_mixing(&mut self) -> Option<MixingSession> {
+        self.mixing.take()
+    }
+    
+    
+    // Advances the mix by at most one round. Call it again after each sync.
+    pub fn mixing_step(&mut self) -> NozyResult<MixStep> {
+        let mut session = self.mixing.clone()
+            .ok_or_else(|| NozyError::InvalidOperation("No mixing session; start one first".to_string()))?;
+        let step = self.advance_mixing(&mut session);
+        self.mixing = Some(session);
+        step
+    }
+    
+    
+    fn advance_mixing(&mut self, session: &mut MixingSession) -> NozyResult<MixStep> {
+        if let Some(pending) = session.pending.clone() {
+            let inputs: Vec<&ShieldedNote> = pending.input_note_ids.iter()
+                .filter_map(|note_id| self.note_manager.get_note(note_id))
+                .collect();
+            
+            if !inputs.is_empty() && inputs.iter().all(|note| note.is_spent()) {
+                let confirmed_height = inputs.iter()
+                    .filter_map(|note| note.spent_at_height)
+                    .max()
+                    .unwrap_or(pending.built_at_height);
+                session.complete_pending(confirmed_height);
+            } else if inputs.iter().any(|note| self.note_manager.get_reservation(&note.id)
+                .is_some_and(|reservation| reservation.txid == pending.txid))
+            {
+                return Ok(MixStep::WaitingForConfirmation(pending));
+            } else {
+                // Expired without being mined; the inputs are free again and the round is retried
+                session.pending = None;
+            }
+        }
+        
+        if session.is_finished() {
+            return Ok(MixStep::Finished { rounds: session.rounds_done, fees_paid: session.fees_paid });
+        }
+        
+        let height = self.current_height()?;
+        if height < session.next_round_height {
+            return Ok(MixStep::WaitingUntil(session.next_round_height));
+        }
+        
+        let notes = self.note_manager.get_selectable_notes(FeeRule::Zip317);
+        let Some(plan) = mixing::plan_round(&mut rand::thread_rng(), &notes, self.dust_threshold()) else {
+            return Ok(MixStep::WaitingForNotes);
+        };
+        
+        let keys = self.viewing_keys()?;
+        let mut payments = Vec::new();
+        for value in &plan.output_values {
+            payments.push(Payment {
+                address: keys.orchard_address_at(session.take_diversifier_index())?,
+                value: *value,
+                memo: None,
+            });
+        }
+        
+        let transaction = self.build_payments(payments, plan.fee, InputSelection::Manual(plan.input_note_ids.clone()))?;
+        self.broadcast_signed_transaction(&transaction)?;
+        
+        let round = MixingRound {
+            round: session.rounds_done + 1,
+            kind: plan.kind,
+            txid: transaction.txid(),
+            input_note_ids: plan.input_note_ids,
+            output_values: plan.output_values,
+            fee: plan.fee,
+            built_at_height: height,
+        };
+        session.pending = Some(round.clone());
+        Ok(MixStep::Broadcast(round))
+    }
+    
+    
+    // Schedule for moving the spendable Sapling balance to Orchard; nothing is stored until start_migration
+    pub fn plan_migration(&mut self) -> NozyResult<Option<MigrationSession>> {
+        let height = self.current_height()?;
+        let sapling_balance: u64 = self.note_manager.get_selectable_notes(FeeRule::Zip317)
+            .iter()
+            .filter(|note| note.note_type == NoteType::Sapling)
+            .map(|note| note.value)
+            .sum();
+        Ok(MigrationSession::plan(&mut rand::thread_rng(), sapling_balance, height))
+    }
+    
+    
+    pub fn start_migration(&mut self, session: MigrationSession) -> NozyResult<()> {
+        if self.migration.as_ref().is_some_and(|current| !current.is_finished()) {
+            return Err(NozyError::InvalidOperation("A Sapling migration is already running".to_string()));
+        }
+        self.migration = Some(session);
         Ok(())
     }
+    
+    
+    pub fn get_migration_session(&self) -> Option<&MigrationSession> {
+        self.migration.as_ref()
+    }
+    
+    
+    pub fn abort_migration(&mut self) -> Option<MigrationSession> {
+        self.migration.take()
+    }
+    
+    
+    // Sends at most one due transfer per call
+    pub fn migration_step(&mut self) -> NozyResult<MigrationStep> {
+        let mut session = self.migration.clone()
+            .ok_or_else(|| NozyError::InvalidOperation("No Sapling migration; start one first".to_string()))?;
+        let step = self.advance_migration(&mut session);
+        self.migration = Some(session);
+        step
+    }
+    
+    
+    fn advance_migration(&mut self, session: &mut MigrationSession) -> NozyResult<MigrationStep> {
+        for transfer in session.transfers.iter_mut().filter(|transfer| transfer.status == TransferStatus::Broadcast) {
+            let inputs: Vec<&ShieldedNote> = transfer.input_note_ids.iter()
+                .filter_map(|note_id| self.note_manager.get_note(note_id))
+                .collect();
+            
+            if !inputs.is_empty() && inputs.iter().all(|note| note.is_spent()) {
+                transfer.status = TransferStatus::Confirmed;
+                transfer.confirmed_height = inputs.iter().filter_map(|note| note.spent_at_height).max();
+            } else if !inputs.iter().any(|note| self.note_manager.get_reservation(&note.id)
+                .is_some_and(|reservation| Some(&reservation.txid) == transfer.txid.as_ref()))
+            {
+                // Expired without being mined; send it again
+                transfer.status = TransferStatus::Scheduled;
+                transfer.txid = None;
+                transfer.input_note_ids.clear();
+            }
+        }
+        
+        if session.is_finished() {
+            return Ok(MigrationStep::Finished { moved: session.moved_value(), fees_paid: session.fees_paid() });
+        }
+        
+        let in_flight = session.count(TransferStatus::Broadcast);
+        let height = self.current_height()?;
+        let Some(index) = session.transfers.iter()
+            .position(|transfer| transfer.status == TransferStatus::Scheduled)
+        else {
+            return Ok(MigrationStep::WaitingForConfirmation(in_flight));
+        };
+        if height < session.transfers[index].scheduled_height {
+            return Ok(MigrationStep::WaitingUntil(session.transfers[index].scheduled_height));
+        }
+        
+        let denomination = session.transfers[index].denomination;
+        let notes = self.note_manager.get_selectable_notes(FeeRule::Zip317);
+        let Some(plan) = migration::plan_transfer(&notes, denomination, self.dust_threshold()) else {
+            let sapling_pending = self.note_manager.get_pending_confirmation_notes()
+                .iter()
+                .any(|note| note.note_type == NoteType::Sapling);
+            if in_flight > 0 || sapling_pending {
+                return Ok(MigrationStep::WaitingForNotes);
+            }
+            session.transfers[index].status = TransferStatus::Skipped;
+            return Ok(MigrationStep::Skipped(session.transfers[index].clone()));
+        };
+        
+        let keys = self.viewing_keys()?;
+        let mut payments = vec![Payment {
+            address: keys.orchard_address_at(session.take_diversifier_index())?,
+            value: denomination,
+            memo: None,
+        }];
+        // Change stays in Sapling so only the denomination crosses pools
+        if plan.change > 0 {
+            payments.push(Payment {
+                address: keys.sapling_change_address(),
+                value: plan.change,
+                memo: None,
+            });
+        }
+        
+        let transaction = self.build_payments(payments, plan.fee, InputSelection::Manual(plan.input_note_ids.clone()))?;
+        self.broadcast_signed_transaction(&transaction)?;
+        
+        let transfer = &mut session.transfers[index];
+        transfer.status = TransferStatus::Broadcast;
+        transfer.txid = Some(transaction.txid());
+        transfer.input_note_ids = plan.input_note_ids;
+        transfer.fee = plan.fee;
+        Ok(MigrationStep::Broadcast(transfer.clone()))
+    }
 
     
     pub fn create_privacy_mask(&mut self, name: String, mask_type: PrivacyMaskType) -> NozyResult<()> {
@@ -712,10 +1691,14 @@ impl NozyWallet {
     
     pub fn get_performance_metrics(&self) -> NozyResult<PerformanceMetrics> {
         // TODO: Implement actual performance tracking
+        let proofs = crate::prover::proof_times();
         Ok(PerformanceMetrics {
             sync_time: 1000,
             transaction_processing_time: 500,
             memory_usage: 50,
+            last_proof_time: proofs.last.as_millis() as u64,
+            average_proof_time: proofs.average().as_millis() as u64,
+            proofs_created: proofs.proofs,
         })
     }
 
@@ -738,18 +1721,222 @@ impl NozyWallet {
     }
 
     
-    pub fn debug_note_issues(&mut self) -> NozyResult<Vec<String>> {
-        // TODO: Implement actual note debugging
-        Ok(vec!["No issues found".to_string()])
+    // Checks stored notes, witnesses and address counters; with `repair`, fixes what it safely can
+    pub fn fsck(&mut self, repair: bool) -> NozyResult<FsckReport> {
+        let mut report = FsckReport::default();
+        let mut rescan_heights = Vec::new();
+        let network = self.zebra_client.consensus_network();
+        
+        // Duplicates first, so the later checks do not report them twice
+        let duplicates = fsck::find_duplicates(&self.note_manager.get_all_notes());
+        for (keep, drop) in duplicates {
+            report.push(IssueKind::DuplicateNote, drop.as_str(), format!("Same commitment as note {}", keep),
+                format!("Merge into note {}", keep));
+            if repair {
+                self.note_manager.merge_duplicate_note(&keep, &drop)?;
+                report.mark_last_repaired();
+            }
+        }
+        
+        let notes: Vec<ShieldedNote> = self.note_manager.get_all_notes().into_iter().cloned().collect();
+        report.notes_checked = notes.len();
+        
+        for note in &notes {
+            // Notes pulled from the indexer carry no address or randomness to rebuild from
+            if note.recipient_address.is_empty() {
+                continue;
+            }
+            let expected = match fsck::chain_commitment(note, &network) {
+                Some(commitment) => commitment,
+                None => self.note_manager.local_commitment(note)?,
+            };
+            let expected_id = self.note_manager.expected_note_id(note);
+            
+            if expected != note.commitment || expected_id != note.id {
+                let scanned = note.tx_hash.is_some() && note.created_at_height > 0;
+                let detail = if expected != note.commitment {
+                    "Commitment does not match the note's address, value and randomness"
+                } else {
+                    "Note ID does not match its commitment"
+                };
+                let fix = if scanned {
+                    format!("Drop the note and rescan from block {}", note.created_at_height)
+                } else {
+                    "Recreate the note; it was not found on-chain so a rescan cannot restore it".to_string()
+                };
+                report.push(IssueKind::CommitmentMismatch, note.id.as_str(), detail, fix);
+                if repair && scanned {
+                    self.note_manager.remove_note(&note.id);
+                    rescan_heights.push(note.created_at_height);
+                    report.mark_last_repaired();
+                }
+            }
+        }
+        
+        let notes: Vec<ShieldedNote> = self.note_manager.get_all_notes().into_iter().cloned().collect();
+        let note_refs: Vec<&ShieldedNote> = notes.iter().collect();
+        for (nullifier, note_ids) in fsck::find_nullifier_collisions(&note_refs) {
+            let heights = notes.iter().filter(|note| note_ids.contains(&note.id)).map(|note| note.created_at_height);
+            let from = heights.min().unwrap_or(0);
+            report.push(IssueKind::NullifierCollision, note_ids.join(", "),
+                format!("Notes share nullifier {}", hex::encode(&nullifier)),
+                format!("Rescan from block {} to recompute nullifiers", from));
+            if repair {
+                rescan_heights.push(from);
+                report.mark_last_repaired();
+            }
+        }
+        
+        for note in notes.iter().filter(|note| note.spent_at_height.is_some() && note.spent_in_txid.is_none()) {
+            let spent_at = note.spent_at_height.unwrap_or(0);
+            report.push(IssueKind::MissingSpendingTx, note.id.as_str(),
+                format!("Marked spent at block {} but the spending transaction is unknown", spent_at),
+                format!("Unmark the spend and rescan from block {}", note.created_at_height));
+            if repair {
+                self.note_manager.clear_spend(&note.id)?;
+                rescan_heights.push(note.created_at_height);
+                report.mark_last_repaired();
+            }
+        }
+        
+        self.check_witnesses(&mut report, &mut rescan_heights, repair)?;
+        
+        for (address_type, current, needed) in self.address_manager.counter_shortfalls() {
+            report.push(IssueKind::AddressCounterBehind, format!("{:?} addresses", address_type),
+                format!("Counter is {} but index {} is already in use", current, needed - 1),
+                format!("Raise the counter to {}", needed));
+            if repair {
+                self.address_manager.set_address_count(address_type, needed);
+                report.mark_last_repaired();
+            }
+        }
+        
+        // One rescan covers every repair; start no later than the oldest unspent note so its witness is rebuilt
+        if let Some(height) = rescan_heights.into_iter().min() {
+            let oldest_unspent = self.note_manager.get_unspent_notes()
+                .iter()
+                .map(|note| note.created_at_height)
+                .filter(|height| *height > 0)
+                .min();
+            let from = oldest_unspent.map_or(height, |oldest| oldest.min(height));
+            self.reset_sync(Some(from));
+            report.rescan_from = Some(from);
+        }
+        
+        if repair {
+            self.update_status()?;
+        }
+        Ok(report)
+    }
+    
+    
+    fn check_witnesses(&mut self, report: &mut FsckReport, rescan_heights: &mut Vec<u32>, repair: bool) -> NozyResult<()> {
+        let Some(scanned_height) = self.sync_state.scanned_height else {
+            report.skipped.push("Witness checks: the wallet has not been synced".to_string());
+            return Ok(());
+        };
+        
+        let mut trees = match TreeState::load(&self.sync_state) {
+            Ok(trees) => trees,
+            Err(e) => {
+                let from = self.sync_state.birthday_height.unwrap_or(0);
+                report.push(IssueKind::AnchorMismatch, "sync state", format!("Stored trees cannot be read: {}", e),
+                    format!("Rescan from block {}", from));
+                if repair {
+                    rescan_heights.push(from);
+                    report.mark_last_repaired();
+                }
+                return Ok(());
+            }
+        };
+        
+        let mut changed = false;
+        let checks = trees.check_witnesses();
+        report.witnesses_checked = checks.len();
+        for check in checks {
+            let note = self.note_manager.get_note(&check.note_id).cloned();
+            match note {
+                Some(note) if !note.is_spent() => {
+                    if !check.root_matches {
+                        report.push(IssueKind::WitnessRootMismatch, check.note_id.as_str(),
+                            format!("{:?} witness does not authenticate against the stored anchor", check.pool),
+                            format!("Rescan from block {}", note.created_at_height));
+                        if repair {
+                            rescan_heights.push(note.created_at_height);
+                            report.mark_last_repaired();
+                        }
+                    } else if note.position != Some(check.position) {
+                        report.push(IssueKind::WitnessRootMismatch, check.note_id.as_str(),
+                            format!("Note position {:?} differs from witness position {}", note.position, check.position),
+                            "Copy the position and path from the witness");
+                        if repair {
+                            trees.update_notes(&mut self.note_manager);
+                            report.mark_last_repaired();
+                        }
+                    }
+                }
+                _ => {
+                    report.push(IssueKind::StaleWitness, check.note_id.as_str(),
+                        "Witness kept for a spent or unknown note", "Drop the witness");
+                    if repair {
+                        trees.forget(&check.note_id);
+                        changed = true;
+                        report.mark_last_repaired();
+                    }
+                }
+            }
+        }
+        
+        let unwitnessed: Vec<ShieldedNote> = self.note_manager.get_unspent_notes()
+            .into_iter()
+            .filter(|note| note.tx_hash.is_some() && note.created_at_height > 0 && note.created_at_height <= scanned_height)
+            .filter(|note| !trees.has_witness(&note.id))
+            .cloned()
+            .collect();
+        for note in unwitnessed {
+            report.push(IssueKind::MissingWitness, note.id.as_str(),
+                "Unspent note has no witness, so it cannot be spent",
+                format!("Rescan from block {}", note.created_at_height));
+            if repair {
+                rescan_heights.push(note.created_at_height);
+                report.mark_last_repaired();
+            }
+        }
+        
+        // The node's tree at the scanned height is the anchor our witnesses must agree with
+        match self.zebra_client.get_tree_state(scanned_height).and_then(|raw| TreeState::from_raw(&raw)) {
+            Ok(chain) => {
+                if chain.sapling_root() != trees.sapling_root() || chain.orchard_root() != trees.orchard_root() {
+                    let from = self.sync_state.birthday_height.unwrap_or(0);
+                    report.push(IssueKind::AnchorMismatch, "sync state",
+                        format!("Stored tree roots differ from the node's at block {}", scanned_height),
+                        format!("Rescan from block {}", from));
+                    if repair {
+                        rescan_heights.push(from);
+                        report.mark_last_repaired();
+                    }
+                }
+            }
+            Err(e) => report.skipped.push(format!("Anchor check against the node: {}", e)),
+        }
+        
+        if changed {
+            trees.store(&mut self.sync_state)?;
+        }
+        Ok(())
     }
 
     
     pub fn run_performance_benchmark(&mut self) -> NozyResult<PerformanceMetrics> {
         // TODO: Implement actual performance benchmarking
+        let proofs = crate::prover::proof_times();
         Ok(PerformanceMetrics {
             sync_time: 800,
             transaction_processing_time: 400,
             memory_usage: 45,
+            last_proof_time: proofs.last.as_millis() as u64,
+            average_proof_time: proofs.average().as_millis() as u64,
+            proofs_created: proofs.proofs,
         })
     }
 
@@ -830,4 +2017,146 @@ impl NozyWallet {
         hasher.update(seed_phrase.as_bytes());
         format!("{:x}", hasher.finalize())
     }
-} 
+}
+
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::scanner::scan_transaction;
+    use crate::zebra_integration::RawTreeState;
+    use zcash_primitives::consensus::{BlockHeight, BranchId, Network};
+    use zcash_keys::address::{Address, UnifiedAddress};
+    use zcash_primitives::transaction::Transaction;
+    
+    // A wallet holding real spendable Orchard notes, scanned up to just past NU6
+    fn funded_wallet(values: &[u64]) -> (NozyWallet, ViewingKeys) {
+        let network = Network::TestNetwork;
+        let (hd_wallet, spending_key, keys) = ViewingKeys::test_wallet();
+        
+        let mut wallet = NozyWallet::new(NozyConfig::default()).unwrap();
+        wallet.hd_wallet = Some(hd_wallet);
+        let mut trees = TreeState::from_raw(&RawTreeState { height: 0, sapling: None, orchard: None }).unwrap();
+        for value in values {
+            let note = trees.spendable_orchard_note(&spending_key, network, *value);
+            wallet.note_manager.add_note(note).unwrap();
+        }
+        trees.store(&mut wallet.sync_state).unwrap();
+        
+        let height = u32::from(network.activation_height(NetworkUpgrade::Nu6).unwrap()) + 10;
+        wallet.sync_state.scanned_height = Some(height);
+        wallet.note_manager.set_chain_height(height);
+        (wallet, keys)
+    }
+    
+    // What the built transaction pays back to us, read by decrypting it like a rescan would
+    fn received_back(transaction: &SignedTransaction, keys: &ViewingKeys) -> Vec<(u64, bool)> {
+        let parsed = Transaction::read(&transaction.raw_transaction[..], BranchId::Nu6).unwrap();
+        scan_transaction(&parsed, keys, BlockHeight::from_u32(transaction.expiry_height as u32)).unwrap()
+            .received.iter()
+            .map(|note| (note.value, note.is_change))
+            .collect()
+    }
+    
+    #[test]
+    fn test_reset_sync_keeps_the_birthday() {
+        let mut wallet = NozyWallet::new(NozyConfig::default()).unwrap();
+        wallet.sync_state = SyncState::new(Some(2_000_000));
+        wallet.sync_state.scanned_height = Some(2_100_000);
+        
+        wallet.reset_sync(Some(2_050_000));
+        assert_eq!(wallet.sync_start_height(), 2_050_000);
+        assert_eq!(wallet.sync_state.birthday_height, Some(2_000_000));
+        
+        // A later reset without a height goes back to the birthday, not the last rescan
+        wallet.reset_sync(None);
+        assert_eq!(wallet.sync_start_height(), 2_000_000);
+    }
+    
+    #[test]
+    fn test_payment_request_amounts_are_exact() {
+        let (mut wallet, _) = funded_wallet(&[]);
+        
+        for (text, zatoshi) in [("0.29", 29_000_000), ("1.15", 115_000_000)] {
+            let amount = payouts::parse_zec(text).unwrap();
+            let uri = wallet.request_payment(Some(amount), None, None, None).unwrap().to_uri();
+            assert!(uri.ends_with(&format!("?amount={}", text)), "{}", uri);
+            assert_eq!(wallet.parse_payment_request(&uri).unwrap().payments[0].amount, Some(zatoshi));
+        }
+    }
+    
+    #[test]
+    fn test_notes_on_a_labelled_full_address_are_tagged() {
+        let network = Network::TestNetwork;
+        let (mut wallet, keys) = funded_wallet(&[50_000]);
+        let note_id = wallet.note_manager.get_all_notes()[0].id.clone();
+        
+        // The note records only its Orchard receiver; the user labels the address they handed out
+        let Some(Address::Unified(orchard)) = Address::decode(&network, &keys.orchard_address_at(0).unwrap()) else {
+            panic!("not a unified address");
+        };
+        let Some(Address::Sapling(sapling)) = Address::decode(&network, &keys.sapling_change_address()) else {
+            panic!("not a Sapling address");
+        };
+        let full = UnifiedAddress::from_receivers(orchard.orchard().copied(), Some(sapling), None).unwrap().encode(&network);
+        wallet.set_address_label(&full, Some("Invoices")).unwrap();
+        
+        wallet.tag_from_address_label(&note_id).unwrap();
+        assert_eq!(wallet.note_manager.get_note(&note_id).unwrap().tags, vec!["invoices".to_string()]);
+        assert_eq!(wallet.get_address_label(&keys.sapling_change_address()), Some("invoices"));
+        
+        wallet.set_address_label(&full, None).unwrap();
+        assert_eq!(wallet.get_address_label(&keys.orchard_address_at(0).unwrap()), None);
+        assert!(wallet.set_address_label("not an address", Some("invoices")).is_err());
+    }
+    
+    #[test]
+    fn test_sweep_dust_pays_the_internal_change_address() {
+        let (mut wallet, keys) = funded_wallet(&[100_000, 4_500]);
+        
+        let sweep = wallet.plan_dust_sweep().unwrap();
+        assert_eq!(sweep.dust_note_ids.len(), 1);
+        let transaction = wallet.sweep_dust(&sweep).unwrap();
+        
+        assert_eq!(transaction.inputs.len(), 2);
+        assert_eq!(transaction.outputs.len(), 1);
+        assert_eq!(transaction.outputs[0].address, keys.orchard_change_address().unwrap());
+        assert_eq!(received_back(&transaction, &keys), vec![(sweep.output_value, true)]);
+    }
+    
+    #[test]
+    fn test_split_outputs_come_back_as_change() {
+        let (mut wallet, keys) = funded_wallet(&[1_000_000]);
+        
+        let plan = wallet.plan_split(3, None).unwrap();
+        let transaction = wallet.split_notes(&plan).unwrap();
+        
+        let change_address = keys.orchard_change_address().unwrap();
+        assert_eq!(transaction.outputs.len(), 3);
+        assert!(transaction.outputs.iter().all(|output| output.address == change_address));
+        
+        let mut received = received_back(&transaction, &keys);
+        received.sort();
+        let mut expected: Vec<(u64, bool)> = plan.output_values.iter().map(|value| (*value, true)).collect();
+        expected.sort();
+        assert_eq!(received, expected);
+    }
+    
+    #[test]
+    fn test_consolidation_builds_one_change_note() {
+        let (mut wallet, keys) = funded_wallet(&[8_000, 9_000, 9_500, 500_000]);
+        
+        let plan = wallet.plan_consolidation(true).unwrap();
+        assert_eq!(plan.note_ids.len(), 3);
+        let transaction = wallet.consolidate_notes(&plan).unwrap();
+        
+        let mut spent: Vec<String> = transaction.inputs.iter().map(|input| input.note.id.clone()).collect();
+        let mut planned = plan.note_ids.clone();
+        spent.sort();
+        planned.sort();
+        assert_eq!(spent, planned);
+        assert_eq!(transaction.fee, plan.fee);
+        assert_eq!(transaction.outputs[0].address, keys.orchard_change_address().unwrap());
+        assert_eq!(received_back(&transaction, &keys), vec![(plan.output_value, true)]);
+    }
+}
@@ -1,7 +1,12 @@
 //! Zebra integration this is the real deal fully private and secure Nozy wallet
 
 use crate::error::{NozyError, NozyResult};
+use crate::fees::{self, ActionCounts};
 use serde::{Deserialize, Serialize};
+use zcash_encoding::CompactSize;
+use zcash_primitives::block::BlockHeader;
+use zcash_primitives::consensus::{BlockHeight, BranchId, Network};
+use zcash_primitives::transaction::Transaction;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ZebraConfig {
@@ -9,8 +14,31 @@ pub struct ZebraConfig {
     pub indexer_endpoint: String,
     pub network: String,
     pub timeout: u64,
+    // Refuse anything but block, tree-state and mempool RPCs, and never send an address or key
+    #[serde(default = "default_scan_only")]
+    pub scan_only: bool,
 }
 
+fn default_scan_only() -> bool {
+    true
+}
+
+// The only RPCs allowed in scan-only mode; none of them take wallet data
+const SCAN_ONLY_METHODS: [&str; 6] = [
+    "getblockchaininfo",
+    "getblock",
+    "z_gettreestate",
+    "getrawmempool",
+    "getrawtransaction",
+    "sendrawtransaction",
+];
+
+// Bech32 prefixes of viewing and spending keys, on both networks
+const KEY_PREFIXES: [&str; 10] = [
+    "uview", "uivk", "zxviews", "zxviewtestsapling", "zivks", "zivktestsapling",
+    "secret-extended-key", "uviewtest", "uivktest", "zxsprout",
+];
+
 // Default config for the Nozy wallet O dont know if we need this or not but it's here Nozy
 impl Default for ZebraConfig {
     fn default() -> Self {
@@ -19,6 +47,7 @@ impl Default for ZebraConfig {
             indexer_endpoint: "http://127.0.0.1:19067".to_string(),
             network: "testnet".to_string(),
             timeout: 30,
+            scan_only: true,
         }
     }
 }
@@ -39,6 +68,13 @@ pub enum SyncStatus {
     Error,
 }
 
+#[derive(Debug, Clone)]
+pub struct RawTreeState {
+    pub height: u32,
+    pub sapling: Option<Vec<u8>>,
+    pub orchard: Option<Vec<u8>>,
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct ZebraClient {
     pub config: ZebraConfig,
@@ -118,6 +154,134 @@ impl ZebraClient {
         })
     }
     
+    pub fn consensus_network(&self) -> Network {
+        if self.config.network == "mainnet" {
+            Network::MainNetwork
+        } else {
+            Network::TestNetwork
+        }
+    }
+    
+    // Checked before anything leaves the machine
+    fn ensure_private(&self, method: &str, params: &serde_json::Value) -> NozyResult<()> {
+        if !self.config.scan_only {
+            return Ok(());
+        }
+        if !SCAN_ONLY_METHODS.contains(&method) {
+            return Err(NozyError::Privacy(format!("{} is not allowed in scan-only mode", method)));
+        }
+        if reveals_wallet_data(params) {
+            return Err(NozyError::Privacy(format!("{} request contains an address or viewing key", method)));
+        }
+        Ok(())
+    }
+    
+    fn call_rpc(&self, method: &str, params: serde_json::Value) -> NozyResult<serde_json::Value> {
+        self.ensure_private(method, &params)?;
+        if !self.connected {
+            return Err(NozyError::Network("Not connected to Zebra".to_string()));
+        }
+        
+        let response = reqwest::blocking::Client::new()
+            .post(&self.config.rpc_endpoint)
+            .timeout(std::time::Duration::from_secs(self.config.timeout))
+            .json(&serde_json::json!({
+                "jsonrpc": "2.0",
+                "id": 1,
+                "method": method,
+                "params": params
+            }))
+            .send()
+            .map_err(|e| NozyError::Network(format!("{} failed: {}", method, e)))?;
+
+        let body: serde_json::Value = response.json()
+            .map_err(|e| NozyError::Network(format!("Failed to parse {} response: {}", method, e)))?;
+
+        if let Some(error) = body.get("error").filter(|e| !e.is_null()) {
+            return Err(NozyError::Network(format!("{} returned error: {}", method, error)));
+        }
+
+        body.get("result")
+            .cloned()
+            .ok_or_else(|| NozyError::Network(format!("No result in {} response", method)))
+    }
+    
+    pub fn get_block_transactions(&self, height: u32) -> NozyResult<Vec<Transaction>> {
+        let result = self.call_rpc("getblock", serde_json::json!([height.to_string(), 0]))?;
+        
+        let block_hex = result.as_str()
+            .ok_or_else(|| NozyError::Network(format!("Block {} is not raw hex", height)))?;
+        let block_bytes = hex::decode(block_hex)
+            .map_err(|e| NozyError::Serialization(format!("Invalid block hex at {}: {}", height, e)))?;
+        
+        let branch_id = BranchId::for_height(&self.consensus_network(), BlockHeight::from_u32(height));
+        let mut reader = &block_bytes[..];
+        
+        BlockHeader::read(&mut reader)
+            .map_err(|e| NozyError::Serialization(format!("Invalid block header at {}: {}", height, e)))?;
+        let tx_count = CompactSize::read(&mut reader)
+            .map_err(|e| NozyError::Serialization(format!("Invalid transaction count at {}: {}", height, e)))?;
+        
+        let mut transactions = Vec::with_capacity(tx_count as usize);
+        for _ in 0..tx_count {
+            let tx = Transaction::read(&mut reader, branch_id)
+                .map_err(|e| NozyError::Serialization(format!("Invalid transaction in block {}: {}", height, e)))?;
+            transactions.push(tx);
+        }
+        
+        Ok(transactions)
+    }
+    
+    // Serialized note commitment trees as of the end of `height`; None means the tree is empty
+    pub fn get_tree_state(&self, height: u32) -> NozyResult<RawTreeState> {
+        let result = self.call_rpc("z_gettreestate", serde_json::json!([height.to_string()]))?;
+        
+        let final_state = |pool: &str| -> NozyResult<Option<Vec<u8>>> {
+            match result.get(pool)
+                .and_then(|p| p.get("commitments"))
+                .and_then(|c| c.get("finalState"))
+                .and_then(|s| s.as_str())
+            {
+                Some(state) => hex::decode(state)
+                    .map(Some)
+                    .map_err(|e| NozyError::Serialization(format!("Invalid {} tree state at {}: {}", pool, height, e))),
+                None => Ok(None),
+            }
+        };
+        
+        Ok(RawTreeState {
+            height,
+            sapling: final_state("sapling")?,
+            orchard: final_state("orchard")?,
+        })
+    }
+    
+    pub fn get_raw_mempool(&self) -> NozyResult<Vec<String>> {
+        let result = self.call_rpc("getrawmempool", serde_json::json!([]))?;
+        
+        let txids = result.as_array()
+            .ok_or_else(|| NozyError::Network("getrawmempool result is not an array".to_string()))?;
+        
+        Ok(txids.iter()
+            .filter_map(|v| v.as_str())
+            .map(|s| s.to_string())
+            .collect())
+    }
+    
+    // `height` is the block the transaction is expected in, used to pick the consensus branch
+    pub fn get_raw_transaction(&self, txid: &str, height: u32) -> NozyResult<Transaction> {
+        let result = self.call_rpc("getrawtransaction", serde_json::json!([txid, 0]))?;
+        
+        let tx_hex = result.as_str()
+            .ok_or_else(|| NozyError::Network(format!("Transaction {} is not raw hex", txid)))?;
+        let tx_bytes = hex::decode(tx_hex)
+            .map_err(|e| NozyError::Serialization(format!("Invalid transaction hex for {}: {}", txid, e)))?;
+        
+        let branch_id = BranchId::for_height(&self.consensus_network(), BlockHeight::from_u32(height));
+        Transaction::read(&tx_bytes[..], branch_id)
+            .map_err(|e| NozyError::Serialization(format!("Invalid transaction {}: {}", txid, e)))
+    }
+    
     pub fn get_block_by_height(&self, height: u32) -> NozyResult<Option<String>> {
         if !self.connected {
             return Err(NozyError::Network("Not connected to Zebra".to_string()));
@@ -134,15 +298,25 @@ impl ZebraClient {
         Ok(Some(format!("tx_{}", txid)))
     }
     
+    // Sends the serialized transaction as-is; Zebra answers with the txid it computed
     pub fn broadcast_transaction(&self, transaction_data: &[u8]) -> NozyResult<String> {
-        if !self.connected {
-            return Err(NozyError::Network("Not connected to Zebra".to_string()));
+        if transaction_data.is_empty() {
+            return Err(NozyError::Transaction("Refusing to broadcast an empty transaction".to_string()));
         }
+        let result = self.call_rpc("sendrawtransaction", serde_json::json!([hex::encode(transaction_data)]))?;
         
-        Ok("broadcast_success".to_string())
+        result.as_str()
+            .map(|txid| txid.to_string())
+            .ok_or_else(|| NozyError::Network("sendrawtransaction result is not a txid".to_string()))
     }
     
+    // Hands the address to the indexer, so it is refused in scan-only mode; sync finds notes locally instead
     pub fn get_shielded_notes(&self, address: &str) -> NozyResult<Vec<crate::notes::ShieldedNote>> {
+        if self.config.scan_only {
+            return Err(NozyError::Privacy(
+                "getaddressnotes would reveal the address to the indexer; use 'nozy network sync' in scan-only mode".to_string(),
+            ));
+        }
         if !self.connected {
             return Err(NozyError::Network("Not connected to Zebra".to_string()));
         }
@@ -202,6 +376,14 @@ impl ZebraClient {
             .and_then(|v| v.as_str())
             .and_then(|s| hex::decode(s).ok());
             
+        let spent_at_height = note_data.get("spent_height")
+            .and_then(|v| v.as_u64())
+            .map(|h| h as u32);
+            
+        let spent_in_txid = note_data.get("spent_txid")
+            .and_then(|v| v.as_str())
+            .and_then(|s| hex::decode(s).ok());
+            
         let memo = note_data.get("memo")
             .and_then(|v| v.as_str())
             .map(|s| s.as_bytes().to_vec());
@@ -236,22 +418,24 @@ impl ZebraClient {
             recipient_address: "".to_string(),
             memo,
             randomness: vec![0u8; 32],
+            rho: None,
             created_at_height,
-            spent_at_height: None,
+            spent_at_height,
+            spent_in_txid,
             tx_hash,
             merkle_path,
             position,
+            is_change: false,
+            label: None,
+            tags: Vec::new(),
         };
 
         Ok(Some(note))
     }
     
-    pub fn estimate_fees(&self, transaction_size: usize) -> NozyResult<u64> {
-        if !self.connected {
-            return Err(NozyError::Network("Not connected to Zebra".to_string()));
-        }
-        
-        Ok(1000)
+    // ZIP-317 fees depend only on the transaction's shape, so no node round trip is needed
+    pub fn estimate_fees(&self, counts: &ActionCounts) -> u64 {
+        fees::conventional_fee(counts)
     }
     
     pub fn wait_for_confirmation(&self, txid: &str, confirmations: u32) -> NozyResult<bool> {
@@ -277,4 +461,40 @@ impl ZebraClient {
         
         Ok("Mempool status: normal".to_string())
     }
-} 
+}
+
+
+fn reveals_wallet_data(value: &serde_json::Value) -> bool {
+    match value {
+        serde_json::Value::String(text) => {
+            let text = text.trim();
+            KEY_PREFIXES.iter().any(|prefix| text.starts_with(prefix))
+                || [Network::MainNetwork, Network::TestNetwork].iter()
+                    .any(|network| zcash_keys::address::Address::decode(network, text).is_some())
+        }
+        serde_json::Value::Array(items) => items.iter().any(reveals_wallet_data),
+        serde_json::Value::Object(fields) => fields.values().any(reveals_wallet_data),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_scan_only_refuses_addresses_and_indexer() {
+        let client = ZebraClient::new(ZebraConfig::default());
+        let (_, _, keys) = crate::scanner::ViewingKeys::test_wallet();
+        let address = keys.sapling_change_address();
+
+        assert!(matches!(client.get_shielded_notes(&address), Err(NozyError::Privacy(_))));
+        assert!(matches!(client.call_rpc("z_listunspent", serde_json::json!([])), Err(NozyError::Privacy(_))));
+        assert!(matches!(client.call_rpc("getblock", serde_json::json!([address])), Err(NozyError::Privacy(_))));
+        assert!(matches!(client.call_rpc("getblock", serde_json::json!([keys.orchard_address_at(3).unwrap()])), Err(NozyError::Privacy(_))));
+        assert!(matches!(client.call_rpc("getblock", serde_json::json!([{ "key": "uview1abc" }])), Err(NozyError::Privacy(_))));
+
+        // Allowed queries get past the guard and only fail for lack of a connection
+        assert!(matches!(client.call_rpc("getblock", serde_json::json!(["100", 0])), Err(NozyError::Network(_))));
+    }
+}